@@ -0,0 +1,360 @@
+//! Queries on a template and its source text
+
+use handlebars::template::{
+    DecoratorTemplate, HelperTemplate, Parameter, SourceSpan, Template, TemplateElement,
+    TemplateVisitor, walk_element, walk_helper,
+};
+
+/// Position in a document as the protocol counts it: zero based line, and
+/// UTF-16 code units in the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+impl Position {
+    pub fn of_offset(text: &str, offset: usize) -> Position {
+        let offset = offset.min(text.len());
+        let line_start = text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Position {
+            line: text[..line_start].matches('\n').count() as u32,
+            character: utf16_len(&text[line_start..offset]),
+        }
+    }
+
+    /// Line and column as counted in `TemplateError::pos`, one based and in
+    /// chars
+    pub fn of_line_col(text: &str, line: usize, col: usize) -> Position {
+        let line_text = text.split('\n').nth(line.saturating_sub(1)).unwrap_or("");
+        let prefix: String = line_text.chars().take(col.saturating_sub(1)).collect();
+        Position {
+            line: line.saturating_sub(1) as u32,
+            character: utf16_len(&prefix),
+        }
+    }
+
+    /// Byte offset of the position, clamped to the end of its line
+    pub fn offset(&self, text: &str) -> usize {
+        let mut line_start = 0;
+        for _ in 0..self.line {
+            match text[line_start..].find('\n') {
+                Some(i) => line_start += i + 1,
+                None => return text.len(),
+            }
+        }
+        let line_end = text[line_start..]
+            .find('\n')
+            .map(|i| line_start + i)
+            .unwrap_or(text.len());
+
+        let mut units = 0;
+        for (i, c) in text[line_start..line_end].char_indices() {
+            if units >= self.character {
+                return line_start + i;
+            }
+            units += c.len_utf16() as u32;
+        }
+        line_end
+    }
+}
+
+fn utf16_len(s: &str) -> u32 {
+    s.chars().map(|c| c.len_utf16() as u32).sum()
+}
+
+/// A name the cursor is on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Symbol {
+    /// The name of a helper call, in a mustache, a block or a subexpression
+    Helper(String, SourceSpan),
+    /// The name of a partial, in `{{> name}}` or `{{#> name}}`
+    Partial(String, SourceSpan),
+}
+
+/// Find the helper or partial name at the byte offset
+pub fn symbol_at(t: &Template, offset: usize) -> Option<Symbol> {
+    let mut finder = SymbolFinder {
+        offset,
+        found: None,
+    };
+    finder.visit_template(t);
+    finder.found
+}
+
+struct SymbolFinder {
+    offset: usize,
+    found: Option<Symbol>,
+}
+
+impl SymbolFinder {
+    fn contains(&self, span: &SourceSpan) -> bool {
+        span.start <= self.offset && self.offset <= span.end && span.start < span.end
+    }
+}
+
+impl TemplateVisitor for SymbolFinder {
+    fn visit_element(&mut self, e: &TemplateElement) {
+        if let TemplateElement::PartialExpression(d) | TemplateElement::PartialBlock(d) = e {
+            if let Parameter::Name(ref name) = d.name {
+                if self.contains(&d.spans.name) {
+                    self.found = Some(Symbol::Partial(name.clone(), d.spans.name));
+                }
+            }
+        }
+        walk_element(self, e);
+    }
+
+    fn visit_helper(&mut self, h: &HelperTemplate) {
+        if let Some(name) = h.name.as_name() {
+            if self.contains(&h.spans.name) {
+                self.found = Some(Symbol::Helper(name.to_owned(), h.spans.name));
+            }
+        }
+        walk_helper(self, h);
+    }
+}
+
+/// Inline partials defined in the template with `{{#*inline "name"}}`, and
+/// the span of their open tag
+pub fn inline_partials(t: &Template) -> Vec<(String, SourceSpan)> {
+    #[derive(Default)]
+    struct Inlines(Vec<(String, SourceSpan)>);
+
+    impl TemplateVisitor for Inlines {
+        fn visit_decorator(&mut self, d: &DecoratorTemplate) {
+            if d.template.is_some() && d.name.as_name() == Some("inline") {
+                if let Some(Parameter::Literal(name)) = d.params.first() {
+                    if let Some(name) = name.as_str() {
+                        self.0.push((name.to_owned(), d.spans.open_tag));
+                    }
+                }
+            }
+            handlebars::template::walk_decorator(self, d);
+        }
+    }
+
+    let mut v = Inlines::default();
+    v.visit_template(t);
+    v.0
+}
+
+/// What a completion at the cursor should offer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompletionContext {
+    /// A helper name, in a mustache or a subexpression
+    Helper,
+    /// The name of a block helper, after `{{#`
+    BlockHelper,
+    /// A partial name, after `{{>` or `{{#>`
+    Partial,
+    /// The name closing the innermost open block, after `{{/`
+    Close(Option<String>),
+}
+
+/// Look at the tag the cursor is typed in, if any
+pub fn completion_context(text: &str, offset: usize) -> Option<CompletionContext> {
+    let before = &text[..offset];
+    let open = before.rfind("{{")?;
+    if before[open..].contains("}}") {
+        return None;
+    }
+    let tag = before[open..]
+        .trim_start_matches('{')
+        .trim_start_matches('~');
+
+    if let Some(rest) = tag.strip_prefix('/') {
+        return is_word(rest).then(|| CompletionContext::Close(open_blocks(&text[..open]).pop()));
+    }
+    if let Some(rest) = tag.strip_prefix("#>").or_else(|| tag.strip_prefix('>')) {
+        return is_word(rest.trim_start()).then_some(CompletionContext::Partial);
+    }
+    if tag.starts_with('!') || tag.starts_with("#*") || tag.starts_with('*') {
+        return None;
+    }
+    if let Some(rest) = tag.strip_prefix('#').or_else(|| tag.strip_prefix('^')) {
+        return is_word(rest).then_some(CompletionContext::BlockHelper);
+    }
+
+    let tag = tag.trim_start_matches('&');
+    let word_start = tag
+        .rfind(|c: char| c.is_whitespace() || c == '(')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    if word_start == 0 || tag[..word_start].ends_with('(') {
+        Some(CompletionContext::Helper)
+    } else {
+        None
+    }
+}
+
+fn is_word(s: &str) -> bool {
+    !s.contains(|c: char| c.is_whitespace() || c == '(' || c == '}')
+}
+
+/// Names of the blocks still open at the end of `text`, innermost last
+pub fn open_blocks(text: &str) -> Vec<String> {
+    let mut blocks: Vec<String> = Vec::new();
+    let mut pos = 0;
+
+    while let Some(i) = text[pos..].find("{{") {
+        let start = pos + i;
+        if text[..start].ends_with('\\') {
+            pos = start + 2;
+            continue;
+        }
+        let rest = &text[start..];
+
+        if let Some(raw) = rest.strip_prefix("{{{{") {
+            if raw.starts_with('/') {
+                pos = start + 4;
+                continue;
+            }
+            let name = first_token(raw.trim_start_matches('~').trim_start());
+            let close = format!("{{{{{{{{/{name}");
+            match rest.find(&close) {
+                Some(end) => pos = start + end + close.len(),
+                None => {
+                    blocks.push(name.to_owned());
+                    break;
+                }
+            }
+            continue;
+        }
+
+        let inner = rest[2..].trim_start_matches('~');
+        let end = if inner.starts_with("!--") {
+            ["--}}", "--~}}"]
+                .iter()
+                .filter_map(|close| rest[2..].find(close).map(|i| i + 2 + close.len()))
+                .min()
+        } else {
+            rest.find("}}").map(|i| i + 2)
+        };
+        let Some(end) = end else {
+            break;
+        };
+        pos = start + end;
+
+        if let Some(open) = inner.strip_prefix('#').or_else(|| inner.strip_prefix('^')) {
+            let open = open.trim_start_matches(['*', '>']).trim_start();
+            let name = first_token(open);
+            if !name.is_empty() {
+                blocks.push(name.to_owned());
+            }
+        } else if let Some(close) = inner.strip_prefix('/') {
+            let name = first_token(close.trim_start());
+            match blocks.iter().rposition(|b| b == name) {
+                Some(i) => blocks.truncate(i),
+                None if name.is_empty() => {
+                    blocks.pop();
+                }
+                None => {}
+            }
+        }
+    }
+    blocks
+}
+
+fn first_token(s: &str) -> &str {
+    let end = match s.chars().next() {
+        Some('[') => s.find(']').map(|i| i + 1),
+        Some('\'') => s[1..].find('\'').map(|i| i + 2),
+        _ => s.find(|c: char| c.is_whitespace() || matches!(c, '}' | '~' | '(' | ')')),
+    };
+    &s[..end.unwrap_or(s.len())]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_positions() {
+        let text = "a\n中😀{{b}}\n";
+        let p = Position::of_offset(text, text.find("{{").unwrap());
+        assert_eq!(
+            p,
+            Position {
+                line: 1,
+                character: 3
+            }
+        );
+        assert_eq!(p.offset(text), text.find("{{").unwrap());
+        assert_eq!(Position::of_line_col(text, 2, 3), p);
+        assert_eq!(
+            Position {
+                line: 0,
+                character: 10
+            }
+            .offset(text),
+            1
+        );
+        assert_eq!(
+            Position {
+                line: 5,
+                character: 0
+            }
+            .offset(text),
+            text.len()
+        );
+    }
+
+    #[test]
+    fn test_symbol_at() {
+        let text = "{{#if (eq a 1)}}{{> header}}{{/if}}";
+        let t = Template::compile(text).unwrap();
+
+        let Some(Symbol::Helper(name, span)) = symbol_at(&t, 4) else {
+            panic!();
+        };
+        assert_eq!((name.as_str(), span.range()), ("if", 3..5));
+        assert!(matches!(symbol_at(&t, 8), Some(Symbol::Helper(name, _)) if name == "eq"));
+        assert!(matches!(symbol_at(&t, 22), Some(Symbol::Partial(name, _)) if name == "header"));
+        assert_eq!(symbol_at(&t, 11), None);
+    }
+
+    #[test]
+    fn test_inline_partials() {
+        let t = Template::compile("{{#*inline \"row\"}}{{this}}{{/inline}}{{> row}}").unwrap();
+        let inlines = inline_partials(&t);
+        assert_eq!(inlines.len(), 1);
+        assert_eq!(inlines[0].0, "row");
+        assert_eq!(inlines[0].1.range(), 0..18);
+    }
+
+    #[test]
+    fn test_completion_context() {
+        let cases = [
+            ("{{", Some(CompletionContext::Helper)),
+            ("{{~up", Some(CompletionContext::Helper)),
+            ("{{{up", Some(CompletionContext::Helper)),
+            ("{{#e", Some(CompletionContext::BlockHelper)),
+            ("{{> he", Some(CompletionContext::Partial)),
+            ("{{#>", Some(CompletionContext::Partial)),
+            ("{{upper (lo", Some(CompletionContext::Helper)),
+            ("{{upper na", None),
+            ("{{a}} b", None),
+            ("{{! note", None),
+            (
+                "{{#each a}}{{#if b}}{{/if}}{{/",
+                Some(CompletionContext::Close(Some("each".to_owned()))),
+            ),
+            ("{{/", Some(CompletionContext::Close(None))),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(completion_context(text, text.len()), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn test_open_blocks() {
+        let text = "{{#each a as |x|}}{{!-- {{/each}} --}}{{#> layout}}{{{{raw}}}}{{/layout}}{{{{/raw}}}}\\{{#if}}{{^unless b}}{{~#*inline \"x\"}}";
+        assert_eq!(open_blocks(text), ["each", "layout", "unless", "inline"]);
+        assert_eq!(
+            open_blocks("{{#if a}}{{#with b}}{{/if}}"),
+            Vec::<String>::new()
+        );
+        assert_eq!(open_blocks("{{#> layout}}{{/}}"), Vec::<String>::new());
+    }
+}
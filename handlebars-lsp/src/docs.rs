@@ -0,0 +1,39 @@
+//! Hover documentation of the built-in helpers
+
+pub fn builtin_helper(name: &str) -> Option<&'static str> {
+    let doc = match name {
+        "if" => {
+            "`{{#if value}}…{{else}}…{{/if}}`\n\nRenders the block when `value` is truthy, the \
+             inverse block otherwise. Empty arrays and objects are falsy, and so is `0` unless \
+             `includeZero=true` is set."
+        }
+        "unless" => {
+            "`{{#unless value}}…{{else}}…{{/unless}}`\n\nRenders the block when `value` is \
+             falsy, the inverse of `if`."
+        }
+        "each" => {
+            "`{{#each items as |item key|}}…{{else}}…{{/each}}`\n\nRenders the block for every \
+             item of an array or every value of an object, with `@index`, `@key`, `@first` and \
+             `@last` set. The inverse block is rendered when there is nothing to iterate."
+        }
+        "with" => {
+            "`{{#with value as |v|}}…{{else}}…{{/with}}`\n\nRenders the block with `value` as \
+             the current context, the inverse block when `value` is falsy."
+        }
+        "lookup" => "`{{lookup object key}}`\n\nReads `key` of an object or an array.",
+        "raw" => "`{{{{raw}}}}…{{{{/raw}}}}`\n\nRenders the block content as is.",
+        "log" => "`{{log value level=\"info\"}}`\n\nLogs the values through the `log` crate.",
+        "eq" => "`(eq a b)`\n\nWhether `a` equals `b`.",
+        "ne" => "`(ne a b)`\n\nWhether `a` differs from `b`.",
+        "gt" => "`(gt a b)`\n\nWhether `a` is greater than `b`.",
+        "gte" => "`(gte a b)`\n\nWhether `a` is greater than or equal to `b`.",
+        "lt" => "`(lt a b)`\n\nWhether `a` is less than `b`.",
+        "lte" => "`(lte a b)`\n\nWhether `a` is less than or equal to `b`.",
+        "and" => "`(and a b …)`\n\nWhether all of the values are truthy.",
+        "or" => "`(or a b …)`\n\nWhether any of the values is truthy.",
+        "not" => "`(not a)`\n\nWhether `a` is falsy.",
+        "len" => "`(len value)`\n\nLength of an array, an object or a string.",
+        _ => return None,
+    };
+    Some(doc)
+}
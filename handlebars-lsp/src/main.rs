@@ -0,0 +1,58 @@
+//! Language server for handlebars templates
+//!
+//! `handlebars-lsp` speaks the language server protocol over stdin and
+//! stdout, and uses the parser of handlebars-rust to offer
+//!
+//! * diagnostics for every syntax error of the open documents,
+//! * go to definition of `{{> partial}}` names, resolved to inline partials of
+//!   the document, or to the files of the templates directory named like
+//!   `Handlebars::register_templates_directory` names them,
+//! * completion of helper names, partial names and block close tags,
+//! * hover documentation of helpers.
+//!
+//! The client configures the server with `initializationOptions`:
+//!
+//! ```json
+//! {
+//!   "templatesDirectory": "templates",
+//!   "extension": ".hbs",
+//!   "helpers": { "upper": "Uppercase the parameter" }
+//! }
+//! ```
+//!
+//! `templatesDirectory` is relative to the workspace root, which is the
+//! default. `helpers` describes the helpers your application registers, in
+//! addition to the built-in ones.
+
+mod analysis;
+mod docs;
+mod rpc;
+mod server;
+
+use std::io::{self, BufReader};
+use std::process;
+
+use serde_json::Value;
+
+use crate::server::Server;
+
+fn main() -> io::Result<()> {
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut server = Server::new(io::stdout().lock());
+
+    while let Some(message) = rpc::read_message(&mut input)? {
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                let response = rpc::error_response(Value::Null, rpc::PARSE_ERROR, &e);
+                rpc::write_message(&mut io::stdout().lock(), &response)?;
+                continue;
+            }
+        };
+        if let Some(code) = server.handle(message)? {
+            process::exit(code);
+        }
+    }
+    // the client went away without asking the server to exit
+    process::exit(1);
+}
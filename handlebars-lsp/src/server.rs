@@ -0,0 +1,404 @@
+//! Requests and notifications of the language server
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use handlebars::template::SourceSpan;
+use handlebars::{DirectorySourceOptions, DirectorySourceOptionsBuilder, Handlebars, Template};
+use serde_json::{Value, json};
+use walkdir::WalkDir;
+
+use crate::analysis::{self, CompletionContext, Position, Symbol};
+use crate::docs;
+use crate::rpc;
+
+const COMPLETION_KIND_FUNCTION: u32 = 3;
+const COMPLETION_KIND_FILE: u32 = 17;
+const COMPLETION_KIND_KEYWORD: u32 = 14;
+const SEVERITY_ERROR: u32 = 1;
+
+pub struct Server<W: Write> {
+    out: W,
+    documents: HashMap<String, String>,
+    templates_dir: Option<PathBuf>,
+    source_options: DirectorySourceOptions,
+    /// Helpers offered in completions, with the description given by the
+    /// client for custom ones
+    helpers: BTreeMap<String, Option<String>>,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(out: W) -> Server<W> {
+        let hbs = Handlebars::new();
+        Server {
+            out,
+            documents: HashMap::new(),
+            templates_dir: None,
+            source_options: DirectorySourceOptions::default(),
+            helpers: hbs
+                .get_helper_names()
+                .into_iter()
+                .map(|name| (name.to_owned(), None))
+                .collect(),
+            shutdown: false,
+        }
+    }
+
+    /// Handle a message from the client, returns the exit code of the server
+    /// once it is asked to exit
+    pub fn handle(&mut self, message: Value) -> io::Result<Option<i32>> {
+        let method = message.get("method").and_then(Value::as_str);
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let Some(id) = message.get("id").cloned() else {
+            match method {
+                Some("exit") => return Ok(Some(if self.shutdown { 0 } else { 1 })),
+                Some(method) => self.notification(method, params)?,
+                None => {}
+            }
+            return Ok(None);
+        };
+        let Some(method) = method else {
+            // a response to a request we never send
+            return Ok(None);
+        };
+
+        let response = if self.shutdown {
+            rpc::error_response(id, rpc::INVALID_REQUEST, "the server is shut down")
+        } else {
+            match self.request(method, &params) {
+                Ok(result) => rpc::response(id, result),
+                Err((code, message)) => rpc::error_response(id, code, &message),
+            }
+        };
+        rpc::write_message(&mut self.out, &response)?;
+        Ok(None)
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => Ok(self.initialize(params)),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => {
+                let (uri, offset) = self.document_position(params)?;
+                Ok(self.definition(&uri, offset))
+            }
+            "textDocument/completion" => {
+                let (uri, offset) = self.document_position(params)?;
+                Ok(self.completion(&uri, offset))
+            }
+            "textDocument/hover" => {
+                let (uri, offset) = self.document_position(params)?;
+                Ok(self.hover(&uri, offset))
+            }
+            _ => Err((
+                rpc::METHOD_NOT_FOUND,
+                format!("unsupported method {method}"),
+            )),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: Value) -> io::Result<()> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .map(ToOwned::to_owned);
+        match (method, uri) {
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_owned());
+                self.publish_diagnostics(&uri)
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // full document sync, the last change holds the whole text
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    self.documents.insert(uri.clone(), text.to_owned());
+                }
+                self.publish_diagnostics(&uri)
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(&uri);
+                rpc::write_message(
+                    &mut self.out,
+                    &rpc::notification(
+                        "textDocument/publishDiagnostics",
+                        json!({ "uri": uri, "diagnostics": [] }),
+                    ),
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn initialize(&mut self, params: &Value) -> Value {
+        let root = params["rootUri"]
+            .as_str()
+            .and_then(uri_to_path)
+            .or_else(|| params["rootPath"].as_str().map(PathBuf::from));
+
+        let options = &params["initializationOptions"];
+        self.templates_dir = match options["templatesDirectory"].as_str() {
+            Some(dir) => Some(match root {
+                Some(ref root) => root.join(dir),
+                None => PathBuf::from(dir),
+            }),
+            None => root,
+        };
+        if let Some(extension) = options["extension"].as_str() {
+            self.source_options = DirectorySourceOptionsBuilder::default()
+                .tpl_extension(extension)
+                .build()
+                .unwrap();
+        }
+        if let Some(helpers) = options["helpers"].as_object() {
+            for (name, description) in helpers {
+                self.helpers
+                    .insert(name.clone(), description.as_str().map(ToOwned::to_owned));
+            }
+        }
+
+        json!({
+            "capabilities": {
+                "textDocumentSync": { "openClose": true, "change": 1 },
+                "definitionProvider": true,
+                "hoverProvider": true,
+                "completionProvider": { "triggerCharacters": ["{", "#", "/", ">", "("] },
+            },
+            "serverInfo": { "name": "handlebars-lsp", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        let text = &self.documents[uri];
+        let (_, errors) = Template::compile_recovering(text);
+        let diagnostics: Vec<_> = errors
+            .iter()
+            .map(|e| {
+                let range = match (e.span(), e.pos()) {
+                    (Some(span), _) => range(text, span.start, span.end),
+                    (None, Some((line, col))) => {
+                        let p = Position::of_line_col(text, line, col);
+                        json!({ "start": position(p), "end": position(p) })
+                    }
+                    (None, None) => range(text, 0, 0),
+                };
+                json!({
+                    "range": range,
+                    "severity": SEVERITY_ERROR,
+                    "source": "handlebars",
+                    "message": e.reason().to_string(),
+                })
+            })
+            .collect();
+
+        rpc::write_message(
+            &mut self.out,
+            &rpc::notification(
+                "textDocument/publishDiagnostics",
+                json!({ "uri": uri, "diagnostics": diagnostics }),
+            ),
+        )
+    }
+
+    fn document_position(&self, params: &Value) -> Result<(String, usize), (i64, String)> {
+        let invalid = |message: &str| (rpc::INVALID_PARAMS, message.to_owned());
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(|| invalid("missing textDocument.uri"))?;
+        let text = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| invalid("the document is not open"))?;
+        let (Some(line), Some(character)) = (
+            params["position"]["line"].as_u64(),
+            params["position"]["character"].as_u64(),
+        ) else {
+            return Err(invalid("missing position"));
+        };
+        let p = Position {
+            line: line as u32,
+            character: character as u32,
+        };
+        Ok((uri.to_owned(), p.offset(text)))
+    }
+
+    fn definition(&self, uri: &str, offset: usize) -> Value {
+        let text = &self.documents[uri];
+        let (t, _) = Template::compile_recovering(text);
+        let Some(Symbol::Partial(name, _)) = analysis::symbol_at(&t, offset) else {
+            return Value::Null;
+        };
+
+        if let Some((_, span)) = analysis::inline_partials(&t)
+            .into_iter()
+            .find(|(n, _)| *n == name)
+        {
+            return json!({ "uri": uri, "range": range(text, span.start, span.end) });
+        }
+        match self.partial_files().into_iter().find(|(n, _)| *n == name) {
+            Some((_, path)) => {
+                json!({ "uri": path_to_uri(&path), "range": range("", 0, 0) })
+            }
+            None => Value::Null,
+        }
+    }
+
+    fn completion(&self, uri: &str, offset: usize) -> Value {
+        let text = &self.documents[uri];
+        let items: Vec<_> = match analysis::completion_context(text, offset) {
+            Some(CompletionContext::Helper) | Some(CompletionContext::BlockHelper) => self
+                .helpers
+                .iter()
+                .map(|(name, description)| {
+                    let doc = description
+                        .as_deref()
+                        .or_else(|| docs::builtin_helper(name));
+                    json!({
+                        "label": name,
+                        "kind": COMPLETION_KIND_FUNCTION,
+                        "detail": "helper",
+                        "documentation": doc.map(|doc| json!({ "kind": "markdown", "value": doc })),
+                    })
+                })
+                .collect(),
+            Some(CompletionContext::Partial) => {
+                let (t, _) = Template::compile_recovering(text);
+                let mut names: Vec<_> = analysis::inline_partials(&t)
+                    .into_iter()
+                    .map(|(name, _)| (name, "inline partial"))
+                    .collect();
+                names.extend(
+                    self.partial_files()
+                        .into_iter()
+                        .map(|(name, _)| (name, "template")),
+                );
+                names
+                    .into_iter()
+                    .map(|(name, detail)| {
+                        json!({ "label": name, "kind": COMPLETION_KIND_FILE, "detail": detail })
+                    })
+                    .collect()
+            }
+            Some(CompletionContext::Close(Some(block))) => {
+                let insert = if text[offset..].trim_start().starts_with("}}") {
+                    block.clone()
+                } else {
+                    format!("{block}}}}}")
+                };
+                vec![json!({
+                    "label": block,
+                    "kind": COMPLETION_KIND_KEYWORD,
+                    "detail": format!("close {{{{#{block}}}}}"),
+                    "insertText": insert,
+                })]
+            }
+            Some(CompletionContext::Close(None)) | None => Vec::new(),
+        };
+        json!(items)
+    }
+
+    fn hover(&self, uri: &str, offset: usize) -> Value {
+        let text = &self.documents[uri];
+        let (t, _) = Template::compile_recovering(text);
+        let Some(Symbol::Helper(name, span)) = analysis::symbol_at(&t, offset) else {
+            return Value::Null;
+        };
+        let doc = match self.helpers.get(&name) {
+            Some(Some(description)) => description.as_str(),
+            Some(None) => docs::builtin_helper(&name).unwrap_or("Registered helper"),
+            None => return Value::Null,
+        };
+        json!({
+            "contents": { "kind": "markdown", "value": format!("**{name}**\n\n{doc}") },
+            "range": span_range(text, &span),
+        })
+    }
+
+    /// Template names in the templates directory, named like
+    /// `Handlebars::register_templates_directory` would register them
+    fn partial_files(&self) -> Vec<(String, PathBuf)> {
+        let Some(ref dir) = self.templates_dir else {
+            return Vec::new();
+        };
+        let mut files: Vec<_> = WalkDir::new(dir)
+            .min_depth(1)
+            .into_iter()
+            .filter_map(|e| e.ok().map(|e| e.into_path()))
+            .filter_map(|path| {
+                self.source_options
+                    .template_name(dir, &path)
+                    .map(|name| (name, path))
+            })
+            .collect();
+        files.sort();
+        files
+    }
+}
+
+fn position(p: Position) -> Value {
+    json!({ "line": p.line, "character": p.character })
+}
+
+fn range(text: &str, start: usize, end: usize) -> Value {
+    json!({
+        "start": position(Position::of_offset(text, start)),
+        "end": position(Position::of_offset(text, end)),
+    })
+}
+
+fn span_range(text: &str, span: &SourceSpan) -> Value {
+    range(text, span.start, span.end)
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], path.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) if u8::from_str_radix(hex, 16).is_ok() => {
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_uri() {
+        let path = uri_to_path("file:///tmp/my%20templates/a.hbs").unwrap();
+        assert_eq!(path, PathBuf::from("/tmp/my templates/a.hbs"));
+        assert_eq!(path_to_uri(&path), "file:///tmp/my%20templates/a.hbs");
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    }
+}
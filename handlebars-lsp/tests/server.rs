@@ -0,0 +1,245 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use serde_json::{Value, json};
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_handlebars-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Client {
+            child,
+            stdin,
+            stdout,
+            next_id: 1,
+        }
+    }
+
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                len = value.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; len];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        let response = self.receive();
+        assert_eq!(response["id"], id);
+        response
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn open(&mut self, uri: &str, text: &str) -> Value {
+        self.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": uri, "languageId": "handlebars", "version": 1, "text": text } }),
+        );
+        let diagnostics = self.receive();
+        assert_eq!(diagnostics["method"], "textDocument/publishDiagnostics");
+        diagnostics["params"]["diagnostics"].clone()
+    }
+
+    fn at(&mut self, method: &str, uri: &str, line: u32, character: u32) -> Value {
+        self.request(
+            method,
+            json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } }),
+        )["result"]
+            .clone()
+    }
+
+    fn stop(mut self) {
+        let response = self.request("shutdown", Value::Null);
+        assert_eq!(response["result"], Value::Null);
+        self.notify("exit", Value::Null);
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+fn templates_dir() -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/templates")
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn initialize(client: &mut Client) {
+    let response = client.request(
+        "initialize",
+        json!({
+            "processId": null,
+            "rootUri": format!("file://{}", templates_dir()),
+            "capabilities": {},
+            "initializationOptions": { "helpers": { "upper": "Uppercase the parameter" } },
+        }),
+    );
+    let capabilities = &response["result"]["capabilities"];
+    assert_eq!(capabilities["hoverProvider"], true);
+    assert_eq!(capabilities["definitionProvider"], true);
+    client.notify("initialized", json!({}));
+}
+
+#[test]
+fn test_diagnostics() {
+    let mut client = Client::start();
+    initialize(&mut client);
+
+    let uri = "file:///tmp/broken.hbs";
+    let diagnostics = client.open(uri, "{{#if a}}\n中{{/each}}\n{{foo bar=}}");
+    let ranges: Vec<_> = diagnostics
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| {
+            let r = &d["range"];
+            (
+                r["start"]["line"].as_u64().unwrap(),
+                r["start"]["character"].as_u64().unwrap(),
+                r["end"]["line"].as_u64().unwrap(),
+                r["end"]["character"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(ranges, [(1, 1, 1, 10), (2, 0, 2, 12)]);
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[0]["source"], "handlebars");
+
+    client.notify(
+        "textDocument/didChange",
+        json!({ "textDocument": { "uri": uri, "version": 2 }, "contentChanges": [{ "text": "{{#if a}}{{/if}}" }] }),
+    );
+    let fixed = client.receive();
+    assert_eq!(fixed["params"]["diagnostics"], json!([]));
+
+    client.stop();
+}
+
+#[test]
+fn test_definition() {
+    let mut client = Client::start();
+    initialize(&mut client);
+
+    let uri = format!("file://{}/page.hbs", templates_dir());
+    let text =
+        "{{> partials/header}}\n{{#*inline \"row\"}}{{this}}{{/inline}}{{> row}}{{> missing}}";
+    assert_eq!(client.open(&uri, text), json!([]));
+
+    let header = client.at("textDocument/definition", &uri, 0, 8);
+    assert_eq!(
+        header["uri"],
+        format!("file://{}/partials/header.hbs", templates_dir())
+    );
+    assert_eq!(
+        header["range"]["start"],
+        json!({ "line": 0, "character": 0 })
+    );
+
+    let row = client.at("textDocument/definition", &uri, 1, 41);
+    assert_eq!(row["uri"], uri);
+    assert_eq!(row["range"]["start"], json!({ "line": 1, "character": 0 }));
+    assert_eq!(row["range"]["end"], json!({ "line": 1, "character": 18 }));
+
+    assert_eq!(
+        client.at("textDocument/definition", &uri, 1, 52),
+        Value::Null
+    );
+    client.stop();
+}
+
+#[test]
+fn test_completion() {
+    let mut client = Client::start();
+    initialize(&mut client);
+
+    let uri = "file:///tmp/page.hbs";
+    client.open(uri, "{{#each items}}{{#if a}}{{/if}}{{/\n{{#e\n{{> ");
+
+    let labels = |items: Value| -> Vec<String> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["label"].as_str().unwrap().to_owned())
+            .collect()
+    };
+
+    let close = client.at("textDocument/completion", uri, 0, 34);
+    assert_eq!(labels(close.clone()), ["each"]);
+    assert_eq!(close[0]["insertText"], "each}}");
+
+    let helpers = labels(client.at("textDocument/completion", uri, 1, 4));
+    assert!(helpers.contains(&"each".to_owned()));
+    assert!(helpers.contains(&"upper".to_owned()));
+
+    let partials = labels(client.at("textDocument/completion", uri, 2, 4));
+    assert_eq!(partials, ["page", "partials/header"]);
+
+    client.stop();
+}
+
+#[test]
+fn test_hover() {
+    let mut client = Client::start();
+    initialize(&mut client);
+
+    let uri = "file:///tmp/page.hbs";
+    client.open(uri, "{{#if (upper a)}}{{title}}{{/if}}");
+
+    let hover = client.at("textDocument/hover", uri, 0, 4);
+    assert!(
+        hover["contents"]["value"]
+            .as_str()
+            .unwrap()
+            .starts_with("**if**")
+    );
+    assert_eq!(hover["range"]["start"]["character"], 3);
+    assert_eq!(hover["range"]["end"]["character"], 5);
+
+    let hover = client.at("textDocument/hover", uri, 0, 9);
+    assert_eq!(
+        hover["contents"]["value"],
+        "**upper**\n\nUppercase the parameter"
+    );
+
+    assert_eq!(client.at("textDocument/hover", uri, 0, 20), Value::Null);
+
+    let response = client.request("textDocument/formatting", json!({}));
+    assert_eq!(response["error"]["code"], -32601);
+
+    client.stop();
+}
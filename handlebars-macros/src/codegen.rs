@@ -0,0 +1,301 @@
+//! Turn a compiled `Template` into the Rust expression that builds it
+//!
+//! Constructs added to the template types after this crate was written are
+//! reported as an error message, turned into a compile error by the macro.
+
+use handlebars::template::{
+    BlockParam, DecoratorTemplate, ExpressionSpans, HelperTemplate, Parameter, SourceSpan,
+    Subexpression, TemplateElement,
+};
+use handlebars::{JsonValue as Json, Path, PathSeg, Template, macro_support};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+pub(crate) type Result<T> = std::result::Result<T, String>;
+
+pub(crate) fn template(t: &Template) -> Result<TokenStream> {
+    let name = option(t.name.as_ref().map(|n| quote!(#n)));
+    let elements = t.elements.iter().map(element).collect::<Result<Vec<_>>>()?;
+    let mapping = t.mapping.iter().map(|m| {
+        let (line, col) = (m.0, m.1);
+        quote!((#line, #col))
+    });
+    let spans = t.spans.iter().map(span);
+    Ok(quote! {
+        ::handlebars::macro_support::template(
+            #name,
+            vec![#(#elements),*],
+            &[#(#mapping),*],
+            &[#(#spans),*],
+        )
+    })
+}
+
+fn element(e: &TemplateElement) -> Result<TokenStream> {
+    let variant = quote!(::handlebars::template::TemplateElement);
+    Ok(match e {
+        TemplateElement::RawString(s) => quote!(#variant::RawString(#s.to_owned())),
+        TemplateElement::Comment(s) => quote!(#variant::Comment(#s.to_owned())),
+        TemplateElement::Expression(h) => {
+            let h = helper(h)?;
+            quote!(#variant::Expression(#h))
+        }
+        TemplateElement::HtmlExpression(h) => {
+            let h = helper(h)?;
+            quote!(#variant::HtmlExpression(#h))
+        }
+        TemplateElement::HelperBlock(h) => {
+            let h = helper(h)?;
+            quote!(#variant::HelperBlock(#h))
+        }
+        TemplateElement::DecoratorExpression(d) => {
+            let d = decorator(d)?;
+            quote!(#variant::DecoratorExpression(#d))
+        }
+        TemplateElement::DecoratorBlock(d) => {
+            let d = decorator(d)?;
+            quote!(#variant::DecoratorBlock(#d))
+        }
+        TemplateElement::PartialExpression(d) => {
+            let d = decorator(d)?;
+            quote!(#variant::PartialExpression(#d))
+        }
+        TemplateElement::PartialBlock(d) => {
+            let d = decorator(d)?;
+            quote!(#variant::PartialBlock(#d))
+        }
+        other => return Err(format!("unsupported template element {other:?}")),
+    })
+}
+
+// expands to a `Box<HelperTemplate>`
+fn helper(h: &HelperTemplate) -> Result<TokenStream> {
+    let name = parameter(&h.name)?;
+    let params = h.params.iter().map(parameter).collect::<Result<Vec<_>>>()?;
+    let hash = hash(h.hash.iter())?;
+    let block_param = option(h.block_param.as_ref().map(block_param).transpose()?);
+    let block = h.block;
+    let indent_before_write = macro_support::helper_indent_before_write(h);
+    let block_template = h.template.as_ref().map(template).transpose()?;
+    let block_template = block_template.map(|t| quote!(h.template = Some(#t);));
+    let inverse = h.inverse.as_ref().map(template).transpose()?;
+    let inverse = inverse.map(|t| quote!(h.inverse = Some(#t);));
+    let chain = h.chain.then(|| quote!(h.chain = true;));
+    let spans = spans(&h.spans);
+
+    Ok(quote! {{
+        #[allow(unused_mut)]
+        let mut h = ::handlebars::macro_support::helper(
+            #name,
+            vec![#(#params),*],
+            #hash,
+            #block_param,
+            #block,
+            #spans,
+            #indent_before_write,
+        );
+        #block_template
+        #inverse
+        #chain
+        Box::new(h)
+    }})
+}
+
+// expands to a `Box<DecoratorTemplate>`
+fn decorator(d: &DecoratorTemplate) -> Result<TokenStream> {
+    let name = parameter(&d.name)?;
+    let params = d.params.iter().map(parameter).collect::<Result<Vec<_>>>()?;
+    let hash = hash(d.hash.iter())?;
+    let indent_before_write = macro_support::decorator_indent_before_write(d);
+    let block_template = d.template.as_ref().map(template).transpose()?;
+    let block_template = block_template.map(|t| quote!(d.template = Some(#t);));
+    let indent = d
+        .indent
+        .as_ref()
+        .map(|i| quote!(d.indent = Some(#i.to_owned());));
+    let spans = spans(&d.spans);
+
+    Ok(quote! {{
+        #[allow(unused_mut)]
+        let mut d = ::handlebars::macro_support::decorator(
+            #name,
+            vec![#(#params),*],
+            #hash,
+            #spans,
+            #indent_before_write,
+        );
+        #block_template
+        #indent
+        Box::new(d)
+    }})
+}
+
+fn span(s: &SourceSpan) -> TokenStream {
+    let (start, end) = (s.start, s.end);
+    let ((start_line, start_col), (end_line, end_col)) = (s.start_pos, s.end_pos);
+    quote! {
+        ::handlebars::template::SourceSpan::new(
+            #start,
+            #end,
+            (#start_line, #start_col),
+            (#end_line, #end_col),
+        )
+    }
+}
+
+fn spans(s: &ExpressionSpans) -> TokenStream {
+    let (element, open_tag, name) = (span(&s.element), span(&s.open_tag), span(&s.name));
+    let params = s.params.iter().map(span);
+    let mut hash: Vec<_> = s.hash.iter().collect();
+    hash.sort_by_key(|(k, _)| *k);
+    let hash = hash.into_iter().map(|(k, v)| {
+        let v = span(v);
+        quote!((#k, #v))
+    });
+    let block_param = option(s.block_param.as_ref().map(span));
+    let inverse_tag = option(s.inverse_tag.as_ref().map(span));
+    let close_tag = option(s.close_tag.as_ref().map(span));
+    quote! {
+        ::handlebars::macro_support::spans(
+            #element,
+            #open_tag,
+            #name,
+            vec![#(#params),*],
+            ::handlebars::macro_support::hash(vec![#(#hash),*]),
+            #block_param,
+            #inverse_tag,
+            #close_tag,
+        )
+    }
+}
+
+fn hash<'a>(hash: impl Iterator<Item = (&'a String, &'a Parameter)>) -> Result<TokenStream> {
+    let mut entries: Vec<_> = hash.collect();
+    entries.sort_by_key(|(k, _)| *k);
+    let entries = entries
+        .into_iter()
+        .map(|(k, v)| {
+            let v = parameter(v)?;
+            Ok(quote!((#k, #v)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(quote!(::handlebars::macro_support::hash(
+        vec![#(#entries),*]
+    )))
+}
+
+fn block_param(bp: &BlockParam) -> Result<TokenStream> {
+    let variant = quote!(::handlebars::template::BlockParam);
+    Ok(match bp {
+        BlockParam::Single(p) => {
+            let p = parameter(p)?;
+            quote!(#variant::Single(#p))
+        }
+        BlockParam::Pair((p1, p2)) => {
+            let (p1, p2) = (parameter(p1)?, parameter(p2)?);
+            quote!(#variant::Pair((#p1, #p2)))
+        }
+        other => return Err(format!("unsupported block param {other:?}")),
+    })
+}
+
+fn parameter(p: &Parameter) -> Result<TokenStream> {
+    let variant = quote!(::handlebars::template::Parameter);
+    Ok(match p {
+        Parameter::Name(n) => quote!(#variant::Name(#n.to_owned())),
+        Parameter::Path(path) => {
+            let path = self::path(path)?;
+            quote!(#variant::Path(#path))
+        }
+        Parameter::Literal(j) => {
+            let j = json(j);
+            quote!(#variant::Literal(#j))
+        }
+        Parameter::Subexpression(s) => {
+            let s = subexpression(s)?;
+            quote!(#variant::Subexpression(#s))
+        }
+        other => return Err(format!("unsupported parameter {other:?}")),
+    })
+}
+
+fn subexpression(s: &Subexpression) -> Result<TokenStream> {
+    Ok(match s.as_element() {
+        TemplateElement::Expression(h) => {
+            let name = parameter(&h.name)?;
+            let params = h.params.iter().map(parameter).collect::<Result<Vec<_>>>()?;
+            let hash = hash(h.hash.iter())?;
+            let spans = spans(&h.spans);
+            quote! {
+                ::handlebars::macro_support::subexpression(
+                    #name,
+                    vec![#(#params),*],
+                    #hash,
+                    #spans,
+                )
+            }
+        }
+        other => return Err(format!("unsupported subexpression {other:?}")),
+    })
+}
+
+fn path(p: &Path) -> Result<TokenStream> {
+    Ok(match p {
+        Path::Relative((segs, raw)) => {
+            let segs = segs
+                .iter()
+                .map(|seg| match seg {
+                    PathSeg::Named(n) => Ok(quote!(::handlebars::PathSeg::Named(#n.to_owned()))),
+                    seg => {
+                        let rule = macro_support::path_seg_rule(seg)
+                            .ok_or_else(|| format!("unsupported path segment {seg:?}"))?;
+                        Ok(quote!(::handlebars::macro_support::path_seg_ruled(#rule)))
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            quote!(::handlebars::Path::Relative((vec![#(#segs),*], #raw.to_owned())))
+        }
+        Path::Local((level, name, raw)) => {
+            quote!(::handlebars::Path::Local((#level, #name.to_owned(), #raw.to_owned())))
+        }
+    })
+}
+
+fn json(j: &Json) -> TokenStream {
+    let value = quote!(::handlebars::JsonValue);
+    match j {
+        Json::Null => quote!(#value::Null),
+        Json::Bool(b) => quote!(#value::Bool(#b)),
+        Json::Number(n) => {
+            if let Some(n) = n.as_u64() {
+                quote!(#value::from(#n))
+            } else if let Some(n) = n.as_i64() {
+                quote!(#value::from(#n))
+            } else {
+                let n = n.as_f64().unwrap_or_default();
+                quote!(#value::from(#n))
+            }
+        }
+        Json::String(s) => quote!(#value::String(#s.to_owned())),
+        Json::Array(items) => {
+            let items = items.iter().map(json);
+            quote!(#value::Array(vec![#(#items),*]))
+        }
+        Json::Object(entries) => {
+            let entries = entries.iter().map(|(k, v)| {
+                let v = json(v);
+                quote!((#k, #v))
+            });
+            quote!(::handlebars::macro_support::json_object(
+                vec![#(#entries),*]
+            ))
+        }
+    }
+}
+
+fn option(t: Option<TokenStream>) -> TokenStream {
+    match t {
+        Some(t) => quote!(Some(#t)),
+        None => quote!(None),
+    }
+}
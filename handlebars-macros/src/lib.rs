@@ -0,0 +1,133 @@
+//! Compile-time templates for [handlebars-rust](https://docs.rs/handlebars)
+//!
+//! The macros of this crate parse a handlebars template while your crate is
+//! being compiled, and expand to an expression building the
+//! `handlebars::Template`. Syntax errors are reported by `cargo build`, with
+//! the line and column in the template, and there is no parsing left to do
+//! at runtime.
+//!
+//! ```
+//! use std::collections::BTreeMap;
+//!
+//! use handlebars::Handlebars;
+//! use handlebars_macros::template;
+//!
+//! let mut hbs = Handlebars::new();
+//! hbs.register_template("hello", template!("Hello {{name}}!"));
+//!
+//! let data = BTreeMap::from([("name", "world")]);
+//! assert_eq!(hbs.render("hello", &data).unwrap(), "Hello world!");
+//! ```
+//!
+//! Templates are compiled with default options. They don't follow
+//! `Handlebars::set_prevent_indent`, which only applies to templates
+//! registered from source.
+
+use std::fs;
+use std::path::PathBuf;
+
+use handlebars::{Template, TemplateError};
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{LitStr, parse_macro_input};
+
+mod codegen;
+
+/// Compile a template given as a string literal
+///
+/// ```
+/// let t: handlebars::Template = handlebars_macros::template!("{{#each items}}{{this}}{{/each}}");
+/// ```
+///
+/// An invalid template fails to compile:
+///
+/// ```compile_fail
+/// let t = handlebars_macros::template!("{{#each items}}");
+/// ```
+#[proc_macro]
+pub fn template(input: TokenStream) -> TokenStream {
+    let source = parse_macro_input!(input as LitStr);
+    expand_template(&source)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Compile a template file
+///
+/// The path is relative to the directory of the `Cargo.toml` of the crate
+/// being compiled, like the `folder` of `rust-embed`, and is used as the
+/// name of the template. The crate is rebuilt when the file changes.
+///
+/// ```ignore
+/// let mut hbs = handlebars::Handlebars::new();
+/// hbs.register_template("index", include_template!("templates/index.hbs"));
+/// ```
+#[proc_macro]
+pub fn include_template(input: TokenStream) -> TokenStream {
+    let path = parse_macro_input!(input as LitStr);
+    expand_include_template(&path)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_template(source: &LitStr) -> syn::Result<TokenStream2> {
+    let t = Template::compile(&source.value()).map_err(|e| template_error(source, &e))?;
+    codegen::template(&t).map_err(|e| syn::Error::new(source.span(), e))
+}
+
+fn expand_include_template(path: &LitStr) -> syn::Result<TokenStream2> {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| syn::Error::new(path.span(), "CARGO_MANIFEST_DIR is not set"))?;
+    let file = PathBuf::from(manifest_dir).join(path.value());
+    let source = fs::read_to_string(&file).map_err(|e| {
+        syn::Error::new(
+            path.span(),
+            format!("failed to read template {}: {e}", file.display()),
+        )
+    })?;
+
+    let t =
+        Template::compile_with_name(source, path.value()).map_err(|e| template_error(path, &e))?;
+    let t = codegen::template(&t).map_err(|e| syn::Error::new(path.span(), e))?;
+    let file = file.to_string_lossy();
+    Ok(quote! {{
+        // track the template file, so the crate is rebuilt on change
+        const _: &str = ::core::include_str!(#file);
+        #t
+    }})
+}
+
+fn template_error(lit: &LitStr, e: &TemplateError) -> syn::Error {
+    syn::Error::new(lit.span(), e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proc_macro2::Span;
+
+    #[test]
+    fn test_syntax_error() {
+        let e = expand_template(&LitStr::new("hello\n{{#if a}}", Span::call_site())).unwrap_err();
+        assert!(e.to_string().contains(":2:"), "{e}");
+
+        let e = expand_include_template(&LitStr::new(
+            "tests/templates/invalid.hbs",
+            Span::call_site(),
+        ))
+        .unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("Template error in \"tests/templates/invalid.hbs\":4:8"),
+            "{e}"
+        );
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let e =
+            expand_include_template(&LitStr::new("missing.hbs", Span::call_site())).unwrap_err();
+        assert!(e.to_string().starts_with("failed to read template"));
+    }
+}
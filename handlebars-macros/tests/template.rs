@@ -0,0 +1,29 @@
+use std::collections::BTreeMap;
+
+use handlebars::{Handlebars, Template};
+use handlebars_macros::{include_template, template};
+
+#[test]
+fn test_template() {
+    let t = template!("Hello {{#if name}}{{name}}{{else}}world{{/if}}!");
+    assert_eq!(
+        t,
+        Template::compile("Hello {{#if name}}{{name}}{{else}}world{{/if}}!").unwrap()
+    );
+
+    let mut hbs = Handlebars::new();
+    hbs.register_template("hello", t);
+    let data = BTreeMap::from([("name", "handlebars")]);
+    assert_eq!(hbs.render("hello", &data).unwrap(), "Hello handlebars!");
+}
+
+#[test]
+fn test_include_template() {
+    let t = include_template!("tests/templates/page.hbs");
+    let expected = Template::compile_with_name(
+        include_str!("templates/page.hbs"),
+        "tests/templates/page.hbs".to_owned(),
+    )
+    .unwrap();
+    assert_eq!(t, expected);
+}
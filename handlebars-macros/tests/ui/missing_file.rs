@@ -0,0 +1,5 @@
+use handlebars_macros::include_template;
+
+fn main() {
+    let _ = include_template!("tests/templates/missing.hbs");
+}
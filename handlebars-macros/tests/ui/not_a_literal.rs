@@ -0,0 +1,5 @@
+use handlebars_macros::template;
+
+fn main() {
+    let _ = template!(42);
+}
@@ -0,0 +1,200 @@
+//! Formatter for handlebars templates
+//!
+//! ```text
+//! hbsfmt [OPTIONS] [PATH]...
+//! ```
+//!
+//! Formats the given template files in place, and the templates found in
+//! given directories. Without path, the template read from stdin is written
+//! formatted to stdout. See `handlebars::format_template` for the style.
+//! Indenting block bodies changes the whitespace of the rendered output,
+//! `--no-indent-bodies` formats only the tags.
+//!
+//! With `--check`, nothing is written: the files that are not formatted are
+//! listed and the exit code is 1. Syntax errors exit with 2.
+
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use handlebars::{FormatOptions, FormatOptionsBuilder, QuoteStyle, format_template};
+use walkdir::WalkDir;
+
+const USAGE: &str = "\
+Usage: hbsfmt [OPTIONS] [PATH]...
+
+Format handlebars templates in place. Directories are searched for files
+with the template extension. Without PATH, format stdin to stdout.
+
+Options:
+      --check              List unformatted files and exit with 1, write nothing
+      --extension <EXT>    Extension of templates in directories [default: .hbs]
+      --indent <N>         Indentation of block bodies [default: 2]
+      --max-width <N>      Column to break hash arguments at [default: 80]
+      --mustache-compat    Parse {{^name}} as an inverted section inside blocks
+      --no-indent-bodies   Keep the indentation of block bodies, and the output
+      --single-quote       Prefer single quotes for string literals
+  -h, --help               Print this help
+";
+
+struct Args {
+    check: bool,
+    extension: String,
+    options: FormatOptions,
+    paths: Vec<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut check = false;
+    let mut extension = ".hbs".to_owned();
+    let mut builder = FormatOptionsBuilder::default();
+    let mut paths = Vec::new();
+
+    fn number(flag: &str, value: Option<String>) -> Result<usize, String> {
+        value
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| format!("{flag} expects a number"))
+    }
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--check" => check = true,
+            "--single-quote" => {
+                builder.quote_style(QuoteStyle::Single);
+            }
+            "--indent" => {
+                builder.indent_width(number(&arg, args.next())?);
+            }
+            "--max-width" => {
+                builder.max_width(number(&arg, args.next())?);
+            }
+            "--mustache-compat" => {
+                builder.mustache_compat(true);
+            }
+            "--no-indent-bodies" => {
+                builder.indent_bodies(false);
+            }
+            "--extension" => {
+                extension = args
+                    .next()
+                    .ok_or_else(|| "--extension expects a value".to_owned())?;
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option {flag}")),
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+
+    Ok(Some(Args {
+        check,
+        extension,
+        options: builder.build().map_err(|e| e.to_string())?,
+        paths,
+    }))
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    if args.paths.is_empty() {
+        return format_stdin(&args);
+    }
+
+    let mut unformatted = false;
+    let mut failed = false;
+    for path in files(&args.paths, &args.extension) {
+        match format_file(&path, &args) {
+            Ok(true) => {}
+            Ok(false) => {
+                unformatted = true;
+                println!("{}", path.display());
+            }
+            Err(e) => {
+                failed = true;
+                eprintln!("{}: {e}", path.display());
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::from(2)
+    } else if unformatted && args.check {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// The given files, and the templates of the given directories
+fn files(paths: &[PathBuf], extension: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut found: Vec<_> = WalkDir::new(path)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.into_path())
+            .filter(|p| p.to_string_lossy().ends_with(extension))
+            .collect();
+        found.sort();
+        files.extend(found);
+    }
+    files
+}
+
+/// Format a file in place, or only check it with `--check`. Returns whether
+/// the file was already formatted.
+fn format_file(path: &Path, args: &Args) -> Result<bool, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let formatted = format_template(&source, &args.options).map_err(|e| e.to_string())?;
+    if formatted == source {
+        return Ok(true);
+    }
+    if !args.check {
+        std::fs::write(path, formatted).map_err(|e| e.to_string())?;
+    }
+    Ok(false)
+}
+
+fn format_stdin(args: &Args) -> ExitCode {
+    let mut source = String::new();
+    if let Err(e) = io::stdin().read_to_string(&mut source) {
+        eprintln!("error: {e}");
+        return ExitCode::from(2);
+    }
+    match format_template(&source, &args.options) {
+        Ok(formatted) if args.check => {
+            if formatted == source {
+                ExitCode::SUCCESS
+            } else {
+                println!("<stdin>");
+                ExitCode::from(1)
+            }
+        }
+        Ok(formatted) => match io::stdout().write_all(formatted.as_bytes()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {e}");
+                ExitCode::from(2)
+            }
+        },
+        Err(e) => {
+            eprintln!("<stdin>: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+const UNFORMATTED: &str = "{{#if a}}\n{{ name   'x' }}\n{{/if}}\n";
+const FORMATTED: &str = "{{#if a}}\n  {{name \"x\"}}\n{{/if}}\n";
+
+fn hbsfmt(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hbsfmt"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the process may exit before reading stdin
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_stdin() {
+    let out = hbsfmt(&[], UNFORMATTED);
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), FORMATTED);
+
+    let out = hbsfmt(&["--indent", "4", "--single-quote"], UNFORMATTED);
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "{{#if a}}\n    {{name 'x'}}\n{{/if}}\n"
+    );
+
+    assert_eq!(hbsfmt(&["--check"], UNFORMATTED).status.code(), Some(1));
+    assert_eq!(hbsfmt(&["--check"], FORMATTED).status.code(), Some(0));
+}
+
+#[test]
+fn test_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("partials")).unwrap();
+    let page = dir.path().join("page.hbs");
+    let header = dir.path().join("partials/header.hbs");
+    let other = dir.path().join("notes.txt");
+    fs::write(&page, UNFORMATTED).unwrap();
+    fs::write(&header, FORMATTED).unwrap();
+    fs::write(&other, UNFORMATTED).unwrap();
+    let root = dir.path().to_str().unwrap();
+
+    let out = hbsfmt(&["--check", root], "");
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        format!("{}\n", page.display())
+    );
+    assert_eq!(fs::read_to_string(&page).unwrap(), UNFORMATTED);
+
+    let out = hbsfmt(&[root], "");
+    assert!(out.status.success());
+    assert_eq!(fs::read_to_string(&page).unwrap(), FORMATTED);
+    assert_eq!(fs::read_to_string(&header).unwrap(), FORMATTED);
+    assert_eq!(fs::read_to_string(&other).unwrap(), UNFORMATTED);
+
+    assert_eq!(hbsfmt(&["--check", root], "").status.code(), Some(0));
+}
+
+#[test]
+fn test_errors() {
+    let out = hbsfmt(&[], "{{#if a}}");
+    assert_eq!(out.status.code(), Some(2));
+    assert!(
+        String::from_utf8(out.stderr)
+            .unwrap()
+            .starts_with("<stdin>: ")
+    );
+
+    assert_eq!(hbsfmt(&["--indent"], "").status.code(), Some(2));
+    assert_eq!(hbsfmt(&["--nope"], "").status.code(), Some(2));
+}
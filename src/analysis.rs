@@ -0,0 +1,519 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::error::TemplateErrorReason;
+use crate::json::path::{Path, PathSeg};
+use crate::printer::parameter_to_source;
+use crate::registry::Registry;
+use crate::template::TemplateElement::{
+    DecoratorBlock, DecoratorExpression, Expression, HtmlExpression, PartialBlock,
+    PartialExpression,
+};
+use crate::template::{
+    BlockParam, DecoratorTemplate, HelperTemplate, Parameter, Template, TemplateElement,
+    TemplateMapping, TemplateVisitor, sorted_hash, walk_element,
+};
+
+/// A partial included by a template
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PartialReference {
+    /// Partial included by its name, like `{{> header}}`
+    Named(String),
+    /// Partial whose name is only known at render time, like
+    /// `{{> (lookup this "layout")}}`. It holds the source of the name
+    /// expression.
+    Dynamic(String),
+}
+
+/// Everything a template refers to, collected from its syntax tree without
+/// rendering it
+///
+/// Names are reported as written in the template. Paths are not resolved
+/// against the block context they are used in, so `{{#with user}}{{name}}{{/with}}`
+/// reports both `user` and `name`. Data variables like `@index` and block
+/// params introduced by `as |item|` are not reported as paths.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TemplateReferences {
+    /// Context paths read by the template
+    pub paths: BTreeSet<String>,
+    /// Helpers called by the template, including built-in ones like `if`
+    pub helpers: BTreeSet<String>,
+    /// Decorators called by the template, including `inline`
+    pub decorators: BTreeSet<String>,
+    /// Partials included by the template
+    pub partials: BTreeSet<PartialReference>,
+    /// Inline partials defined by the template with `{{#*inline "name"}}`
+    pub inline_partials: BTreeSet<String>,
+}
+
+impl Template {
+    /// Collect the paths, helpers, decorators and partials used by this
+    /// template.
+    ///
+    /// A simple mustache like `{{name}}` is either a helper call or a value
+    /// lookup, depending on the helpers registered at render time. It is
+    /// reported as a path here. Use `Registry::analyze` to take registered
+    /// helpers into account.
+    ///
+    /// ```
+    /// use handlebars::Template;
+    ///
+    /// let t = Template::compile("{{#each items as |item|}}{{> row item}}{{/each}}").unwrap();
+    /// let refs = t.references();
+    /// assert!(refs.paths.contains("items"));
+    /// assert!(refs.helpers.contains("each"));
+    /// ```
+    pub fn references(&self) -> TemplateReferences {
+        collect_references(self, &|_| false)
+    }
+}
+
+pub(crate) fn collect_references(
+    t: &Template,
+    is_helper: &dyn Fn(&str) -> bool,
+) -> TemplateReferences {
+    let mut refs = TemplateReferences::default();
+    walk_references(t, is_helper, |r, _| match r {
+        Reference::Path(p) => {
+            refs.paths.insert(p.to_owned());
+        }
+        Reference::Helper(h) => {
+            refs.helpers.insert(h.to_owned());
+        }
+        Reference::Decorator(d) => {
+            refs.decorators.insert(d.to_owned());
+        }
+        Reference::Partial { partial, .. } => {
+            refs.partials.insert(partial);
+        }
+        Reference::InlinePartial(p) => {
+            refs.inline_partials.insert(p.to_owned());
+        }
+    });
+    refs
+}
+
+/// A single reference found in a template
+pub(crate) enum Reference<'a> {
+    Path(&'a str),
+    Helper(&'a str),
+    Decorator(&'a str),
+    Partial {
+        partial: PartialReference,
+        // partial block with content to render when the partial is missing
+        has_fallback: bool,
+    },
+    InlinePartial(&'a str),
+}
+
+/// Call `f` with every reference of the template, along with the line and
+/// column of the top level element it is found in.
+pub(crate) fn walk_references<F>(t: &Template, is_helper: &dyn Fn(&str) -> bool, f: F)
+where
+    F: FnMut(Reference<'_>, Option<(usize, usize)>),
+{
+    let mut collector = ReferenceCollector {
+        f,
+        pos: None,
+        block_params: Vec::new(),
+        is_helper,
+    };
+    collector.visit_template(t);
+}
+
+struct ReferenceCollector<'a, F> {
+    f: F,
+    pos: Option<(usize, usize)>,
+    // block params in scope, from outermost to innermost block
+    block_params: Vec<String>,
+    is_helper: &'a dyn Fn(&str) -> bool,
+}
+
+impl<F> ReferenceCollector<'_, F>
+where
+    F: FnMut(Reference<'_>, Option<(usize, usize)>),
+{
+    fn emit(&mut self, r: Reference<'_>) {
+        (self.f)(r, self.pos);
+    }
+
+    fn params(&mut self, params: &[Parameter], hash: &HashMap<String, Parameter>) {
+        for p in params {
+            self.visit_parameter(p);
+        }
+        for (_, p) in sorted_hash(hash) {
+            self.visit_parameter(p);
+        }
+    }
+
+    fn partial(&mut self, d: &DecoratorTemplate) {
+        let partial = match d.name {
+            Parameter::Name(ref n) if n.starts_with('@') => {
+                // `@partial-block`, provided by the caller
+                None
+            }
+            Parameter::Name(ref n) => Some(PartialReference::Named(n.to_owned())),
+            Parameter::Path(ref p) => Some(PartialReference::Named(p.raw().to_owned())),
+            ref name => {
+                self.visit_parameter(name);
+                Some(PartialReference::Dynamic(parameter_to_source(name)))
+            }
+        };
+        if let Some(partial) = partial {
+            self.emit(Reference::Partial {
+                partial,
+                has_fallback: d.template.is_some(),
+            });
+        }
+        self.params(&d.params, &d.hash);
+        if let Some(ref t) = d.template {
+            self.visit_template(t);
+        }
+    }
+
+    fn decorator(&mut self, d: &DecoratorTemplate) {
+        match d.name.as_name() {
+            Some(name) => {
+                if name == "inline" {
+                    if let Some(Parameter::Literal(partial_name)) = d.params.first() {
+                        if let Some(partial_name) = partial_name.as_str() {
+                            self.emit(Reference::InlinePartial(partial_name));
+                        }
+                    }
+                }
+                self.emit(Reference::Decorator(name));
+            }
+            None => self.visit_parameter(&d.name),
+        }
+        self.params(&d.params, &d.hash);
+        if let Some(ref t) = d.template {
+            self.visit_template(t);
+        }
+    }
+
+    fn block_param(&mut self, p: &Parameter) {
+        if let Some(name) = p.as_name() {
+            self.block_params.push(name.to_owned());
+        }
+    }
+
+    fn is_block_param(&self, path: &Path) -> bool {
+        match path.segs().and_then(|segs| segs.first()) {
+            Some(PathSeg::Named(name)) => self.block_params.contains(name),
+            _ => false,
+        }
+    }
+}
+
+impl<F> TemplateVisitor for ReferenceCollector<'_, F>
+where
+    F: FnMut(Reference<'_>, Option<(usize, usize)>),
+{
+    fn visit_template(&mut self, t: &Template) {
+        for (idx, e) in t.elements.iter().enumerate() {
+            // else-if chain templates have no mapping, keep the position of
+            // the block they belong to
+            if let Some(&TemplateMapping(line, col)) = t.mapping.get(idx) {
+                self.pos = Some((line, col));
+            }
+            self.visit_element(e);
+        }
+    }
+
+    fn visit_element(&mut self, e: &TemplateElement) {
+        match e {
+            Expression(ht) | HtmlExpression(ht) if ht.is_name_only() => match ht.name {
+                // decided at render time: a helper if one is registered with
+                // this name, a value lookup otherwise
+                Parameter::Path(ref p) if (self.is_helper)(p.raw()) => {
+                    self.emit(Reference::Helper(p.raw()));
+                }
+                ref name => self.visit_parameter(name),
+            },
+            PartialExpression(dt) | PartialBlock(dt) => self.partial(dt),
+            DecoratorExpression(dt) | DecoratorBlock(dt) => self.decorator(dt),
+            _ => walk_element(self, e),
+        }
+    }
+
+    fn visit_helper(&mut self, h: &HelperTemplate) {
+        match h.name.as_name() {
+            Some(name) => self.emit(Reference::Helper(name)),
+            None => self.visit_parameter(&h.name),
+        }
+        self.params(&h.params, &h.hash);
+
+        let pos = self.pos;
+        if let Some(ref t) = h.template {
+            let scope = self.block_params.len();
+            match h.block_param {
+                Some(BlockParam::Single(ref p)) => self.block_param(p),
+                Some(BlockParam::Pair((ref p1, ref p2))) => {
+                    self.block_param(p1);
+                    self.block_param(p2);
+                }
+                None => {}
+            }
+            self.visit_template(t);
+            self.block_params.truncate(scope);
+        }
+        if let Some(ref t) = h.inverse {
+            self.pos = pos;
+            self.visit_template(t);
+        }
+    }
+
+    fn visit_path(&mut self, path: &Path) {
+        if let Path::Relative((ref segs, ref raw)) = *path {
+            if !segs.is_empty() && !self.is_block_param(path) {
+                self.emit(Reference::Path(raw));
+            }
+        }
+    }
+}
+
+/// Find the helpers, decorators and partials used by a template that are not
+/// registered. Partials are only checked when the set of known partial names
+/// is given.
+pub(crate) fn unknown_references(
+    registry: &Registry<'_>,
+    t: &Template,
+    partials: Option<&BTreeSet<&str>>,
+) -> Vec<(TemplateErrorReason, Option<(usize, usize)>)> {
+    let mut unknown = Vec::new();
+    walk_references(t, &|n| registry.has_helper(n), |r, pos| match r {
+        Reference::Helper(h) if !registry.has_helper(h) => {
+            unknown.push((TemplateErrorReason::UnknownHelper(h.to_owned()), pos));
+        }
+        Reference::Decorator(d) if registry.get_decorator(d).is_none() => {
+            unknown.push((TemplateErrorReason::UnknownDecorator(d.to_owned()), pos));
+        }
+        Reference::Partial {
+            partial: PartialReference::Named(p),
+            has_fallback: false,
+        } if partials.is_some_and(|partials| !partials.contains(p.as_str())) => {
+            unknown.push((TemplateErrorReason::UnknownPartial(p), pos));
+        }
+        _ => {}
+    });
+    unknown
+}
+
+/// Partial dependencies between the templates of a registry
+///
+/// Each registered template is a node, with an edge to every partial it
+/// includes by name. Partials that are defined inline in the including
+/// template, and dynamic partials, are not part of the graph.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    edges: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl DependencyGraph {
+    pub(crate) fn from_registry(registry: &Registry<'_>) -> DependencyGraph {
+        let edges = registry
+            .get_templates()
+            .iter()
+            .map(|(name, t)| {
+                let refs = t.references();
+                let partials = refs
+                    .partials
+                    .into_iter()
+                    .filter_map(|p| match p {
+                        PartialReference::Named(n) if !refs.inline_partials.contains(&n) => Some(n),
+                        _ => None,
+                    })
+                    .collect();
+                (name.to_owned(), partials)
+            })
+            .collect();
+        DependencyGraph { edges }
+    }
+
+    /// Names of all templates in the graph
+    pub fn templates(&self) -> impl Iterator<Item = &str> {
+        self.edges.keys().map(String::as_str)
+    }
+
+    /// Partials included directly by the given template, `None` if the
+    /// template is not registered
+    pub fn dependencies(&self, name: &str) -> Option<&BTreeSet<String>> {
+        self.edges.get(name)
+    }
+
+    /// Templates that include the given partial directly
+    pub fn dependents<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.edges
+            .iter()
+            .filter(move |(_, deps)| deps.contains(name))
+            .map(|(t, _)| t.as_str())
+    }
+
+    /// Partials included by name that are not registered
+    pub fn missing(&self) -> BTreeSet<&str> {
+        self.edges
+            .values()
+            .flatten()
+            .filter(|p| !self.edges.contains_key(p.as_str()))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Find the cycles of partial inclusion
+    ///
+    /// Each cycle is returned as the list of templates on it, starting from
+    /// its smallest name, e.g. `["a", "b"]` for `a` including `b` and `b`
+    /// including `a`. At least one cycle is reported for every group of
+    /// mutually including templates.
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let mut state = BTreeMap::new();
+        let mut stack = Vec::new();
+        let mut cycles = BTreeSet::new();
+        for name in self.edges.keys() {
+            self.find_cycles(name, &mut state, &mut stack, &mut cycles);
+        }
+        cycles.into_iter().collect()
+    }
+
+    fn find_cycles<'a>(
+        &'a self,
+        name: &'a str,
+        state: &mut BTreeMap<&'a str, bool>,
+        stack: &mut Vec<&'a str>,
+        cycles: &mut BTreeSet<Vec<String>>,
+    ) {
+        // `false` while the node is on the stack, `true` once done
+        match state.get(name) {
+            Some(true) => return,
+            Some(false) => {
+                let start = stack.iter().rposition(|n| *n == name).unwrap();
+                let cycle = &stack[start..];
+                let min = (0..cycle.len()).min_by_key(|i| cycle[*i]).unwrap();
+                let cycle = cycle[min..]
+                    .iter()
+                    .chain(&cycle[..min])
+                    .map(|n| (*n).to_owned())
+                    .collect();
+                cycles.insert(cycle);
+                return;
+            }
+            None => {}
+        }
+
+        state.insert(name, false);
+        stack.push(name);
+        if let Some(deps) = self.edges.get(name) {
+            for dep in deps {
+                self.find_cycles(dep, state, stack, cycles);
+            }
+        }
+        stack.pop();
+        state.insert(name, true);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registry::Registry;
+
+    fn set(items: &[&str]) -> BTreeSet<String> {
+        items.iter().map(|s| (*s).to_owned()).collect()
+    }
+
+    #[test]
+    fn test_references() {
+        let t = Template::compile(
+            "{{title}} {{{body}}}
+{{#each items as |item idx|}}{{item.name}} {{@index}} {{../title}}{{/each}}
+{{#if (gt count 1)}}many{{else if @root.single}}one{{else}}{{none}}{{/if}}
+{{format date pattern=config.date}} {{#with user}}{{name}}{{/with}}
+{{> header}} {{#> layout title=title}}{{> @partial-block}}{{/layout}}
+{{> (lookup this \"page\") ctx}}
+{{#*inline \"row\"}}{{this}}{{/inline}}{{*deco flag}}",
+        )
+        .unwrap();
+        let refs = t.references();
+
+        assert_eq!(
+            refs.paths,
+            set(&[
+                "../title",
+                "@root.single",
+                "body",
+                "config.date",
+                "count",
+                "ctx",
+                "date",
+                "flag",
+                "items",
+                "name",
+                "none",
+                "title",
+                "user"
+            ])
+        );
+        assert_eq!(
+            refs.helpers,
+            set(&["each", "format", "gt", "if", "lookup", "with"])
+        );
+        assert_eq!(refs.decorators, set(&["deco", "inline"]));
+        assert_eq!(refs.inline_partials, set(&["row"]));
+        assert_eq!(
+            refs.partials,
+            [
+                PartialReference::Named("header".to_owned()),
+                PartialReference::Named("layout".to_owned()),
+                PartialReference::Dynamic("(lookup this \"page\")".to_owned()),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn test_analyze_with_registered_helpers() {
+        let mut r = Registry::new();
+        r.register_template_string("t", "{{upper}} {{name}} {{#if ok}}{{/if}}")
+            .unwrap();
+        handlebars_helper!(upper: |s: str| s.to_uppercase());
+        r.register_helper("upper", Box::new(upper));
+
+        let refs = r.analyze("t").unwrap();
+        assert_eq!(refs.paths, set(&["name", "ok"]));
+        assert_eq!(refs.helpers, set(&["if", "upper"]));
+
+        assert!(r.analyze("missing").is_none());
+    }
+
+    #[test]
+    fn test_dependency_graph() {
+        let mut r = Registry::new();
+        r.register_template_string("page", "{{> header}}{{> body}}{{> footer}}")
+            .unwrap();
+        r.register_template_string("header", "{{> nav}}").unwrap();
+        r.register_template_string("nav", "{{> header}}").unwrap();
+        r.register_template_string("body", "{{#*inline \"item\"}}x{{/inline}}{{> item}}")
+            .unwrap();
+        r.register_template_string("self", "{{#if x}}{{> self}}{{/if}}")
+            .unwrap();
+
+        let graph = r.dependency_graph();
+        assert_eq!(
+            graph.dependencies("page"),
+            Some(&set(&["body", "footer", "header"]))
+        );
+        assert_eq!(graph.dependencies("body"), Some(&set(&[])));
+        assert_eq!(
+            graph.dependents("header").collect::<Vec<_>>(),
+            ["nav", "page"]
+        );
+        assert_eq!(graph.missing(), ["footer"].into_iter().collect());
+        assert_eq!(
+            graph.cycles(),
+            vec![
+                vec!["header".to_owned(), "nav".to_owned()],
+                vec!["self".to_owned()]
+            ]
+        );
+    }
+}
@@ -0,0 +1,571 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::Poll;
+
+use serde_json::Value as Json;
+
+use crate::context::Context;
+use crate::error::{RenderError, RenderErrorReason};
+use crate::helpers::{HelperDef, HelperResult};
+use crate::json::value::{JsonRender, ScopedJson};
+use crate::limits::LimitUsage;
+use crate::output::Output;
+use crate::registry::Registry;
+use crate::render::{Helper, RenderContext, do_escape, indent_aware_write};
+
+/// The future returned by an `AsyncHelperDef`
+pub type AsyncHelperFuture = Pin<Box<dyn Future<Output = Result<Json, RenderError>> + Send>>;
+
+/// Async helper definition
+///
+/// An async helper computes a value from its parameters and hash, like a
+/// helper implementing `HelperDef::call_inner`, but the value is awaited.
+/// It can be used in mustaches, `{{fetch id}}`, and subexpressions,
+/// `{{#each (fetch id)}}`, anywhere in a template rendered with
+/// `Registry::render_async`. Rendering a template that calls an async helper
+/// with a sync render method fails with
+/// `RenderErrorReason::AsyncHelperInSyncRender`.
+///
+/// The future is not tied to an async runtime, and a function or closure
+/// returning a future is an async helper:
+///
+/// ```
+/// use handlebars::*;
+///
+/// let mut hbs = Handlebars::new();
+/// hbs.register_async_helper(
+///     "greeting",
+///     Box::new(|params: Vec<JsonValue>, _| async move {
+///         let name = params.first().and_then(|v| v.as_str()).unwrap_or("world");
+///         Ok(JsonValue::from(format!("hello {name}")))
+///     }),
+/// );
+/// ```
+///
+/// ## How templates are rendered
+///
+/// `render_async` renders the template, and collects the calls to async
+/// helpers along the way, rendering `null` in their place. The collected
+/// calls are then awaited concurrently, and the template is rendered again
+/// with their values, until a render makes no new call. When an element of
+/// the template uses the value of a call not awaited yet, as a parameter or
+/// the context of a block, the calls in the rest of that element may depend
+/// on that value, so they are left to the next render. The next elements
+/// make their calls in the same render. The number of renders grows with the
+/// nesting of calls depending on other calls, not with the number of calls:
+/// `{{#each items}}{{#if (allowed this)}}{{/if}}{{/each}}` calls `allowed`
+/// for all the items in the first render.
+///
+/// These renders skip `{{log}}`, the `RenderObserver` and the coverage of
+/// the registry. The output of the render making no call is written to the
+/// output, unless it skipped one of them: the template is then rendered a
+/// last time with all the values. So an async helper is called once for the
+/// same parameters and hash in a render, and the other helpers may be called
+/// several times. The steps and iterations of all the renders count against
+/// the same `RenderLimits`.
+pub trait AsyncHelperDef {
+    fn call(&self, params: Vec<Json>, hash: BTreeMap<String, Json>) -> AsyncHelperFuture;
+}
+
+impl<F, Fut> AsyncHelperDef for F
+where
+    F: Fn(Vec<Json>, BTreeMap<String, Json>) -> Fut,
+    Fut: Future<Output = Result<Json, RenderError>> + Send + 'static,
+{
+    fn call(&self, params: Vec<Json>, hash: BTreeMap<String, Json>) -> AsyncHelperFuture {
+        Box::pin(self(params, hash))
+    }
+}
+
+/// Calls to async helpers of a render, shared by its passes
+#[derive(Default)]
+pub(crate) struct AsyncCalls {
+    state: Mutex<AsyncCallsState>,
+}
+
+#[derive(Default)]
+struct AsyncCallsState {
+    results: HashMap<String, Json>,
+    pending: Vec<(String, AsyncHelperFuture)>,
+    // a value not awaited yet was used in the current element, the calls
+    // in the rest of the element are not started
+    used_pending: bool,
+    // a side effect was skipped in this pass, like a log
+    skipped_effects: bool,
+    // all the calls are awaited, the pass renders to the output
+    final_pass: bool,
+    // steps and iterations of the passes so far
+    limit_usage: LimitUsage,
+}
+
+impl AsyncCalls {
+    fn state(&self) -> std::sync::MutexGuard<'_, AsyncCallsState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn result(&self, key: &str) -> Option<Json> {
+        self.state().results.get(key).cloned()
+    }
+
+    fn defer(&self, key: String, used: bool, call: impl FnOnce() -> AsyncHelperFuture) {
+        let mut state = self.state();
+        if !state.used_pending && !state.pending.iter().any(|(k, _)| *k == key) {
+            state.pending.push((key, call()));
+        }
+        state.used_pending |= used;
+    }
+
+    /// Start a pass rendering the template
+    pub(crate) fn begin_pass(&self) {
+        let mut state = self.state();
+        state.used_pending = false;
+        state.skipped_effects = false;
+    }
+
+    /// Start rendering an element, returns the state to restore once it's
+    /// rendered with `end_element`
+    pub(crate) fn begin_element(&self) -> bool {
+        self.state().used_pending
+    }
+
+    /// Restore the state of `begin_element` once the element is rendered,
+    /// the next elements don't depend on the values it used
+    pub(crate) fn end_element(&self, used_pending: bool) {
+        self.state().used_pending = used_pending;
+    }
+
+    /// Whether a side effect of a helper is skipped, in the passes before
+    /// the last one
+    pub(crate) fn skip_effect(&self) -> bool {
+        let mut state = self.state();
+        state.skipped_effects |= !state.final_pass;
+        !state.final_pass
+    }
+
+    pub(crate) fn skipped_effects(&self) -> bool {
+        self.state().skipped_effects
+    }
+
+    pub(crate) fn limit_usage(&self) -> LimitUsage {
+        self.state().limit_usage
+    }
+
+    pub(crate) fn set_limit_usage(&self, usage: LimitUsage) {
+        self.state().limit_usage = usage;
+    }
+
+    /// Start the pass rendering the template with all the calls awaited
+    pub(crate) fn begin_final_pass(&self) {
+        self.state().final_pass = true;
+    }
+
+    pub(crate) fn is_final_pass(&self) -> bool {
+        self.state().final_pass
+    }
+
+    /// Await the calls collected by the last pass, returns false when there
+    /// was none
+    pub(crate) async fn resolve(&self) -> Result<bool, RenderError> {
+        let pending = std::mem::take(&mut self.state().pending);
+        if pending.is_empty() {
+            return Ok(false);
+        }
+
+        let (keys, mut futures): (Vec<_>, Vec<_>) =
+            pending.into_iter().map(|(k, f)| (k, Some(f))).unzip();
+        let mut values: Vec<Option<Json>> = vec![None; futures.len()];
+        std::future::poll_fn(|cx| {
+            for (future, value) in futures.iter_mut().zip(values.iter_mut()) {
+                if let Some(f) = future {
+                    if let Poll::Ready(result) = f.as_mut().poll(cx) {
+                        *value = Some(result?);
+                        *future = None;
+                    }
+                }
+            }
+            if futures.iter().all(Option::is_none) {
+                Poll::Ready(Ok::<(), RenderError>(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await?;
+
+        self.state()
+            .results
+            .extend(keys.into_iter().zip(values.into_iter().flatten()));
+        Ok(true)
+    }
+}
+
+/// The `HelperDef` registered for an async helper, it returns the value of
+/// the call once awaited
+pub(crate) struct AsyncHelper<'reg> {
+    pub(crate) def: Box<dyn AsyncHelperDef + Send + Sync + 'reg>,
+}
+
+impl AsyncHelper<'_> {
+    /// The value of the call, `null` until it's awaited. `used` tells whether
+    /// the template uses the value, rather than writing it.
+    fn value(
+        &self,
+        h: &Helper<'_>,
+        rc: &RenderContext<'_, '_>,
+        used: bool,
+    ) -> Result<Json, RenderError> {
+        let calls = rc
+            .async_calls()
+            .ok_or_else(|| RenderErrorReason::AsyncHelperInSyncRender(h.name().to_owned()))?;
+
+        let params: Vec<Json> = h.params().iter().map(|p| p.value().clone()).collect();
+        let hash: BTreeMap<String, Json> = h
+            .hash()
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), v.value().clone()))
+            .collect();
+        let key = Json::from(vec![
+            Json::from(h.name()),
+            Json::from(params.clone()),
+            Json::from(serde_json::Map::from_iter(hash.clone())),
+        ])
+        .to_string();
+
+        if let Some(value) = calls.result(&key) {
+            return Ok(value);
+        }
+        if calls.is_final_pass() {
+            // the template made other calls than the previous pass
+            return Err(RenderErrorReason::AsyncHelperNotAwaited(h.name().to_owned()).into());
+        }
+        calls.defer(key, used, || self.def.call(params, hash));
+        Ok(Json::Null)
+    }
+}
+
+impl HelperDef for AsyncHelper<'_> {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Registry<'reg>,
+        _: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        self.value(h, rc, true).map(ScopedJson::Derived)
+    }
+
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Registry<'reg>,
+        _: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let value = self.value(h, rc, false)?;
+        let output = do_escape(r, rc, value.render());
+        indent_aware_write(&output, rc, out)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll, Waker};
+
+    use serde_json::{Value as Json, json};
+
+    use crate::error::RenderError;
+    use crate::observer::RenderObserver;
+    use crate::output::Output;
+    use crate::registry::Registry;
+    use crate::render::{Helper, RenderContext};
+    use crate::{
+        CancellationToken, RenderErrorReason, RenderLimit, RenderLimitsBuilder,
+        RenderOptionsBuilder,
+    };
+
+    // a minimal executor, the crate doesn't depend on a runtime
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = pin!(f);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+                return v;
+            }
+        }
+    }
+
+    // a future ready on its second poll, like an IO
+    async fn yield_once() {
+        let mut yielded = false;
+        std::future::poll_fn(|cx| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    fn registry(calls: Arc<AtomicUsize>) -> Registry<'static> {
+        let mut hbs = Registry::new();
+        hbs.register_async_helper(
+            "user",
+            Box::new(move |params: Vec<Json>, _: BTreeMap<String, Json>| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    yield_once().await;
+                    let id = params[0].as_u64().unwrap();
+                    Ok(json!({"name": format!("user{id}"), "friends": [id + 1, id + 2]}))
+                }
+            }),
+        );
+        hbs.register_async_helper(
+            "upper",
+            Box::new(
+                |params: Vec<Json>, hash: BTreeMap<String, Json>| async move {
+                    let s = params[0].as_str().unwrap_or_default().to_uppercase();
+                    let suffix = hash
+                        .get("suffix")
+                        .and_then(Json::as_str)
+                        .unwrap_or_default();
+                    Ok(Json::from(s + suffix))
+                },
+            ),
+        );
+        hbs
+    }
+
+    #[test]
+    fn test_render_async() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut hbs = registry(calls.clone());
+        hbs.register_template_string(
+            "t",
+            "{{#with (user id)}}{{upper name suffix=\"!\"}}:\
+             {{#each friends}} {{lookup (user this) \"name\"}}{{/each}}\
+             {{/with}} {{lookup (user id) \"name\"}}",
+        )
+        .unwrap();
+
+        let out = block_on(hbs.render_async("t", &json!({"id": 1}))).unwrap();
+        assert_eq!(out, "USER1!: user2 user3 user1");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        let mut buf = Vec::new();
+        block_on(hbs.render_to_write_async("t", &json!({"id": 5}), &mut buf)).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "USER5!: user6 user7 user5");
+    }
+
+    #[test]
+    fn test_dependent_calls() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut hbs = Registry::new();
+        let echo_calls = calls.clone();
+        hbs.register_async_helper(
+            "echo",
+            Box::new(move |params: Vec<Json>, _: BTreeMap<String, Json>| {
+                echo_calls.lock().unwrap().push(Json::from(params.clone()));
+                async move { Ok(Json::from(params)) }
+            }),
+        );
+        hbs.register_template_string(
+            "t",
+            "{{echo 1}} {{#each (echo 2)}}{{echo this \"x\"}}{{/each}} {{echo (echo 3)}}",
+        )
+        .unwrap();
+
+        let out = block_on(hbs.render_async("t", &())).unwrap();
+        assert_eq!(out, "[1] [2, x] [[3]]");
+        // calls using the value of `echo 2` or `echo 3` wait for it, the
+        // next elements don't
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                json!([1]),
+                json!([2]),
+                json!([3]),
+                json!([2, "x"]),
+                json!([[3]])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_independent_calls() {
+        let renders = Arc::new(AtomicUsize::new(0));
+        let counts = Arc::new(AtomicUsize::new(0));
+        let mut hbs = Registry::new();
+        let allowed_calls = renders.clone();
+        hbs.register_async_helper(
+            "allowed",
+            Box::new(move |params: Vec<Json>, _: BTreeMap<String, Json>| {
+                allowed_calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    yield_once().await;
+                    Ok(Json::from(params[0].as_u64().unwrap() % 2 == 0))
+                }
+            }),
+        );
+        let count = counts.clone();
+        hbs.register_helper(
+            "count",
+            Box::new(
+                move |_: &Helper<'_>,
+                      _: &Registry<'_>,
+                      _: &crate::Context,
+                      _: &mut RenderContext<'_, '_>,
+                      _: &mut dyn Output| {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                },
+            ),
+        );
+        hbs.register_template_string(
+            "t",
+            "{{#each this}}{{count}}{{#if (allowed this)}}{{this}}{{/if}}{{/each}}",
+        )
+        .unwrap();
+
+        let items: Vec<u64> = (0..1000).collect();
+        let out = block_on(hbs.render_async("t", &items)).unwrap();
+        let even: String = items.iter().step_by(2).map(u64::to_string).collect();
+        assert_eq!(out, even);
+        assert_eq!(renders.load(Ordering::SeqCst), 1000);
+        // all the calls are made by the first render, the second one renders
+        // to the output
+        assert_eq!(counts.load(Ordering::SeqCst), 2 * 1000);
+
+        // without async helper, the template is rendered once
+        counts.store(0, Ordering::SeqCst);
+        hbs.register_template_string("sync", "{{#each this}}{{count}}{{/each}}")
+            .unwrap();
+        block_on(hbs.render_async("sync", &items)).unwrap();
+        assert_eq!(counts.load(Ordering::SeqCst), 1000);
+    }
+
+    #[test]
+    fn test_render_options() {
+        let mut hbs = registry(Arc::new(AtomicUsize::new(0)));
+        hbs.register_template_string(
+            "t",
+            "{{#each (lookup (user 1) \"friends\")}}{{this}}{{/each}}",
+        )
+        .unwrap();
+
+        // 1 step for the render making the call, 3 for the one iterating over
+        // its value
+        let limited = |max_steps| {
+            RenderOptionsBuilder::default()
+                .limits(
+                    RenderLimitsBuilder::default()
+                        .max_steps(max_steps)
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap()
+        };
+        assert!(block_on(hbs.render_async_with_options("t", &(), &limited(4))).is_ok());
+        assert!(matches!(
+            block_on(hbs.render_async_with_options("t", &(), &limited(3)))
+                .unwrap_err()
+                .reason(),
+            RenderErrorReason::LimitExceeded(RenderLimit::Steps(3))
+        ));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let options = RenderOptionsBuilder::default()
+            .cancellation_token(token)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            block_on(hbs.render_async_with_options("t", &(), &options))
+                .unwrap_err()
+                .reason(),
+            RenderErrorReason::Cancelled
+        ));
+    }
+
+    #[test]
+    fn test_side_effects_on_last_render() {
+        #[derive(Default)]
+        struct Templates(AtomicUsize);
+
+        impl RenderObserver for Templates {
+            fn enter_template(&self, _: &str) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let templates = Arc::new(Templates::default());
+        let mut hbs = registry(Arc::new(AtomicUsize::new(0)));
+        hbs.set_render_observer(Some(templates.clone()));
+        hbs.register_template_string(
+            "t",
+            "{{lookup (user (lookup (lookup (user 1) \"friends\") 0)) \"name\"}}",
+        )
+        .unwrap();
+
+        assert_eq!(block_on(hbs.render_async("t", &())).unwrap(), "user2");
+        assert_eq!(templates.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_sync_render() {
+        let mut hbs = registry(Arc::new(AtomicUsize::new(0)));
+        hbs.register_template_string("t", "{{upper \"a\"}}")
+            .unwrap();
+        hbs.register_template_string("sync", "{{len \"abc\"}}")
+            .unwrap();
+
+        assert!(matches!(
+            hbs.render("t", &()).unwrap_err().reason(),
+            RenderErrorReason::AsyncHelperInSyncRender(name) if name == "upper"
+        ));
+        assert_eq!(block_on(hbs.render_async("sync", &())).unwrap(), "3");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut hbs = Registry::new();
+        hbs.register_async_helper(
+            "fail",
+            Box::new(|_, _| async { Err(RenderError::from(RenderErrorReason::Unimplemented)) }),
+        );
+        hbs.register_template_string("t", "{{fail}}").unwrap();
+        hbs.register_template_string("missing", "{{> nope}}")
+            .unwrap();
+
+        assert!(matches!(
+            block_on(hbs.render_async("t", &())).unwrap_err().reason(),
+            RenderErrorReason::Unimplemented
+        ));
+        assert!(matches!(
+            block_on(hbs.render_async("missing", &()))
+                .unwrap_err()
+                .reason(),
+            RenderErrorReason::PartialNotFound(_)
+        ));
+    }
+
+    #[test]
+    fn test_future_is_send() {
+        fn assert_send<T: Send>(_: T) {}
+
+        let hbs = Registry::new();
+        let data = json!({});
+        assert_send(hbs.render_async("t", &data));
+    }
+}
@@ -1,4 +1,5 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
 
 use serde::Serialize;
 use serde_json::Map;
@@ -8,7 +9,7 @@ use crate::block::{BlockContext, BlockParamHolder};
 use crate::error::{RenderError, RenderErrorReason};
 use crate::grammar::Rule;
 use crate::json::path::{PathSeg, merge_json_path};
-use crate::json::value::ScopedJson;
+use crate::json::value::{LazyJson, ScopedJson};
 use crate::util::extend;
 
 pub type Object = HashMap<String, Json>;
@@ -18,6 +19,67 @@ pub type Object = HashMap<String, Json>;
 #[derive(Debug, Clone)]
 pub struct Context {
     data: Json,
+    // values computed on first use, by their full path
+    lazy: BTreeMap<Vec<String>, Arc<LazyJson>>,
+}
+
+/// Data that templates navigate by paths, converted to JSON only at the
+/// paths templates use
+///
+/// Render it with `Registry::render_value`. It's implemented for JSON values
+/// and, through `SerializeValue`, for any `Serialize` type, so a large
+/// struct isn't converted to a `serde_json::Value` as a whole before
+/// rendering.
+pub trait ContextValue {
+    /// The value at `path`, a list of object keys and array indices from
+    /// this value, or `None` when it's missing
+    ///
+    /// An empty path is the value itself. A path into a scalar is missing,
+    /// a non-numeric index into an array is a
+    /// `RenderErrorReason::InvalidJsonIndex` error.
+    fn lookup(&self, path: &[String]) -> Result<Option<Json>, RenderError>;
+}
+
+impl ContextValue for Json {
+    fn lookup(&self, path: &[String]) -> Result<Option<Json>, RenderError> {
+        let mut ptr = Some(self);
+        for p in path {
+            ptr = get_data(ptr, p)?;
+        }
+        Ok(ptr.cloned())
+    }
+}
+
+/// A borrowed `Serialize` value rendered as a `ContextValue`
+///
+/// Each lookup serializes the value again but only converts the looked up
+/// field or item to JSON.
+///
+/// ```
+/// use handlebars::{Handlebars, SerializeValue};
+/// use serde_derive::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Report {
+///     title: String,
+///     rows: Vec<u64>,
+/// }
+///
+/// let report = Report {
+///     title: "daily".to_owned(),
+///     rows: (0..100_000).collect(),
+/// };
+/// let mut hbs = Handlebars::new();
+/// hbs.register_template_string("t", "{{title}}: {{rows.[2]}}").unwrap();
+/// assert_eq!(hbs.render_value("t", &SerializeValue(&report)).unwrap(), "daily: 2");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct SerializeValue<'a, T: ?Sized>(pub &'a T);
+
+impl<T: Serialize + ?Sized> ContextValue for SerializeValue<'_, T> {
+    fn lookup(&self, path: &[String]) -> Result<Option<Json>, RenderError> {
+        crate::json::lookup::lookup(self.0, path)
+    }
 }
 
 #[derive(Debug)]
@@ -177,22 +239,71 @@ pub(crate) fn merge_json(base: &Json, addition: &HashMap<&str, &Json>) -> Json {
 impl Context {
     /// Create a context with null data
     pub fn null() -> Context {
-        Context { data: Json::Null }
+        Context::from(Json::Null)
     }
 
     /// Create a context with given data
     pub fn wraps<T: Serialize>(e: T) -> Result<Context, RenderError> {
         to_value(e)
             .map_err(|e| RenderErrorReason::SerdeError(e).into())
-            .map(|d| Context { data: d })
+            .map(Context::from)
     }
 
-    /// Navigate the context with relative path and block scopes
+    /// The value at an absolute path, from `value` when the context is
+    /// rendered from a `ContextValue`
+    fn get_absolute<'rc>(
+        &'rc self,
+        value: Option<&dyn ContextValue>,
+        paths: Vec<String>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        if let Some(value) = value {
+            return Ok(value
+                .lookup(&paths)?
+                .map_or_else(|| ScopedJson::Missing, ScopedJson::Derived));
+        }
+        // the lazy value with the longest path the path is in
+        let lazy = self
+            .lazy
+            .iter()
+            .rev()
+            .find(|(lazy_path, _)| paths.starts_with(lazy_path));
+        let (mut ptr, rest) = match lazy {
+            Some((lazy_path, lazy)) if lazy_path.len() == paths.len() => {
+                return Ok(ScopedJson::Context(lazy.try_get()?, paths));
+            }
+            Some((lazy_path, lazy)) => (Some(lazy.try_get()?), &paths[lazy_path.len()..]),
+            None => (Some(self.data()), &paths[..]),
+        };
+        for p in rest {
+            ptr = get_data(ptr, p)?;
+        }
+        Ok(ptr.map_or_else(|| ScopedJson::Missing, |v| ScopedJson::Context(v, paths)))
+    }
+
+    /// The sequence inserted by `Context::insert_iter` at a relative path,
+    /// with its full path, to iterate its items without collecting them
+    pub(crate) fn items_at(
+        &self,
+        relative_path: &[PathSeg],
+        block_contexts: &VecDeque<BlockContext<'_>>,
+    ) -> Option<(&LazyJson, Vec<String>)> {
+        match parse_json_visitor(relative_path, block_contexts, true) {
+            ResolvedPath::AbsolutePath(paths) => {
+                let lazy = self.lazy.get(&paths).filter(|lazy| lazy.is_items())?;
+                Some((lazy, paths))
+            }
+            _ => None,
+        }
+    }
+
+    /// Navigate the context with relative path and block scopes, `value` is
+    /// the data of the context when it's rendered from a `ContextValue`
     pub(crate) fn navigate<'rc>(
         &'rc self,
         relative_path: &[PathSeg],
         block_contexts: &VecDeque<BlockContext<'_>>,
         recursive_lookup: bool,
+        value: Option<&dyn ContextValue>,
     ) -> Result<ScopedJson<'rc>, RenderError> {
         // always use absolute at the moment until we get base_value lifetime issue fixed
         let resolved_visitor = parse_json_visitor(relative_path, block_contexts, true);
@@ -213,42 +324,30 @@ impl Context {
                     // ["children", "name"]
                     // ["name"]
                     while !paths.is_empty() {
-                        let mut ptr = Some(self.data());
-                        for p in &paths {
-                            ptr = match get_data(ptr, p) {
-                                Ok(p) => p,
-                                Err(err) => {
-                                    use crate::RenderErrorReason::InvalidJsonIndex;
-                                    if matches!(err.reason(), InvalidJsonIndex(_)) {
-                                        ptr = None;
-                                        break;
-                                    }
+                        let found = match self.get_absolute(value, paths.clone()) {
+                            Ok(v) => v,
+                            Err(err) => {
+                                use crate::RenderErrorReason::InvalidJsonIndex;
+                                if !matches!(err.reason(), InvalidJsonIndex(_)) {
                                     return Err(err);
                                 }
-                            };
-                        }
-
-                        match ptr {
-                            Some(v) => {
-                                return Ok(ScopedJson::Context(v, paths));
+                                ScopedJson::Missing
                             }
-                            None => {
-                                let paths_len = paths.len();
-                                if paths_len == 1 {
-                                    return Ok(ScopedJson::Missing);
-                                }
+                        };
 
-                                paths.remove(paths_len - 2);
-                            }
+                        if !found.is_missing() {
+                            return Ok(found);
+                        }
+                        let paths_len = paths.len();
+                        if paths_len == 1 {
+                            return Ok(ScopedJson::Missing);
                         }
+
+                        paths.remove(paths_len - 2);
                     }
                     Ok(ScopedJson::Missing)
                 } else {
-                    let mut ptr = Some(self.data());
-                    for p in &paths {
-                        ptr = get_data(ptr, p)?;
-                    }
-                    Ok(ptr.map_or_else(|| ScopedJson::Missing, |v| ScopedJson::Context(v, paths)))
+                    self.get_absolute(value, paths)
                 }
             }
             ResolvedPath::RelativePath(_paths) => {
@@ -274,6 +373,120 @@ impl Context {
         }
     }
 
+    /// Navigate the context like Mustache does: the first name of the path
+    /// is searched in the current scope, then in the scope of each enclosing
+    /// block and the root, and the rest of the path is resolved from where
+    /// it's found
+    pub(crate) fn navigate_context_stack<'rc>(
+        &'rc self,
+        relative_path: &[PathSeg],
+        block_contexts: &VecDeque<BlockContext<'_>>,
+        value: Option<&dyn ContextValue>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let all_named = !relative_path.is_empty()
+            && relative_path
+                .iter()
+                .all(|seg| matches!(seg, PathSeg::Named(_)));
+        let paths = match parse_json_visitor(relative_path, block_contexts, true) {
+            ResolvedPath::AbsolutePath(paths) if all_named => paths,
+            _ => return self.navigate(relative_path, block_contexts, false, value),
+        };
+        let Some(base_len) = paths.len().checked_sub(relative_path.len()) else {
+            return self.navigate(relative_path, block_contexts, false, value);
+        };
+
+        let current = &paths[..base_len];
+        let scopes = std::iter::once(current)
+            .chain(block_contexts.iter().map(|b| b.base_path().as_slice()))
+            .chain(std::iter::once(&[][..]));
+        for scope in scopes {
+            let mut path = scope.to_vec();
+            path.push(paths[base_len].clone());
+            if self
+                .get_absolute(value, path.clone())
+                .is_ok_and(|v| !v.is_missing())
+            {
+                path.extend_from_slice(&paths[base_len + 1..]);
+                return Ok(self
+                    .get_absolute(value, path)
+                    .unwrap_or(ScopedJson::Missing));
+            }
+        }
+        Ok(ScopedJson::Missing)
+    }
+
+    /// Insert a value computed by `f` the first time a template uses the
+    /// value at `path`, or a value in it
+    ///
+    /// `path` is a list of object keys separated by `.`, like `stats` or
+    /// `site.stats`. The value takes over the data at the same path, but it
+    /// isn't part of the values of its parents: `{{site}}` or
+    /// `{{#each site}}` don't see it. It's computed once and shared by the
+    /// clones of this context, also when the context is reused by several
+    /// renders. A panic of `f` unwinds out of the render that called it, the
+    /// value is `null` afterwards.
+    ///
+    /// ```
+    /// use handlebars::{Context, Handlebars};
+    /// use serde_json::json;
+    ///
+    /// let mut ctx = Context::wraps(json!({"admin": false})).unwrap();
+    /// ctx.insert_lazy("stats", || {
+    ///     // expensive aggregation, only run when rendered
+    ///     json!({"visits": 42})
+    /// });
+    ///
+    /// let hbs = Handlebars::new();
+    /// let t = "{{#if admin}}{{stats.visits}}{{/if}}";
+    /// assert_eq!(hbs.render_template_with_context(t, &ctx).unwrap(), "");
+    /// ```
+    pub fn insert_lazy<F>(&mut self, path: &str, f: F)
+    where
+        F: FnOnce() -> Json + Send + 'static,
+    {
+        let path = path.split('.').map(str::to_owned).collect();
+        self.lazy.insert(path, Arc::new(LazyJson::new(f)));
+    }
+
+    /// Insert a sequence at `path` whose items are pulled from the iterator
+    /// `f` returns, so `{{#each path}}` renders them one at a time instead of
+    /// holding all of them in the context
+    ///
+    /// `f` is called by each `{{#each}}` over the sequence. `@index`,
+    /// `@first` and `@last` work as with arrays, `@last` pulls one item
+    /// ahead. Other uses of the value, like `{{len rows}}` or `{{rows.[0]}}`,
+    /// collect all the items once, as for `insert_lazy`. An item failing to
+    /// serialize fails the render, streamed or collected.
+    ///
+    /// Only the items of an iterator are streamed. A `Serialize` value, like
+    /// a sequence serialized by serde, isn't: convert it with
+    /// `Context::wraps` or iterate its items in `f`.
+    ///
+    /// ```
+    /// use handlebars::{Context, Handlebars};
+    /// use serde_json::json;
+    ///
+    /// let mut ctx = Context::wraps(json!({"sep": ","})).unwrap();
+    /// ctx.insert_iter("rows", || (1..=3).map(|i| json!({"id": i, "square": i * i})));
+    ///
+    /// let hbs = Handlebars::new();
+    /// let t = "{{#each rows}}{{id}}{{../sep}}{{square}}{{#unless @last}};{{/unless}}{{/each}}";
+    /// assert_eq!(
+    ///     hbs.render_template_with_context(t, &ctx).unwrap(),
+    ///     "1,1;2,4;3,9"
+    /// );
+    /// ```
+    pub fn insert_iter<F, I>(&mut self, path: &str, f: F)
+    where
+        F: Fn() -> I + Send + Sync + 'static,
+        I: IntoIterator,
+        I::IntoIter: 'static,
+        I::Item: Serialize,
+    {
+        let path = path.split('.').map(str::to_owned).collect();
+        self.lazy.insert(path, Arc::new(LazyJson::from_items(f)));
+    }
+
     /// Return the Json data wrapped in context
     pub fn data(&self) -> &Json {
         &self.data
@@ -287,14 +500,21 @@ impl Context {
 
 impl From<Json> for Context {
     fn from(data: Json) -> Context {
-        Context { data }
+        Context {
+            data,
+            lazy: BTreeMap::new(),
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
     use crate::json::value;
-    use crate::{BlockParams, Path};
+    use crate::registry::Registry;
+    use crate::{BlockParams, Path, RenderErrorReason};
 
     use super::*;
 
@@ -303,7 +523,7 @@ mod test {
         path: &str,
     ) -> Result<ScopedJson<'rc>, RenderError> {
         let relative_path = Path::parse(path).unwrap();
-        ctx.navigate(relative_path.segs().unwrap(), &VecDeque::new(), false)
+        ctx.navigate(relative_path.segs().unwrap(), &VecDeque::new(), false, None)
     }
 
     #[derive(Serialize)]
@@ -320,6 +540,15 @@ mod test {
         titles: Vec<String>,
     }
 
+    // fails when serialized, so it's only rendered if it's looked up
+    struct Unused;
+
+    impl Serialize for Unused {
+        fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("serialized"))
+        }
+    }
+
     #[test]
     fn test_render() {
         let v = "hello";
@@ -506,7 +735,8 @@ mod test {
             ctx.navigate(
                 Path::parse("@root/b").unwrap().segs().unwrap(),
                 &blocks,
-                false
+                false,
+                None
             )
             .unwrap()
             .render(),
@@ -539,17 +769,205 @@ mod test {
             ctx.navigate(
                 Path::parse("z.[1]").unwrap().segs().unwrap(),
                 &blocks,
-                false
+                false,
+                None
             )
             .unwrap()
             .render(),
             "2".to_string()
         );
         assert_eq!(
-            ctx.navigate(Path::parse("t").unwrap().segs().unwrap(), &blocks, false)
+            ctx.navigate(
+                Path::parse("t").unwrap().segs().unwrap(),
+                &blocks,
+                false,
+                None
+            )
+            .unwrap()
+            .render(),
+            "good".to_string()
+        );
+    }
+
+    #[test]
+    fn test_render_value() {
+        #[derive(Serialize)]
+        struct Page {
+            title: &'static str,
+            people: Vec<Person>,
+            unused: Unused,
+        }
+
+        let page = Page {
+            title: "t",
+            people: vec![Person {
+                name: "n".to_owned(),
+                age: 27,
+                addr: Address {
+                    city: "c".to_owned(),
+                    country: "co".to_owned(),
+                },
+                titles: vec!["a".to_owned(), "b".to_owned()],
+            }],
+            unused: Unused,
+        };
+
+        let mut hbs = Registry::new();
+        hbs.register_template_string("addr", "{{city}}/{{country}}/{{x}}")
+            .unwrap();
+        hbs.register_template_string(
+            "t",
+            "{{#each people}}{{name}} {{titles.[1]}} {{../title}} {{@root.title}} \
+             {{#with addr}}{{> addr x=../age}}{{/with}}{{/each}}{{people.0.age}}{{nothing.at.all}}",
+        )
+        .unwrap();
+
+        let value = SerializeValue(&page);
+        assert_eq!(hbs.render_value("t", &value).unwrap(), "n b t t c/co/2727");
+        assert!(hbs.render("t", &page).is_err());
+
+        hbs.register_template_string("this", "{{this}}").unwrap();
+        assert!(hbs.render_value("this", &value).is_err());
+
+        let json = json!({"people": [{"name": "j", "titles": [], "addr": {}}]});
+        assert_eq!(
+            hbs.render_value("t", &json).unwrap(),
+            hbs.render("t", &json).unwrap()
+        );
+    }
+
+    #[derive(Serialize)]
+    struct Profile {
+        name: &'static str,
+        tags: Vec<&'static str>,
+        unused: Unused,
+    }
+
+    #[test]
+    fn test_render_value_partial() {
+        let profile = Profile {
+            name: "n",
+            tags: vec!["a", "b"],
+            unused: Unused,
+        };
+
+        let mut hbs = Registry::new();
+        hbs.register_template_string("p", "{{name}}/{{x}}/{{len tags}}/{{@root.name}}")
+            .unwrap();
+        hbs.register_template_string("hash", "{{> p x=1}} {{> p name=\"o\"}}")
+            .unwrap();
+        hbs.register_template_string("inline", "{{#*inline \"q\"}}{{> p x=2}}{{/inline}}{{> q}}")
+            .unwrap();
+
+        let value = SerializeValue(&profile);
+        assert_eq!(hbs.render_value("hash", &value).unwrap(), "n/1/2/n o//2/n");
+        assert_eq!(hbs.render_value("inline", &value).unwrap(), "n/2/2/n");
+    }
+
+    #[test]
+    fn test_render_value_with() {
+        let profile = Profile {
+            name: "n",
+            tags: vec!["a", "b"],
+            unused: Unused,
+        };
+
+        let mut hbs = Registry::new();
+        hbs.register_template_string(
+            "with",
+            "{{#with tags}}{{this.[1]}}{{../name}}{{@root.name}}{{/with}}",
+        )
+        .unwrap();
+        hbs.register_template_string("with_this", "{{#with this}}{{name}}{{/with}}")
+            .unwrap();
+
+        let value = SerializeValue(&profile);
+        assert_eq!(hbs.render_value("with", &value).unwrap(), "bnn");
+        // the root given to a helper is converted whole
+        let err = hbs.render_value("with_this", &value).unwrap_err();
+        assert!(matches!(err.reason(), RenderErrorReason::SerdeError(_)));
+    }
+
+    fn lazy_context(calls: &Arc<AtomicUsize>) -> Context {
+        let mut ctx = Context::wraps(json!({"show": false, "stats": "data"})).unwrap();
+        let counter = calls.clone();
+        ctx.insert_lazy("stats", move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            json!({"visits": 2, "pages": ["a", "b"]})
+        });
+        ctx.insert_lazy("site.name", || json!("s"));
+        ctx
+    }
+
+    #[test]
+    fn test_lazy_not_used() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let ctx = lazy_context(&calls);
+
+        let hbs = Registry::new();
+        let out = hbs
+            .render_template_with_context("{{#if show}}{{stats.visits}}{{/if}}", &ctx)
+            .unwrap();
+        assert_eq!(out, "");
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_lazy_computed_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let ctx = lazy_context(&calls);
+
+        let hbs = Registry::new();
+        let render = |t| hbs.render_template_with_context(t, &ctx).unwrap();
+        assert_eq!(
+            render(
+                "{{stats.visits}} {{#with stats}}{{#each pages}}{{this}}{{../visits}}{{/each}}{{/with}}"
+            ),
+            "2 a2b2"
+        );
+        assert_eq!(render("{{site.name}} {{len stats.pages}}"), "s 2");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_lazy_navigation() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let ctx = lazy_context(&calls);
+
+        assert_eq!(
+            navigate_from_root(&ctx, "stats.pages.[1]")
                 .unwrap()
                 .render(),
-            "good".to_string()
+            "b"
+        );
+        assert!(matches!(
+            navigate_from_root(&ctx, "site.name").unwrap(),
+            ScopedJson::Context(v, p) if v == "s" && p == ["site", "name"]
+        ));
+        assert!(navigate_from_root(&ctx, "site.other").unwrap().is_missing());
+        // the value replaced by the lazy one is hidden
+        assert!(
+            navigate_from_root(&ctx, "stats")
+                .unwrap()
+                .as_json()
+                .is_object()
+        );
+    }
+
+    #[test]
+    fn test_lazy_panic() {
+        let mut ctx = Context::null();
+        ctx.insert_lazy("broken", || panic!("no value"));
+
+        let hbs = Registry::new();
+        let render = || hbs.render_template_with_context("[{{broken}}]", &ctx);
+        let panicked = panic::catch_unwind(AssertUnwindSafe(render));
+        assert!(panicked.is_err());
+
+        // the closure is called once, the value is null afterwards
+        assert_eq!(render().unwrap(), "[]");
+        assert!(
+            matches!(ctx.lazy.values().next(), Some(lazy) if lazy.get_computed() == Some(&Json::Null))
         );
     }
 }
@@ -0,0 +1,521 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::template::TemplateElement::{Comment, HelperBlock, RawString};
+use crate::template::{SourceSpan, Template, TemplateElement, TemplateVisitor, walk_element};
+
+/// Coverage of templates, collected over renders and exported in the lcov
+/// format
+///
+/// Set a collector with `Registry::set_coverage`, and every render of the
+/// registry records the elements it renders, and the blocks and `{{else}}`
+/// blocks of block helpers it takes. The templates of the registry are in the
+/// report from the start, with no hit until they are rendered. Templates
+/// without name, like the ones of `Registry::render_template`, are not
+/// recorded. Clones of a collector share its data, so it can be kept to
+/// export the coverage once the renders are done.
+///
+/// ```
+/// use handlebars::{Coverage, Handlebars};
+///
+/// let coverage = Coverage::new();
+/// let mut hbs = Handlebars::new();
+/// hbs.set_coverage(Some(coverage.clone()));
+/// hbs.register_template_string("t", "{{#if admin}}\nadmin\n{{else}}\nuser\n{{/if}}\n")
+///     .unwrap();
+///
+/// hbs.render("t", &serde_json::json!({"admin": false})).unwrap();
+///
+/// let lcov = coverage.to_lcov();
+/// assert!(lcov.contains("SF:t\n"));
+/// // the `{{#if}}` block is not covered, its `{{else}}` block is
+/// assert!(lcov.contains("BRDA:1,0,0,0\nBRDA:3,0,1,1\n"));
+/// assert!(lcov.contains("DA:2,0\nDA:3,1\nDA:4,1\n"));
+/// ```
+///
+/// Lines of the report are the lines of the template source, and an element
+/// counts on all the lines of a text and on the first line of an
+/// expression. Sources are named after the templates, `to_lcov_with_paths`
+/// maps them to the paths of the template files.
+#[derive(Clone, Debug, Default)]
+pub struct Coverage {
+    data: Arc<Mutex<CoverageData>>,
+}
+
+// coverage by template name
+type CoverageData = BTreeMap<String, SourceCoverage>;
+
+// a block (0) or inverse block (1) of the block helper at a byte offset
+type Branch = (usize, usize);
+
+#[derive(Clone, Debug, Default)]
+struct SourceCoverage {
+    // rendered elements, by byte offset in the source
+    elements: BTreeMap<usize, ElementCoverage>,
+    // rendered blocks and inverse blocks of block helpers
+    branches: BTreeMap<Branch, BranchCoverage>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ElementCoverage {
+    first_line: usize,
+    last_line: usize,
+    hits: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BranchCoverage {
+    line: usize,
+    hits: u64,
+}
+
+impl SourceCoverage {
+    fn merge(&mut self, other: SourceCoverage) {
+        for (offset, e) in other.elements {
+            self.elements
+                .entry(offset)
+                .and_modify(|c| c.hits += e.hits)
+                .or_insert(e);
+        }
+        for (key, b) in other.branches {
+            self.branches
+                .entry(key)
+                .and_modify(|c| c.hits += b.hits)
+                .or_insert(b);
+        }
+    }
+
+    fn write_lcov(&self, out: &mut String, path: &str) -> fmt::Result {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{path}")?;
+
+        let mut blocks = BTreeMap::new();
+        for (&(offset, branch), b) in &self.branches {
+            let block_count = blocks.len();
+            let block = *blocks.entry(offset).or_insert(block_count);
+            match self.elements.get(&offset) {
+                Some(e) if e.hits > 0 => {
+                    writeln!(out, "BRDA:{},{block},{branch},{}", b.line, b.hits)?
+                }
+                _ => writeln!(out, "BRDA:{},{block},{branch},-", b.line)?,
+            }
+        }
+        writeln!(out, "BRF:{}", self.branches.len())?;
+        writeln!(
+            out,
+            "BRH:{}",
+            self.branches.values().filter(|b| b.hits > 0).count()
+        )?;
+
+        let mut lines: BTreeMap<usize, u64> = BTreeMap::new();
+        for e in self.elements.values() {
+            for line in e.first_line..=e.last_line {
+                let hits = lines.entry(line).or_default();
+                *hits = (*hits).max(e.hits);
+            }
+        }
+        for (line, hits) in &lines {
+            writeln!(out, "DA:{line},{hits}")?;
+        }
+        writeln!(out, "LF:{}", lines.len())?;
+        writeln!(out, "LH:{}", lines.values().filter(|h| **h > 0).count())?;
+        writeln!(out, "end_of_record")
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    fn data(&self) -> MutexGuard<'_, CoverageData> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Clear the collected hits, the templates stay in the report with no
+    /// hit
+    pub fn reset(&self) {
+        for source in self.data().values_mut() {
+            for e in source.elements.values_mut() {
+                e.hits = 0;
+            }
+            for b in source.branches.values_mut() {
+                b.hits = 0;
+            }
+        }
+    }
+
+    /// Export the coverage in the lcov format, with the template names as
+    /// source files
+    pub fn to_lcov(&self) -> String {
+        self.to_lcov_with_paths(ToOwned::to_owned)
+    }
+
+    /// Export the coverage in the lcov format, with the source file of each
+    /// template given by `path`
+    ///
+    /// For templates registered with `Registry::register_templates_directory`
+    /// for instance, `|name| format!("templates/{name}.hbs")`.
+    pub fn to_lcov_with_paths(&self, path: impl Fn(&str) -> String) -> String {
+        let mut lcov = String::new();
+        for (name, source) in self.data().iter() {
+            let _ = source.write_lcov(&mut lcov, &path(name));
+        }
+        lcov
+    }
+
+    /// Add a template of the registry to the report, with no hit
+    pub(crate) fn declare(&self, name: &str, t: &Template) {
+        let mut recorder = CoverageRecorder::new(Some(name));
+        recorder.template(t);
+        self.merge(recorder);
+    }
+
+    pub(crate) fn merge(&self, recorder: CoverageRecorder) {
+        let mut data = self.data();
+        for (name, source) in recorder.data {
+            data.entry(name).or_default().merge(source);
+        }
+    }
+}
+
+// the span of an element, the nodes of `else if` chains are not in the spans
+// of their template
+fn element_span(t: &Template, idx: usize) -> Option<SourceSpan> {
+    t.spans
+        .get(idx)
+        .copied()
+        .or_else(|| match t.elements.get(idx) {
+            Some(HelperBlock(h)) if h.chain => Some(h.spans.element),
+            _ => None,
+        })
+}
+
+fn element_lines(e: &TemplateElement, span: &SourceSpan) -> (usize, usize) {
+    match e {
+        // a text ending with a newline doesn't cover the next line
+        RawString(_) if span.end_pos.0 > span.start_pos.0 && span.end_pos.1 <= 1 => {
+            (span.start_pos.0, span.end_pos.0 - 1)
+        }
+        RawString(_) => (span.start_pos.0, span.end_pos.0),
+        _ => (span.start_pos.0, span.start_pos.0),
+    }
+}
+
+/// Coverage of a render, merged into the `Coverage` of the registry once the
+/// render is done
+pub(crate) struct CoverageRecorder {
+    // name of the rendered template, if it has one
+    root: Option<String>,
+    data: CoverageData,
+    // template and branch of the templates of the render, by address
+    templates: HashMap<*const Template, (String, Option<Branch>)>,
+}
+
+impl CoverageRecorder {
+    pub(crate) fn new(root: Option<&str>) -> CoverageRecorder {
+        CoverageRecorder {
+            root: root.map(ToOwned::to_owned),
+            data: CoverageData::new(),
+            templates: HashMap::new(),
+        }
+    }
+
+    /// Record the render of a template, a whole template or the block of an
+    /// element
+    pub(crate) fn template(&mut self, t: &Template) {
+        match self.templates.get(&(t as *const Template)) {
+            Some((name, Some(branch))) => {
+                let source = self.data.get_mut(name).expect("declared template");
+                if let Some(b) = source.branches.get_mut(branch) {
+                    b.hits += 1;
+                }
+            }
+            Some((_, None)) => {}
+            // first render of a template in this render, the blocks of the
+            // templates are always rendered after them. Templates without
+            // name are not recorded, their offsets would mix in one report.
+            None => {
+                let Some(name) = t.name.clone().or_else(|| self.root.clone()) else {
+                    return;
+                };
+                let mut declare = Declare {
+                    name,
+                    recorder: self,
+                };
+                declare.visit_template(t);
+            }
+        }
+    }
+
+    /// Record the render of an element of a template
+    pub(crate) fn element(&mut self, t: &Template, idx: usize) {
+        let Some((name, _)) = self.templates.get(&(t as *const Template)) else {
+            return;
+        };
+        let Some(span) = element_span(t, idx) else {
+            return;
+        };
+        let source = self.data.get_mut(name).expect("declared template");
+        if let Some(e) = source.elements.get_mut(&span.start) {
+            e.hits += 1;
+        }
+    }
+}
+
+// declare the elements and branches of a template with no hit
+struct Declare<'a> {
+    name: String,
+    recorder: &'a mut CoverageRecorder,
+}
+
+impl Declare<'_> {
+    fn source(&mut self) -> &mut SourceCoverage {
+        self.recorder.data.entry(self.name.clone()).or_default()
+    }
+}
+
+impl TemplateVisitor for Declare<'_> {
+    fn visit_template(&mut self, t: &Template) {
+        self.recorder
+            .templates
+            .entry(t as *const Template)
+            .or_insert_with(|| (self.name.clone(), None));
+
+        for (idx, e) in t.elements.iter().enumerate() {
+            if matches!(e, Comment(_)) {
+                continue;
+            }
+            if let Some(span) = element_span(t, idx) {
+                let (first_line, last_line) = element_lines(e, &span);
+                self.source()
+                    .elements
+                    .entry(span.start)
+                    .or_insert(ElementCoverage {
+                        first_line,
+                        last_line,
+                        hits: 0,
+                    });
+
+                if let HelperBlock(h) = e {
+                    let open_line = h.spans.open_tag.start_pos.0;
+                    let inverse_line = h.spans.inverse_tag.map_or(open_line, |s| s.start_pos.0);
+                    let branches = [(&h.template, open_line), (&h.inverse, inverse_line)];
+                    for (branch, (block, line)) in branches.into_iter().enumerate() {
+                        if let Some(block) = block {
+                            let key = (span.start, branch);
+                            self.recorder
+                                .templates
+                                .insert(block as *const Template, (self.name.clone(), Some(key)));
+                            self.source()
+                                .branches
+                                .entry(key)
+                                .or_insert(BranchCoverage { line, hits: 0 });
+                        }
+                    }
+                }
+            }
+            walk_element(self, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::Coverage;
+    use crate::RenderErrorReason;
+    use crate::registry::Registry;
+
+    fn records(lcov: &str) -> Vec<Vec<&str>> {
+        lcov.split_terminator("end_of_record\n")
+            .map(|r| r.lines().collect())
+            .collect()
+    }
+
+    fn list_registry(coverage: &Coverage) -> Registry<'static> {
+        let mut hbs = Registry::new();
+        hbs.set_coverage(Some(coverage.clone()));
+        hbs.register_template_string(
+            "list",
+            "<ul>\n{{#each items}}\n<li>{{> item}}</li>\n{{else}}\nempty\n{{/each}}\n</ul>\n",
+        )
+        .unwrap();
+        hbs.register_template_string("item", "{{#if done}}x{{else if skip}}-{{/if}}{{name}}")
+            .unwrap();
+        hbs.register_template_string("unused", "never").unwrap();
+        hbs
+    }
+
+    #[test]
+    fn test_coverage() {
+        let coverage = Coverage::new();
+        let hbs = list_registry(&coverage);
+
+        hbs.render(
+            "list",
+            &json!({"items": [{"name": "a", "done": true}, {"name": "b"}]}),
+        )
+        .unwrap();
+
+        assert_eq!(
+            records(&coverage.to_lcov()),
+            [
+                vec![
+                    "TN:",
+                    "SF:item",
+                    "BRDA:1,0,0,1",
+                    "BRDA:1,0,1,1",
+                    "BRDA:1,1,0,0",
+                    "BRF:3",
+                    "BRH:2",
+                    "DA:1,2",
+                    "LF:1",
+                    "LH:1",
+                ],
+                vec![
+                    "TN:",
+                    "SF:list",
+                    "BRDA:2,0,0,2",
+                    "BRDA:4,0,1,0",
+                    "BRF:2",
+                    "BRH:1",
+                    "DA:1,1",
+                    "DA:2,2",
+                    "DA:3,2",
+                    "DA:4,0",
+                    "DA:5,0",
+                    "DA:6,1",
+                    "DA:7,1",
+                    "LF:7",
+                    "LH:5",
+                ],
+                vec![
+                    "TN:",
+                    "SF:unused",
+                    "BRF:0",
+                    "BRH:0",
+                    "DA:1,0",
+                    "LF:1",
+                    "LH:0",
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_declared_templates() {
+        let coverage = Coverage::new();
+        let mut hbs = Registry::new();
+        hbs.register_template_string("before", "a").unwrap();
+        hbs.set_coverage(Some(coverage.clone()));
+        hbs.register_template_string("after", "{{#if x}}b{{/if}}")
+            .unwrap();
+
+        assert_eq!(
+            records(&coverage.to_lcov()),
+            [
+                vec![
+                    "TN:",
+                    "SF:after",
+                    "BRDA:1,0,0,-",
+                    "BRF:1",
+                    "BRH:0",
+                    "DA:1,0",
+                    "LF:1",
+                    "LH:0",
+                ],
+                vec![
+                    "TN:",
+                    "SF:before",
+                    "BRF:0",
+                    "BRH:0",
+                    "DA:1,0",
+                    "LF:1",
+                    "LH:0"
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_template() {
+        let coverage = Coverage::new();
+        let mut hbs = Registry::new();
+        hbs.set_coverage(Some(coverage.clone()));
+        hbs.register_template_string("p", "{{name}}").unwrap();
+
+        hbs.render_template("a\n{{> p}}", &json!({"name": "x"}))
+            .unwrap();
+        hbs.render_template("{{#if x}}b{{/if}}", &json!({}))
+            .unwrap();
+        // only the named partial is recorded
+        assert_eq!(
+            records(&coverage.to_lcov()),
+            [vec![
+                "TN:", "SF:p", "BRF:0", "BRH:0", "DA:1,1", "LF:1", "LH:1"
+            ]]
+        );
+    }
+
+    #[test]
+    fn test_hits_add_up() {
+        let coverage = Coverage::new();
+        let hbs = list_registry(&coverage);
+
+        hbs.render("list", &json!({"items": [{"name": "a"}, {"name": "b"}]}))
+            .unwrap();
+        hbs.render("list", &json!({"items": []})).unwrap();
+        let lcov = coverage.to_lcov_with_paths(|name| format!("templates/{name}.hbs"));
+        assert!(lcov.contains("SF:templates/list.hbs\nBRDA:2,0,0,2\nBRDA:4,0,1,1\n"));
+        assert!(lcov.contains("DA:4,1\nDA:5,1\n"));
+    }
+
+    #[test]
+    fn test_reset() {
+        let coverage = Coverage::new();
+        let hbs = list_registry(&coverage);
+
+        let declared = coverage.to_lcov();
+        hbs.render("list", &json!({"items": []})).unwrap();
+        assert_ne!(coverage.to_lcov(), declared);
+        coverage.reset();
+        assert_eq!(coverage.to_lcov(), declared);
+    }
+
+    #[test]
+    fn test_failed_render() {
+        let coverage = Coverage::new();
+        let mut hbs = Registry::new();
+        hbs.set_coverage(Some(coverage.clone()));
+        hbs.register_template_string("t", "a\n{{#if x}}\n{{> missing}}\n{{/if}}\nb\n")
+            .unwrap();
+
+        let err = hbs.render("t", &json!({"x": true})).unwrap_err();
+        assert!(matches!(
+            err.reason(),
+            RenderErrorReason::PartialNotFound(name) if name == "missing"
+        ));
+
+        // covered up to the error
+        assert_eq!(
+            records(&coverage.to_lcov()),
+            [vec![
+                "TN:",
+                "SF:t",
+                "BRDA:2,0,0,1",
+                "BRF:1",
+                "BRH:1",
+                "DA:1,1",
+                "DA:2,1",
+                "DA:3,1",
+                "DA:4,0",
+                "DA:5,0",
+                "LF:5",
+                "LH:3",
+            ]]
+        );
+    }
+}
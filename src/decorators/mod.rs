@@ -4,6 +4,7 @@ use crate::registry::Registry;
 use crate::render::{Decorator, RenderContext};
 
 pub use self::inline::INLINE_DECORATOR;
+pub use self::slot::SLOT_DECORATOR;
 
 pub type DecoratorResult = Result<(), RenderError>;
 
@@ -88,6 +89,7 @@ impl<
 }
 
 mod inline;
+mod slot;
 
 #[cfg(test)]
 mod test {
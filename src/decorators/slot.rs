@@ -0,0 +1,104 @@
+use crate::RenderErrorReason;
+use crate::context::Context;
+use crate::decorators::{DecoratorDef, DecoratorResult};
+use crate::error::RenderError;
+use crate::registry::Registry;
+use crate::render::{Decorator, RenderContext, Renderable};
+
+/// `{{#*slot "name"}}...{{/slot}}` in a partial block, rendered by the partial
+/// with `{{> @slot.name}}`
+#[derive(Clone, Copy)]
+pub struct SlotDecorator;
+
+fn get_name<'reg: 'rc, 'rc>(d: &Decorator<'rc>) -> Result<String, RenderError> {
+    d.param(0)
+        .ok_or_else(|| RenderErrorReason::ParamNotFoundForIndex("slot", 0).into())
+        .and_then(|v| {
+            v.value()
+                .as_str()
+                .map(std::borrow::ToOwned::to_owned)
+                .ok_or_else(|| RenderErrorReason::InvalidParamType("String").into())
+        })
+}
+
+impl DecoratorDef for SlotDecorator {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        d: &Decorator<'rc>,
+        r: &'reg Registry<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> DecoratorResult {
+        let name = get_name(d)?;
+
+        let template = d
+            .template()
+            .ok_or(RenderErrorReason::BlockContentRequired)?;
+
+        // like `@partial-block`, the slot is rendered in the context of the
+        // caller
+        let mut out = rc.buffer();
+        template.render(r, ctx, rc, &mut out)?;
+        rc.add_slot(name, out.into_string()?);
+
+        Ok(())
+    }
+}
+
+pub static SLOT_DECORATOR: SlotDecorator = SlotDecorator;
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::registry::Registry;
+    use crate::testing::TestHandlebars;
+
+    #[test]
+    fn test_slots() {
+        let mut hbs = Registry::new();
+        hbs.register(
+            "card",
+            "<div>{{#> @slot.header}}<h2>untitled</h2>{{/@slot.header}}\
+             <p>{{> @partial-block}}</p>{{> @slot.footer}}</div>",
+        );
+        hbs.register(
+            "page",
+            "{{#> card}}{{#*slot \"header\"}}<h1>{{title}}</h1>{{/slot}}\
+             {{body}}{{#*slot \"footer\"}}<small>{{@root.year}}</small>{{/slot}}{{/card}}",
+        );
+        hbs.register("default", "{{#> card}}{{body}}{{/card}}");
+
+        let data = json!({"title": "Hello", "body": "text", "year": 2024});
+        hbs.assert_render(
+            "page",
+            &data,
+            "<div><h1>Hello</h1><p>text</p><small>2024</small></div>",
+        );
+        hbs.assert_render("default", &data, "<div><h2>untitled</h2><p>text</p></div>");
+    }
+
+    #[test]
+    fn test_nested_slots() {
+        let mut hbs = Registry::new();
+        hbs.register("box", "[{{> @slot.title}}:{{> @partial-block}}]");
+        hbs.register(
+            "card",
+            "{{#> box}}{{#*slot \"title\"}}card {{> @slot.header}}{{/slot}}\
+             {{> @partial-block}}{{/box}}",
+        );
+        hbs.register(
+            "page",
+            "{{#> card}}{{#*slot \"header\"}}{{name}}{{/slot}}body{{/card}}",
+        );
+
+        hbs.assert_render("page", &json!({"name": "n"}), "[card n:body]");
+    }
+
+    #[test]
+    fn test_slot_outside_partial_block() {
+        let mut hbs = Registry::new();
+        hbs.register("t", "a{{#*slot \"x\"}}b{{/slot}}{{> @slot.x}}c");
+        hbs.assert_render("t", &(), "ac");
+    }
+}
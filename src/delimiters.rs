@@ -0,0 +1,547 @@
+//! Custom tag delimiters and the Mustache set delimiters tag
+//!
+//! The pest grammar only knows about `{{` and `}}`. A template written with
+//! other delimiters, or switching them with `{{=<% %>=}}`, is translated to
+//! the standard syntax before parsing:
+//!
+//! * tags are rewritten with `{{` and `}}`,
+//! * `{` and `\` in text are replaced by placeholders, so literal braces and
+//!   backslashes are never taken for a tag or an escape, and are restored in
+//!   the compiled template,
+//! * set delimiters tags are replaced by an empty comment, so they are
+//!   stripped from standalone lines like other comments.
+//!
+//! Line and column numbers of the translated source are mapped back to the
+//! original one, for both the template mapping and errors.
+
+use crate::error::{TemplateError, TemplateErrorReason};
+use crate::support::str::{line_col, offset_of};
+use crate::template::{
+    DecoratorTemplate, HelperTemplate, SourceSpan, Template, TemplateVisitorMut,
+    walk_decorator_mut, walk_helper_mut, walk_template_mut,
+};
+
+const DEFAULT_OPEN: &str = "{{";
+const DEFAULT_CLOSE: &str = "}}";
+
+/// Open and close delimiters of template tags
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Delimiters {
+    pub(crate) open: String,
+    pub(crate) close: String,
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Delimiters {
+            open: DEFAULT_OPEN.to_owned(),
+            close: DEFAULT_CLOSE.to_owned(),
+        }
+    }
+}
+
+impl Delimiters {
+    /// Delimiters can't be empty, contain whitespaces or `=`, like in
+    /// Mustache.
+    pub(crate) fn new(open: &str, close: &str) -> Result<Delimiters, TemplateError> {
+        let valid = |d: &str| !d.is_empty() && !d.contains(|c: char| c.is_whitespace() || c == '=');
+        if valid(open) && valid(close) {
+            Ok(Delimiters {
+                open: open.to_owned(),
+                close: close.to_owned(),
+            })
+        } else {
+            Err(TemplateError::of(TemplateErrorReason::InvalidDelimiters(
+                format!("{open} {close}"),
+            )))
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        self.open == DEFAULT_OPEN && self.close == DEFAULT_CLOSE
+    }
+}
+
+/// A template source rewritten with the standard delimiters
+pub(crate) struct Translation {
+    pub(crate) source: String,
+    // (placeholder, original) characters of the text
+    placeholders: [(char, char); 2],
+    // char offsets in the translated and original sources where each
+    // piece of the translation starts
+    offsets: Vec<(usize, usize)>,
+    // char offsets of the line starts
+    source_lines: Vec<usize>,
+    original_lines: Vec<usize>,
+    source_chars: usize,
+    original_chars: usize,
+}
+
+/// Translate `source` to the standard syntax
+///
+/// Returns `None` when the template can be parsed as it is: it uses the
+/// standard delimiters and has no set delimiters tag.
+pub(crate) fn translate(
+    source: &str,
+    delimiters: &Delimiters,
+) -> Result<Option<Translation>, TemplateError> {
+    if delimiters.is_default() && !source.contains("{{=") {
+        return Ok(None);
+    }
+
+    let mut unused = ('\u{e000}'..='\u{f8ff}').filter(|c| !source.contains(*c));
+    let (Some(brace), Some(backslash)) = (unused.next(), unused.next()) else {
+        return Err(TemplateError::of(TemplateErrorReason::InvalidDelimiters(
+            "no placeholder character available".to_owned(),
+        )));
+    };
+
+    let mut t = Translator {
+        input: source,
+        delimiters: delimiters.clone(),
+        out: String::with_capacity(source.len()),
+        out_chars: 0,
+        in_chars: 0,
+        offsets: Vec::new(),
+        placeholders: [(brace, '{'), (backslash, '\\')],
+    };
+    t.translate()?;
+
+    Ok(Some(Translation {
+        source_lines: line_starts(&t.out),
+        original_lines: line_starts(source),
+        source_chars: t.out_chars,
+        original_chars: t.in_chars,
+        source: t.out,
+        placeholders: t.placeholders,
+        offsets: t.offsets,
+    }))
+}
+
+impl Translation {
+    /// Restore the text and positions of a template compiled from the
+    /// translated source
+    pub(crate) fn restore(&self, template: &mut Template, original: &str) {
+        Restore(self, original).visit_template_mut(template);
+    }
+
+    /// Point an error on the translated source to the original one
+    pub(crate) fn restore_error(&self, e: TemplateError, original: &str) -> TemplateError {
+        match e.pos() {
+            Some((line, col)) => {
+                let (line, col) = self.original_pos(line, col);
+                e.at(original, line, col)
+            }
+            None => e,
+        }
+    }
+
+    pub(crate) fn original_pos(&self, line: usize, col: usize) -> (usize, usize) {
+        self.map_pos(line, col, false)
+    }
+
+    /// Point a span of the translated source to the original one
+    pub(crate) fn original_span(&self, span: SourceSpan, original: &str) -> SourceSpan {
+        let start_pos = self.map_pos(span.start_pos.0, span.start_pos.1, false);
+        let end_pos = self.map_pos(span.end_pos.0, span.end_pos.1, true);
+        SourceSpan::new(
+            offset_of(original, start_pos.0, start_pos.1),
+            offset_of(original, end_pos.0, end_pos.1),
+            start_pos,
+            end_pos,
+        )
+    }
+
+    // the end of a span is mapped to the end of the piece it closes, so
+    // rewritten tags are covered entirely
+    fn map_pos(&self, line: usize, col: usize, end: bool) -> (usize, usize) {
+        let line_start = self
+            .source_lines
+            .get(line.saturating_sub(1))
+            .or(self.source_lines.last())
+            .copied()
+            .unwrap_or_default();
+        let offset = line_start + col.saturating_sub(1);
+
+        let idx = self
+            .offsets
+            .partition_point(|&(t, _)| t < offset || (!end && t == offset))
+            .saturating_sub(1);
+        let (t, o) = self.offsets.get(idx).copied().unwrap_or_default();
+        let next = self.offsets.get(idx + 1).copied();
+        let (next_t, next_o) = next.unwrap_or((self.source_chars, self.original_chars));
+        let original = if end && offset == next_t {
+            next_o
+        } else {
+            let original = o + (offset - t.min(offset));
+            // a rewritten tag may be longer than the original one
+            match next {
+                Some(_) => original.min(next_o.saturating_sub(1).max(o)),
+                None => original,
+            }
+        };
+
+        let line = self.original_lines.partition_point(|&s| s <= original);
+        (line, original - self.original_lines[line - 1] + 1)
+    }
+}
+
+struct Restore<'a>(&'a Translation, &'a str);
+
+impl Restore<'_> {
+    fn span(&self, span: &mut SourceSpan) {
+        *span = self.0.original_span(*span, self.1);
+    }
+}
+
+impl TemplateVisitorMut for Restore<'_> {
+    fn visit_template_mut(&mut self, t: &mut Template) {
+        for m in &mut t.mapping {
+            (m.0, m.1) = self.0.original_pos(m.0, m.1);
+        }
+        for span in &mut t.spans {
+            self.span(span);
+        }
+        walk_template_mut(self, t);
+    }
+
+    fn visit_helper_mut(&mut self, h: &mut HelperTemplate) {
+        for span in h.spans.iter_mut() {
+            self.span(span);
+        }
+        walk_helper_mut(self, h);
+    }
+
+    fn visit_decorator_mut(&mut self, d: &mut DecoratorTemplate) {
+        for span in d.spans.iter_mut() {
+            self.span(span);
+        }
+        walk_decorator_mut(self, d);
+    }
+
+    fn visit_raw_string_mut(&mut self, s: &mut String) {
+        for (placeholder, c) in self.0.placeholders {
+            if s.contains(placeholder) {
+                *s = s.replace(placeholder, c.encode_utf8(&mut [0; 4]));
+            }
+        }
+    }
+}
+
+fn line_starts(s: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(
+            s.chars()
+                .enumerate()
+                .filter(|&(_, c)| c == '\n')
+                .map(|(i, _)| i + 1),
+        )
+        .collect()
+}
+
+/// Position of the first `{{` in `s`
+///
+/// Tags escaped with a single backslash are skipped, like the grammar does.
+pub(crate) fn find_default_tag(s: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(idx) = s[from..].find(DEFAULT_OPEN).map(|i| i + from) {
+        let backslashes = s[..idx].len() - s[..idx].trim_end_matches('\\').len();
+        if backslashes != 1 {
+            return Some(idx);
+        }
+        from = idx + DEFAULT_OPEN.len();
+        if s[from..].starts_with(DEFAULT_OPEN) {
+            from += DEFAULT_OPEN.len();
+        }
+    }
+    None
+}
+
+/// Length of the tag content after the open delimiter, up to the `close`
+/// delimiter
+pub(crate) fn tag_len(after: &str, close: &str) -> Option<usize> {
+    let tilde = usize::from(after.starts_with('~'));
+    let body = &after[tilde..];
+    let suffix = if body.starts_with("!--") {
+        "--"
+    } else if body.starts_with('{') {
+        "}"
+    } else {
+        ""
+    };
+    // skip the prefix so `{{!--}}` or `<%{}%>` don't match themselves
+    let skip = tilde + suffix.len();
+    let rest = after.get(skip..)?;
+    [format!("{suffix}{close}"), format!("{suffix}~{close}")]
+        .iter()
+        .filter_map(|end| rest.find(end).map(|i| i + end.len() - close.len()))
+        .min()
+        .map(|i| i + skip)
+}
+
+struct Translator<'a> {
+    input: &'a str,
+    delimiters: Delimiters,
+    out: String,
+    out_chars: usize,
+    in_chars: usize,
+    offsets: Vec<(usize, usize)>,
+    placeholders: [(char, char); 2],
+}
+
+impl Translator<'_> {
+    fn translate(&mut self) -> Result<(), TemplateError> {
+        let mut pos = 0;
+        while pos < self.input.len() {
+            let rest = &self.input[pos..];
+            match self.find_tag(rest) {
+                Some(start) => {
+                    self.text(&rest[..start]);
+                    pos = self.tag(pos + start)?;
+                }
+                None => {
+                    self.text(rest);
+                    pos = self.input.len();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn find_tag(&self, s: &str) -> Option<usize> {
+        if self.delimiters.is_default() {
+            find_default_tag(s)
+        } else {
+            s.find(&self.delimiters.open)
+        }
+    }
+
+    /// Translate the tag at `pos`, and return the position after it
+    fn tag(&mut self, pos: usize) -> Result<usize, TemplateError> {
+        let input = self.input;
+        let Delimiters { open, close } = self.delimiters.clone();
+        let after = &input[pos + open.len()..];
+
+        if let Some(content) = after.strip_prefix('=') {
+            let end = format!("={close}");
+            let Some(len) = content.find(&end) else {
+                return Err(self.unclosed(pos, &end));
+            };
+            let mut parts = content[..len].split_whitespace();
+            let delimiters = match (parts.next(), parts.next(), parts.next()) {
+                (Some(o), Some(c), None) => Delimiters::new(o, c),
+                _ => Err(TemplateError::of(TemplateErrorReason::InvalidDelimiters(
+                    content[..len].trim().to_owned(),
+                ))),
+            }
+            .map_err(|e| self.error_at(e, pos))?;
+
+            let tag = &input[pos..pos + open.len() + 1 + len + end.len()];
+            self.emit("{{!}}", tag);
+            self.delimiters = delimiters;
+            return Ok(pos + tag.len());
+        }
+
+        if after.starts_with(open.as_str()) {
+            return self.raw_block(pos);
+        }
+
+        let Some(len) = tag_len(after, &close) else {
+            if self.delimiters.is_default() {
+                // copied as is, the grammar reports malformed tags
+                self.copy(&input[pos..]);
+                return Ok(input.len());
+            }
+            return Err(self.unclosed(pos, &close));
+        };
+        if self.delimiters.is_default() {
+            self.copy(&input[pos..pos + open.len() + len + close.len()]);
+            return Ok(pos + open.len() + len + close.len());
+        }
+
+        self.emit("{{", &open);
+        self.copy(&after[..len]);
+        self.emit("}}", &close);
+        Ok(pos + open.len() + len + close.len())
+    }
+
+    /// Translate the raw block at `pos`: its start tag, the content kept as
+    /// text, and the end tag
+    fn raw_block(&mut self, pos: usize) -> Result<usize, TemplateError> {
+        let input = self.input;
+        let Delimiters { open, close } = self.delimiters.clone();
+        let (start_open, start_close) = (format!("{open}{open}"), format!("{close}{close}"));
+        let end_open = format!("{start_open}/");
+
+        let start_tag = &input[pos + start_open.len()..];
+        let start_len = start_tag.find(&start_close);
+        let content_start = start_len.map(|l| pos + start_open.len() + l + start_close.len());
+        let content_len = content_start.and_then(|c| input[c..].find(&end_open));
+        let name_len = content_start
+            .zip(content_len)
+            .and_then(|(c, l)| input[c + l + end_open.len()..].find(&start_close));
+
+        let (Some(start_len), Some(content_start), Some(content_len), Some(name_len)) =
+            (start_len, content_start, content_len, name_len)
+        else {
+            if self.delimiters.is_default() {
+                self.copy(&input[pos..]);
+                return Ok(input.len());
+            }
+            return Err(self.unclosed(pos, &end_open));
+        };
+        let end = content_start + content_len + end_open.len() + name_len + start_close.len();
+
+        if self.delimiters.is_default() {
+            self.copy(&input[pos..end]);
+        } else {
+            self.emit("{{{{", &start_open);
+            self.copy(&start_tag[..start_len]);
+            self.emit("}}}}", &start_close);
+            self.text(&input[content_start..][..content_len]);
+            self.emit("{{{{/", &end_open);
+            self.copy(&input[content_start + content_len + end_open.len()..][..name_len]);
+            self.emit("}}}}", &start_close);
+        }
+        Ok(end)
+    }
+
+    fn text(&mut self, s: &str) {
+        if self.delimiters.is_default() {
+            self.copy(s);
+            return;
+        }
+        let [(brace, _), (backslash, _)] = self.placeholders;
+        let s = s.replace('{', brace.encode_utf8(&mut [0; 4]));
+        let s = s.replace('\\', backslash.encode_utf8(&mut [0; 4]));
+        self.copy(&s);
+    }
+
+    fn copy(&mut self, s: &str) {
+        self.emit(s, s);
+    }
+
+    fn emit(&mut self, translated: &str, original: &str) {
+        self.offsets.push((self.out_chars, self.in_chars));
+        self.out.push_str(translated);
+        self.out_chars += translated.chars().count();
+        self.in_chars += original.chars().count();
+    }
+
+    fn unclosed(&self, pos: usize, expected: &str) -> TemplateError {
+        self.error_at(
+            TemplateError::of(TemplateErrorReason::InvalidSyntax(format!(
+                "unclosed tag, expected {expected:?}"
+            ))),
+            pos,
+        )
+    }
+
+    fn error_at(&self, e: TemplateError, pos: usize) -> TemplateError {
+        let (line, col) = line_col(self.input, pos);
+        e.at(self.input, line, col)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::TemplateErrorReason;
+    use crate::registry::Registry;
+    use crate::template::{Template, TemplateElement, TemplateMapping};
+
+    #[test]
+    fn test_custom_delimiters() {
+        let mut r = Registry::new();
+        r.set_delimiters("<%", "%>").unwrap();
+        assert_eq!(r.delimiters(), ("<%", "%>"));
+
+        r.register_template_string(
+            "t",
+            "\\section{<% title %>} {{literal}} \\{{x}}\n\
+             <%#each items as |i|%>\n  <%{i}%>,<% @index %>\n<%/each%>\n\
+             <%! a comment %><%!-- a %> comment --%>\
+             <%<%raw%>%><% x %>{{y}}<%<%/raw%>%>",
+        )
+        .unwrap();
+        assert_eq!(
+            r.render(
+                "t",
+                &json!({"title": "T", "items": ["<a>", "b"], "literal": "no"})
+            )
+            .unwrap(),
+            "\\section{T} {{literal}} \\{{x}}\n  <a>,0\n  b,1\n<% x %>{{y}}"
+        );
+
+        assert_eq!(r.render_template("[<% this %>]", &json!(1)).unwrap(), "[1]");
+
+        assert!(r.set_delimiters("<%", "").is_err());
+        assert!(r.set_delimiters("< %", "%>").is_err());
+        assert!(r.set_delimiters("<=", "%>").is_err());
+    }
+
+    #[test]
+    fn test_set_delimiters_tag() {
+        let r = Registry::new();
+        let data = json!({"a": 1, "b": 2});
+        assert_eq!(
+            r.render_template("{{a}}\n{{=<% %>=}}\n<%b%>{{a}}\n<%={{ }}=%>\n{{b}}", &data)
+                .unwrap(),
+            "1\n2{{a}}\n2"
+        );
+        assert_eq!(
+            r.render_template("{{= | | =}}|a| |=[[ ]]=|[[b]]|a|", &data)
+                .unwrap(),
+            "1 2|a|"
+        );
+        // escaped, commented and raw tags are left alone
+        assert_eq!(
+            r.render_template(
+                "\\{{=<% %>=}}{{!-- {{=<% %>=}} --}}{{{{raw}}}}{{=<% %>=}}{{{{/raw}}}}<%a%>",
+                &data
+            )
+            .unwrap(),
+            "{{=<% %>=}}{{=<% %>=}}<%a%>"
+        );
+
+        let e = Template::compile("a\n {{=<% %>}}").unwrap_err();
+        assert!(matches!(e.reason(), TemplateErrorReason::InvalidSyntax(_)));
+        assert_eq!(e.pos(), Some((2, 2)));
+
+        let e = Template::compile("{{=<%=}}").unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            TemplateErrorReason::InvalidDelimiters(_)
+        ));
+    }
+
+    #[test]
+    fn test_positions() {
+        let t = Template::compile("{{=<<< >>>=}}\n中<<<a>>> <<<b>>>").unwrap();
+        assert_eq!(
+            t.mapping,
+            vec![
+                TemplateMapping(1, 1),
+                TemplateMapping(2, 1),
+                TemplateMapping(2, 2),
+                TemplateMapping(2, 10),
+                TemplateMapping(2, 10),
+            ]
+        );
+        assert_eq!(t.elements[1], TemplateElement::RawString("中".to_owned()));
+        let source = "{{=<<< >>>=}}\n中<<<a>>> <<<b>>>";
+        let spans: Vec<_> = t.spans.iter().map(|s| &source[s.range()]).collect();
+        // text stripped from standalone lines is part of the span
+        assert_eq!(
+            spans,
+            vec!["{{=<<< >>>=}}", "\n中", "<<<a>>>", " ", "<<<b>>>"]
+        );
+        assert_eq!(t.spans[2].end_pos, (2, 9));
+
+        let e = Template::compile("{{=<<< >>>=}}\n{{ <<<#if a>>>\n<<</each>>>").unwrap_err();
+        assert_eq!(e.pos(), Some((3, 1)));
+
+        let e = Template::compile("{{=<<< >>>=}}\n{ <<<a b c ( >>>").unwrap_err();
+        assert!(matches!(e.reason(), TemplateErrorReason::InvalidSyntax(_)));
+        assert_eq!(e.pos().map(|p| p.0), Some(2));
+    }
+}
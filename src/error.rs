@@ -2,11 +2,14 @@ use std::borrow::ToOwned;
 use std::error::Error as StdError;
 use std::fmt::{self, Write};
 use std::io::Error as IOError;
+use std::ops::Range;
 use std::string::FromUtf8Error;
 
 use serde_json::error::Error as SerdeError;
 use thiserror::Error;
 
+use crate::limits::RenderLimit;
+
 #[cfg(feature = "dir_source")]
 use walkdir::Error as WalkdirError;
 
@@ -139,6 +142,14 @@ pub enum RenderErrorReason {
     ),
     #[error("Unimplemented")]
     Unimplemented,
+    #[error("Render cancelled")]
+    Cancelled,
+    #[error("Render limit exceeded: {0}")]
+    LimitExceeded(RenderLimit),
+    #[error("Async helper {0} called outside of render_async")]
+    AsyncHelperInSyncRender(String),
+    #[error("Async helper {0} called with other arguments than the previous render")]
+    AsyncHelperNotAwaited(String),
     #[error("{0}")]
     Other(String),
 }
@@ -204,12 +215,24 @@ pub enum TemplateErrorReason {
     MismatchingClosedHelper(String, String),
     #[error("decorator {0:?} was opened, but {1:?} is closing")]
     MismatchingClosedDecorator(String, String),
+    #[error("block {0:?} is not closed")]
+    UnclosedBlock(String),
+    #[error("{0:?} is closing, but no block is open")]
+    UnexpectedClosingTag(String),
     #[error("invalid handlebars syntax: {0}")]
     InvalidSyntax(String),
     #[error("invalid parameter {0:?}")]
     InvalidParam(String),
     #[error("nested subexpression is not supported")]
     NestedSubexpression,
+    #[error("helper {0:?} is not registered")]
+    UnknownHelper(String),
+    #[error("decorator {0:?} is not registered")]
+    UnknownDecorator(String),
+    #[error("partial {0:?} is not registered")]
+    UnknownPartial(String),
+    #[error("invalid delimiters: {0}")]
+    InvalidDelimiters(String),
     #[error("Template \"{1}\": {0}")]
     IoError(IOError, String),
     #[cfg(feature = "dir_source")]
@@ -228,6 +251,7 @@ pub struct TemplateError {
     line_no: Option<usize>,
     column_no: Option<usize>,
     segment: Option<String>,
+    span: Option<Range<usize>>,
 }
 
 impl TemplateError {
@@ -239,6 +263,7 @@ impl TemplateError {
             line_no: None,
             column_no: None,
             segment: None,
+            span: None,
         }
     }
 
@@ -249,6 +274,19 @@ impl TemplateError {
         self
     }
 
+    // position without the source segment, for checks on compiled templates
+    pub(crate) fn at_pos(mut self, line_no: usize, column_no: usize) -> TemplateError {
+        self.line_no = Some(line_no);
+        self.column_no = Some(column_no);
+        self
+    }
+
+    // byte range of the faulty tag in the template source
+    pub(crate) fn with_span(mut self, span: Range<usize>) -> TemplateError {
+        self.span = Some(span);
+        self
+    }
+
     pub fn in_template(mut self, name: String) -> TemplateError {
         self.template_name = Some(name);
         self
@@ -267,6 +305,14 @@ impl TemplateError {
         }
     }
 
+    /// Get the byte range of the template source this error points to
+    ///
+    /// Only available on errors reported by
+    /// [`Template::compile_recovering`](crate::template::Template::compile_recovering).
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.span.clone()
+    }
+
     /// Get template name of this error
     /// Returns `None` when the template has no associated name
     pub fn name(&self) -> Option<&String> {
@@ -329,6 +375,14 @@ impl fmt::Display for TemplateError {
                 seg,
                 self.reason()
             ),
+            (Some(line), Some(col), None) => write!(
+                f,
+                "Template error in \"{}\":{}:{}: {}",
+                self.template_name.as_deref().unwrap_or("Unnamed template"),
+                line,
+                col,
+                self.reason()
+            ),
             _ => write!(f, "{}", self.reason()),
         }
     }
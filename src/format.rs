@@ -0,0 +1,804 @@
+use std::collections::HashMap;
+
+use derive_builder::Builder;
+use serde_json::value::Value as Json;
+
+use crate::delimiters::{self, Delimiters};
+use crate::error::TemplateError;
+use crate::template::TemplateElement::{
+    Comment, DecoratorBlock, DecoratorExpression, Expression, HelperBlock, HtmlExpression,
+    PartialBlock, PartialExpression, RawString,
+};
+use crate::template::{
+    BlockParam, DecoratorTemplate, ExpressionSpans, HelperTemplate, Parameter, SourceSpan,
+    Subexpression, Template, TemplateElement, TemplateOptions,
+};
+
+/// Quotes of the string literals written by [`format_template`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum QuoteStyle {
+    #[default]
+    Double,
+    Single,
+}
+
+/// Options of [`format_template`]
+#[non_exhaustive]
+#[derive(Builder, Clone, Debug)]
+#[builder(default)]
+pub struct FormatOptions {
+    /// Spaces added to the lines of a block body, relative to the line of
+    /// the block's open tag
+    pub indent_width: usize,
+    /// Tags with hash arguments that would end past this column are written
+    /// with one hash argument per line
+    pub max_width: usize,
+    /// Preferred quotes of string literals. The other quotes are used when
+    /// they avoid escaping the string.
+    pub quote_style: QuoteStyle,
+    /// Parse `{{^name}}` as an inverted section, also inside a block, like
+    /// templates registered with `Registry::set_mustache_compat`
+    pub mustache_compat: bool,
+    /// Indent the lines of block bodies. Without it, only the tags are
+    /// formatted and the text keeps its indentation, so the rendering is
+    /// not changed.
+    pub indent_bodies: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent_width: 2,
+            max_width: 80,
+            quote_style: QuoteStyle::Double,
+            mustache_compat: false,
+            indent_bodies: true,
+        }
+    }
+}
+
+/// Format a template source to the canonical style
+///
+/// Tags are written with single spaces between their parts, string literals
+/// with the preferred quotes and hash arguments in the order they are
+/// written. The lines of a block body are indented one level deeper than
+/// the line of its open tag, keeping their indentation relative to each
+/// other, and `else` and close tags are aligned with the open tag. Text
+/// outside of tags is left as is apart from the indentation of its lines,
+/// and whitespace control `~`, comments and raw blocks are kept.
+///
+/// Indenting block bodies changes the whitespace of the rendered output:
+/// the text lines of a body, and the lines of a standalone partial in a
+/// body, are rendered with the added indentation. Set
+/// `FormatOptions::indent_bodies` to `false` to format only the tags and
+/// keep the rendering of the template.
+///
+/// Templates that change their delimiters with `{{=<% %>=}}` are returned
+/// unchanged.
+///
+/// ```
+/// use handlebars::{FormatOptions, format_template};
+///
+/// let source = "<ul>\n{{#each items as | item |}}\n<li>{{ link item.url   title='home' }}</li>\n{{/each}}\n</ul>\n";
+/// let formatted = format_template(source, &FormatOptions::default()).unwrap();
+/// assert_eq!(
+///     formatted,
+///     "<ul>\n{{#each items as |item|}}\n  <li>{{link item.url title=\"home\"}}</li>\n{{/each}}\n</ul>\n"
+/// );
+/// ```
+pub fn format_template(source: &str, options: &FormatOptions) -> Result<String, TemplateError> {
+    let t = Template::compile2(
+        source,
+        TemplateOptions {
+            mustache_compat: options.mustache_compat,
+            ..Default::default()
+        },
+    )?;
+    if delimiters::translate(source, &Delimiters::default())?.is_some() {
+        return Ok(source.to_owned());
+    }
+
+    let mut f = Formatter {
+        source,
+        options,
+        buf: String::with_capacity(source.len()),
+        delta: 0,
+        pending: None,
+    };
+    f.template(&t, 0, source.len());
+    if let Some(ws) = f.pending.take() {
+        f.buf.push_str(&ws);
+    }
+    Ok(f.buf)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    options: &'a FormatOptions,
+    buf: String,
+    // columns added to the indentation of the lines of the current body
+    delta: isize,
+    // indentation of a new line whose content is not written yet, the next
+    // tag decides where the line starts
+    pending: Option<String>,
+}
+
+impl<'a> Formatter<'a> {
+    /// Write the elements of a template covering `start..end` of the source,
+    /// the text between them is copied from the source
+    fn template(&mut self, t: &Template, start: usize, end: usize) {
+        let mut pos = start;
+        for (e, span) in tag_elements(t) {
+            self.text(&self.source[pos..span.start]);
+            self.element(e, span);
+            pos = span.end;
+        }
+        self.text(&self.source[pos..end]);
+    }
+
+    /// Write a block body covering `start..end`, indented relative to the
+    /// line of its open tag unless `indent_bodies` is off
+    fn body(&mut self, t: Option<&Template>, start: usize, end: usize) {
+        let saved = self.delta;
+        let min = self
+            .options
+            .indent_bodies
+            .then(|| self.min_indent(t, start, end))
+            .flatten();
+        if let Some(min) = min {
+            let base = self.line_indent() + self.options.indent_width;
+            self.delta = base as isize - min as isize;
+        }
+        match t {
+            Some(t) => self.template(t, start, end),
+            None => self.text(&self.source[start..end]),
+        }
+        self.delta = saved;
+    }
+
+    /// Smallest indentation of the lines starting in the body, not counting
+    /// blank lines nor the line of its closing tag
+    fn min_indent(&self, t: Option<&Template>, start: usize, end: usize) -> Option<usize> {
+        let mut gaps = Vec::new();
+        let mut pos = start;
+        for (_, span) in t.into_iter().flat_map(tag_elements) {
+            gaps.push((&self.source[pos..span.start], true));
+            pos = span.end;
+        }
+        gaps.push((&self.source[pos..end], false));
+
+        let mut min = None;
+        for (gap, before_tag) in gaps {
+            let mut lines = gap.split('\n').skip(1).peekable();
+            while let Some(line) = lines.next() {
+                let content = line.trim_start_matches([' ', '\t']);
+                let last = lines.peek().is_none();
+                if !content.is_empty() || (last && before_tag) {
+                    let width = self.width(&line[..line.len() - content.len()]);
+                    min = Some(min.map_or(width, |m: usize| m.min(width)));
+                }
+            }
+        }
+        min
+    }
+
+    fn text(&mut self, text: &str) {
+        let mut lines = text.split('\n').peekable();
+        if let Some(first) = lines.next() {
+            match self.pending.take() {
+                Some(mut line) => {
+                    line.push_str(first);
+                    self.line(&line, lines.peek().is_none());
+                }
+                None => self.buf.push_str(first),
+            }
+        }
+        while let Some(line) = lines.next() {
+            self.buf.push('\n');
+            self.line(line, lines.peek().is_none());
+        }
+    }
+
+    /// Write a line of text starting on a new line. A whitespace only line
+    /// followed by a tag stays pending, the tag decides where it starts.
+    fn line(&mut self, line: &str, before_tag: bool) {
+        let content = line.trim_start_matches([' ', '\t']);
+        if !content.is_empty() {
+            self.indent(&line[..line.len() - content.len()], None);
+            self.buf.push_str(content);
+        } else if before_tag {
+            self.pending = Some(line.to_owned());
+        } else if self.delta == 0 {
+            // blank lines keep their whitespace only outside of blocks
+            self.buf.push_str(line);
+        }
+    }
+
+    /// Start the line of a tag, at `base` for the `else` and close tags of a
+    /// block
+    fn start_tag(&mut self, base: Option<usize>) {
+        if let Some(ws) = self.pending.take() {
+            self.indent(&ws, base);
+        }
+    }
+
+    fn indent(&mut self, ws: &str, base: Option<usize>) {
+        match base {
+            Some(base) => self.push_spaces(base),
+            None if self.delta == 0 => self.buf.push_str(ws),
+            None => {
+                let width = self.width(ws) as isize + self.delta;
+                self.push_spaces(width.max(0) as usize);
+            }
+        }
+    }
+
+    fn push_spaces(&mut self, n: usize) {
+        self.buf.extend(std::iter::repeat_n(' ', n));
+    }
+
+    fn width(&self, ws: &str) -> usize {
+        ws.chars()
+            .map(|c| {
+                if c == '\t' {
+                    self.options.indent_width
+                } else {
+                    1
+                }
+            })
+            .sum()
+    }
+
+    /// Indentation of the current output line
+    fn line_indent(&self) -> usize {
+        let line = self.buf.rsplit('\n').next().unwrap_or_default();
+        self.width(&line[..line.len() - line.trim_start_matches([' ', '\t']).len()])
+    }
+
+    fn column(&self) -> usize {
+        self.buf
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count()
+    }
+
+    fn element(&mut self, e: &TemplateElement, span: &SourceSpan) {
+        self.start_tag(None);
+        match e {
+            RawString(_) => {}
+            // comments and raw blocks are kept as written
+            Comment(_) => self.buf.push_str(&self.source[span.range()]),
+            Expression(ht) => {
+                let tag = self.helper_tag(ht, "", &ht.spans.open_tag);
+                self.tag(tag);
+            }
+            HtmlExpression(ht) => {
+                let mut tag = self.helper_tag(ht, "{", &ht.spans.open_tag);
+                tag.suffix = "}";
+                self.tag(tag);
+            }
+            HelperBlock(ht) => self.helper_block(ht, span),
+            DecoratorExpression(dt) => {
+                let tag = self.decorator_tag(dt, "*", false);
+                self.tag(tag);
+            }
+            PartialExpression(dt) => {
+                let tag = self.decorator_tag(dt, "> ", true);
+                self.tag(tag);
+            }
+            DecoratorBlock(dt) => self.decorator_block(dt, "#*", false),
+            PartialBlock(dt) => self.decorator_block(dt, "#> ", true),
+        }
+    }
+
+    fn helper_block(&mut self, ht: &HelperTemplate, span: &SourceSpan) {
+        let open_tag = &self.source[ht.spans.open_tag.range()];
+        if open_tag.starts_with("{{{{") {
+            self.buf.push_str(&self.source[span.range()]);
+            return;
+        }
+        let Some(close_tag) = ht.spans.close_tag else {
+            self.buf.push_str(&self.source[span.range()]);
+            return;
+        };
+
+        let inverted = open_tag
+            .trim_start_matches('{')
+            .trim_start_matches('~')
+            .starts_with('^');
+        let tag = self.helper_tag(ht, if inverted { "^" } else { "#" }, &ht.spans.open_tag);
+        self.tag(tag);
+        let base = self.line_indent();
+
+        let body_end = |spans: &ExpressionSpans| {
+            spans
+                .inverse_tag
+                .map(|s| s.start)
+                .unwrap_or(close_tag.start)
+        };
+        let (first, second) = if inverted {
+            (ht.inverse.as_ref(), ht.template.as_ref())
+        } else {
+            (ht.template.as_ref(), ht.inverse.as_ref())
+        };
+        self.body(first, ht.spans.open_tag.end, body_end(&ht.spans));
+
+        let mut node = ht;
+        let mut inverse = second;
+        while let Some(else_tag) = node.spans.inverse_tag {
+            self.start_tag(Some(base));
+            match inverse.and_then(chained_node) {
+                Some(chained) if !inverted => {
+                    let tag = self.helper_tag(chained, "else ", &else_tag);
+                    self.tag(tag);
+                    self.body(
+                        chained.template.as_ref(),
+                        else_tag.end,
+                        body_end(&chained.spans),
+                    );
+                    node = chained;
+                    inverse = chained.inverse.as_ref();
+                }
+                _ => {
+                    self.tag(self.bare_tag("else", &else_tag));
+                    self.body(inverse, else_tag.end, close_tag.start);
+                    break;
+                }
+            }
+        }
+
+        self.start_tag(Some(base));
+        let mut tag = self.bare_tag("/", &close_tag);
+        tag.name = self.name(&ht.name);
+        self.tag(tag);
+    }
+
+    fn decorator_block(&mut self, dt: &DecoratorTemplate, prefix: &'static str, partial: bool) {
+        let tag = self.decorator_tag(dt, prefix, partial);
+        self.tag(tag);
+        let Some(close_tag) = dt.spans.close_tag else {
+            return;
+        };
+        let base = self.line_indent();
+        self.body(dt.template.as_ref(), dt.spans.open_tag.end, close_tag.start);
+
+        self.start_tag(Some(base));
+        let mut tag = self.bare_tag("/", &close_tag);
+        tag.name = match dt.name {
+            Parameter::Subexpression(_) => String::new(),
+            ref name if partial => self.partial_name(name),
+            ref name => self.name(name),
+        };
+        self.tag(tag);
+    }
+
+    fn bare_tag(&self, prefix: &'static str, span: &SourceSpan) -> Tag {
+        let (open_tilde, close_tilde) = tildes(&self.source[span.range()]);
+        Tag {
+            open_tilde,
+            close_tilde,
+            prefix,
+            suffix: "",
+            name: String::new(),
+            params: Vec::new(),
+            hash: Vec::new(),
+            block_param: None,
+        }
+    }
+
+    fn helper_tag(&self, ht: &HelperTemplate, prefix: &'static str, span: &SourceSpan) -> Tag {
+        let mut tag = self.bare_tag(prefix, span);
+        tag.name = self.name(&ht.name);
+        tag.params = ht.params.iter().map(|p| self.parameter(p)).collect();
+        tag.hash = self.hash(&ht.hash, &ht.spans);
+        tag.block_param = ht.block_param.as_ref().map(|bp| match bp {
+            BlockParam::Single(p) => format!("as |{}|", self.parameter(p)),
+            BlockParam::Pair((p1, p2)) => {
+                format!("as |{} {}|", self.parameter(p1), self.parameter(p2))
+            }
+        });
+        tag
+    }
+
+    fn decorator_tag(&self, dt: &DecoratorTemplate, prefix: &'static str, partial: bool) -> Tag {
+        let mut tag = self.bare_tag(prefix, &dt.spans.open_tag);
+        tag.name = if partial {
+            self.partial_name(&dt.name)
+        } else {
+            self.name(&dt.name)
+        };
+        tag.params = dt.params.iter().map(|p| self.parameter(p)).collect();
+        tag.hash = self.hash(&dt.hash, &dt.spans);
+        tag
+    }
+
+    /// Write a tag on one line, or with one hash argument per line aligned
+    /// on the first argument when it doesn't fit
+    fn tag(&mut self, tag: Tag) {
+        let mut head = String::from("{{");
+        if tag.open_tilde {
+            head.push('~');
+        }
+        head.push_str(tag.prefix);
+        head.push_str(&tag.name);
+
+        let mut args: Vec<_> = tag.params.clone();
+        args.extend(tag.hash.iter().map(|(k, v)| format!("{k}={v}")));
+        let mut tail = String::new();
+        if let Some(ref bp) = tag.block_param {
+            tail.push(' ');
+            tail.push_str(bp);
+        }
+        tail.push_str(tag.suffix);
+        if tag.close_tilde {
+            tail.push('~');
+        }
+        tail.push_str("}}");
+
+        let line: usize = head.chars().count()
+            + args.iter().map(|a| a.chars().count() + 1).sum::<usize>()
+            + tail.chars().count();
+        let start = self.column();
+        self.buf.push_str(&head);
+
+        if tag.hash.len() > 1 && start + line > self.options.max_width {
+            let align = start + head.chars().count() + 1;
+            for p in &tag.params {
+                self.buf.push(' ');
+                self.buf.push_str(p);
+            }
+            for (i, (k, v)) in tag.hash.iter().enumerate() {
+                if i == 0 && tag.params.is_empty() {
+                    self.buf.push(' ');
+                } else {
+                    self.buf.push('\n');
+                    self.push_spaces(align);
+                }
+                self.buf.push_str(k);
+                self.buf.push('=');
+                self.buf.push_str(v);
+            }
+        } else {
+            for a in &args {
+                self.buf.push(' ');
+                self.buf.push_str(a);
+            }
+        }
+        self.buf.push_str(&tail);
+    }
+
+    fn hash(
+        &self,
+        hash: &HashMap<String, Parameter>,
+        spans: &ExpressionSpans,
+    ) -> Vec<(String, String)> {
+        let mut pairs: Vec<_> = hash.iter().collect();
+        // in the order they are written
+        pairs.sort_by_key(|(k, _)| (spans.hash.get(*k).map(|s| s.start), k.as_str()));
+        pairs
+            .into_iter()
+            .map(|(k, v)| (k.clone(), self.parameter(v)))
+            .collect()
+    }
+
+    fn name(&self, p: &Parameter) -> String {
+        self.parameter(p)
+    }
+
+    fn partial_name(&self, p: &Parameter) -> String {
+        match p {
+            Parameter::Name(n)
+                if !n.chars().all(|c| {
+                    c.is_alphanumeric() || matches!(c, '-' | '_' | '@' | '?' | '/' | '.')
+                }) =>
+            {
+                format!("[{n}]")
+            }
+            p => self.name(p),
+        }
+    }
+
+    fn parameter(&self, p: &Parameter) -> String {
+        match p {
+            Parameter::Name(n) => n.clone(),
+            Parameter::Path(path) => path.raw().to_owned(),
+            Parameter::Literal(Json::String(s)) => self.string_literal(s),
+            Parameter::Literal(j) => j.to_string(),
+            Parameter::Subexpression(s) => self.subexpression(s),
+        }
+    }
+
+    fn subexpression(&self, s: &Subexpression) -> String {
+        let mut buf = String::from("(");
+        if let Expression(ref ht) = *s.as_element() {
+            buf.push_str(&self.name(&ht.name));
+            for p in &ht.params {
+                buf.push(' ');
+                buf.push_str(&self.parameter(p));
+            }
+            for (k, v) in self.hash(&ht.hash, &ht.spans) {
+                buf.push(' ');
+                buf.push_str(&k);
+                buf.push('=');
+                buf.push_str(&v);
+            }
+        }
+        buf.push(')');
+        buf
+    }
+
+    fn string_literal(&self, s: &str) -> String {
+        let (preferred, other) = match self.options.quote_style {
+            QuoteStyle::Double => ('"', '\''),
+            QuoteStyle::Single => ('\'', '"'),
+        };
+        let quote = if s.contains(preferred) && !s.contains(other) {
+            other
+        } else {
+            preferred
+        };
+
+        let mut buf = String::with_capacity(s.len() + 2);
+        buf.push(quote);
+        for c in s.chars() {
+            match c {
+                '\\' => buf.push_str("\\\\"),
+                '\n' => buf.push_str("\\n"),
+                '\r' => buf.push_str("\\r"),
+                '\t' => buf.push_str("\\t"),
+                '\u{8}' => buf.push_str("\\b"),
+                '\u{c}' => buf.push_str("\\f"),
+                c if c == quote => {
+                    buf.push('\\');
+                    buf.push(c);
+                }
+                c if c < ' ' => buf.push_str(&format!("\\u{:04x}", c as u32)),
+                c => buf.push(c),
+            }
+        }
+        buf.push(quote);
+        buf
+    }
+}
+
+struct Tag {
+    open_tilde: bool,
+    close_tilde: bool,
+    prefix: &'static str,
+    suffix: &'static str,
+    name: String,
+    params: Vec<String>,
+    hash: Vec<(String, String)>,
+    block_param: Option<String>,
+}
+
+/// Elements of a template other than text, with their source spans
+fn tag_elements(t: &Template) -> impl Iterator<Item = (&TemplateElement, &SourceSpan)> {
+    t.elements
+        .iter()
+        .zip(t.spans.iter())
+        .filter(|(e, _)| !matches!(e, RawString(_)))
+}
+
+/// Whitespace control of a tag, `{{~` and `~}}`
+fn tildes(tag: &str) -> (bool, bool) {
+    (
+        tag.trim_start_matches('{').starts_with('~'),
+        tag.trim_end_matches('}').ends_with('~'),
+    )
+}
+
+/// Returns the inverse template's chained `else if` node, if any.
+fn chained_node(t: &Template) -> Option<&HelperTemplate> {
+    match t.elements.as_slice() {
+        [HelperBlock(node)] if node.chain => Some(node),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FormatOptions, FormatOptionsBuilder, QuoteStyle, format_template};
+    use crate::Handlebars;
+    use serde_json::json;
+
+    fn assert_format(source: &str, expected: &str) {
+        let formatted = format_template(source, &FormatOptions::default()).unwrap();
+        assert_eq!(formatted, expected, "source: {source:?}");
+        assert_eq!(
+            format_template(&formatted, &FormatOptions::default()).unwrap(),
+            formatted,
+            "not idempotent"
+        );
+    }
+
+    #[test]
+    fn test_mustache_compat() {
+        let options = FormatOptionsBuilder::default()
+            .mustache_compat(true)
+            .build()
+            .unwrap();
+        let cases = [
+            ("{{^ a}}1{{ else }}2{{/a}}", "{{^a}}1{{else}}2{{/a}}"),
+            ("{{#a}}{{^ b}}x{{/b}}{{/a}}", "{{#a}}{{^b}}x{{/b}}{{/a}}"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(format_template(source, &options).unwrap(), expected);
+        }
+        // a chain of the enclosing block by default
+        assert!(format_template("{{#a}}{{^b}}x{{/b}}{{/a}}", &FormatOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_spacing() {
+        let cases = [
+            ("{{ name }}", "{{name}}"),
+            ("{{  foo   a  'b'  k=1  }}", "{{foo a \"b\" k=1}}"),
+            ("{{{ html }}} {{& amp}}", "{{{html}}} {{{amp}}}"),
+            ("{{~ a ~}} {{~{ b }~}}", "{{~a~}} {{~{b}~}}"),
+            ("{{foo (bar  x k='v') }}", "{{foo (bar x k=\"v\")}}"),
+            (
+                "{{#each l as | a b |}}{{a}}{{/each}}",
+                "{{#each l as |a b|}}{{a}}{{/each}}",
+            ),
+            ("{{>  header  title=t}}", "{{> header title=t}}"),
+            ("{{#>layout}}x{{/layout}}", "{{#> layout}}x{{/layout}}"),
+            (
+                "{{#* inline 'p'}}x{{/inline}}",
+                "{{#*inline \"p\"}}x{{/inline}}",
+            ),
+            ("{{* deco }}", "{{*deco}}"),
+            (
+                "{{#if a}}1{{ else if b }}2{{^}}3{{/ if}}",
+                "{{#if a}}1{{else if b}}2{{else}}3{{/if}}",
+            ),
+            (
+                "{{#if a}}1{{^ if b}}2{{/if}}",
+                "{{#if a}}1{{else if b}}2{{/if}}",
+            ),
+            ("{{k a=1 b=2 c=3}}", "{{k a=1 b=2 c=3}}"),
+            ("{{k c=3 a=1}}", "{{k c=3 a=1}}"),
+        ];
+        for (source, expected) in cases {
+            assert_format(source, expected);
+        }
+    }
+
+    #[test]
+    fn test_kept_as_written() {
+        let cases = [
+            "{{!  a  comment }}{{!-- {{x}} --}}",
+            "{{{{raw}}}} {{ x }} {{{{/raw}}}}",
+            r"\{{ escaped }} text",
+            "{{=<% %>=}}<% a %>",
+        ];
+        for source in cases {
+            assert_format(source, source);
+        }
+    }
+
+    #[test]
+    fn test_quotes() {
+        assert_format(r#"{{a 'x' "y" 'it"s'}}"#, r#"{{a "x" "y" 'it"s'}}"#);
+        assert_format(
+            r#"{{a "it's \"q\"" 'b\\c'}}"#,
+            r#"{{a "it's \"q\"" "b\\c"}}"#,
+        );
+
+        let options = FormatOptionsBuilder::default()
+            .quote_style(QuoteStyle::Single)
+            .build()
+            .unwrap();
+        assert_eq!(
+            format_template(r#"{{a "x" "it's" [1, "b"]}}"#, &options).unwrap(),
+            r#"{{a 'x' "it's" [1,"b"]}}"#
+        );
+    }
+
+    #[test]
+    fn test_indent() {
+        assert_format(
+            "<ul>\n{{#each items}}\n<li>\n    {{#if a}}\n{{a}}\n      {{else}}\n  b\n {{/if}}\n</li>\n{{/each}}\n</ul>\n",
+            "<ul>\n{{#each items}}\n  <li>\n      {{#if a}}\n        {{a}}\n      {{else}}\n        b\n      {{/if}}\n  </li>\n{{/each}}\n</ul>\n",
+        );
+        assert_format(
+            "<div>\n    {{#if a}}\n\n\t<p>x</p>\n    {{else if b}}\n y\n    {{/if}}\n</div>",
+            "<div>\n    {{#if a}}\n\n      <p>x</p>\n    {{else if b}}\n      y\n    {{/if}}\n</div>",
+        );
+        assert_format(
+            "{{#> layout}}\n{{#*inline \"body\"}}\nx\n{{/inline}}\n{{/layout}}\n",
+            "{{#> layout}}\n  {{#*inline \"body\"}}\n    x\n  {{/inline}}\n{{/layout}}\n",
+        );
+        assert_format("a {{#if a}}b{{/if}}  \n  c", "a {{#if a}}b{{/if}}  \n  c");
+    }
+
+    #[test]
+    fn test_hash_alignment() {
+        let options = FormatOptionsBuilder::default()
+            .max_width(30)
+            .build()
+            .unwrap();
+        assert_eq!(
+            format_template(
+                "<p>\n  {{> card item  title=item.title subtitle=\"sub\"}}\n</p>",
+                &options
+            )
+            .unwrap(),
+            "<p>\n  {{> card item\n           title=item.title\n           subtitle=\"sub\"}}\n</p>"
+        );
+        assert_eq!(
+            format_template(
+                "{{#each list key=\"id\" sort=\"name\" as |x|}}{{/each}}",
+                &options
+            )
+            .unwrap(),
+            "{{#each list\n        key=\"id\"\n        sort=\"name\" as |x|}}{{/each}}"
+        );
+        assert_eq!(
+            format_template("{{link title=\"a long title\" href=url}}", &options).unwrap(),
+            "{{link title=\"a long title\"\n       href=url}}"
+        );
+    }
+
+    #[test]
+    fn test_same_rendering() {
+        // only the whitespace stripped by `~` is reindented
+        let source = "{{#each items as |item|~}}\n{{~#if item.on~}}\n {{item.name}}:  {{{item.html}}}\n{{~/if~}}\n{{~/each}}\n{{> p a='x'}}";
+        let formatted = format_template(source, &FormatOptions::default()).unwrap();
+        assert_ne!(formatted, source);
+
+        let mut hbs = Handlebars::new();
+        hbs.register_template_string("p", "[{{a}}]").unwrap();
+        let data = json!({"items": [{"on": true, "name": "a", "html": "<b>"}, {"on": false}]});
+        assert_eq!(
+            hbs.render_template(source, &data).unwrap(),
+            hbs.render_template(&formatted, &data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rendering_changes() {
+        let mut hbs = Handlebars::new();
+        hbs.register_template_string("p", "line\nline\n").unwrap();
+        let data = json!({"a": true, "name": "x", "list": [1, 2]});
+        let render = |source: &str| hbs.render_template(source, &data).unwrap();
+        let keep = FormatOptionsBuilder::default()
+            .indent_bodies(false)
+            .build()
+            .unwrap();
+
+        // tags, standalone tag lines and bodies stripped with `~` render the same
+        let kept = [
+            "{{#if a}}\n  {{ name }}\n{{ else }}\n  no\n  {{/if}}\n",
+            "{{!-- c --}}\n{{#each list}}{{  this  }},{{/each}}",
+            "{{#if a~}}\n<pre>{{name}}</pre>\n{{~/if}}",
+        ];
+        for source in kept {
+            let formatted = format_template(source, &FormatOptions::default()).unwrap();
+            assert_ne!(formatted, source);
+            assert_eq!(render(&formatted), render(source));
+        }
+
+        // indented text lines and standalone partials of a body do not
+        let changed = [
+            "{{#if a}}\n<pre>\nline\n</pre>\n{{/if}}\n",
+            "{{#if a}}\n{{> p}}\n{{/if}}\n",
+        ];
+        for source in changed {
+            let formatted = format_template(source, &FormatOptions::default()).unwrap();
+            assert_ne!(render(&formatted), render(source));
+            assert_eq!(format_template(source, &keep).unwrap(), source);
+        }
+        assert_eq!(
+            format_template("{{#if a}}\n  {{ name }}\n    {{/if}}\n", &keep).unwrap(),
+            "{{#if a}}\n  {{name}}\n{{/if}}\n"
+        );
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        assert!(format_template("{{#if a}}", &FormatOptions::default()).is_err());
+    }
+}
@@ -144,11 +144,12 @@ mod test {
                  "{{!--
                     <li><a href=\"{{up-dir nest-count}}{{base-url}}index.html\">{{this.title}}</a></li>
                 --}}",
-                     "{{!    -- good  --}}"];
+                     "{{!    -- good  --}}",
+                     "{{~!-- hello --~}}"];
         for i in &s {
             assert_rule!(Rule::hbs_comment, i);
         }
-        let s2 = ["{{! hello }}", "{{! test me }}"];
+        let s2 = ["{{! hello }}", "{{! test me }}", "{{~! hello ~}}"];
         for i in &s2 {
             assert_rule!(Rule::hbs_comment_compact, i);
         }
@@ -266,12 +267,29 @@ mod test {
             "{{#if}}hello{{~else if foo~}}world{{/if}}",
             "{{#if}}{{/if}}",
             "{{#if}}hello{{else if}}world{{else}}test{{/if}}",
+            "{{#if}}hello{{^if foo}}world{{/if}}",
         ];
         for i in &s {
             assert_rule!(Rule::helper_block, i);
         }
     }
 
+    #[test]
+    fn test_inverted_block() {
+        let s = [
+            "{{^if hello}}world{{/if}}",
+            "{{~^ section ~}}hello{{else}}world{{/section}}",
+            "{{#if}}{{^if}}hello{{/if}}{{/if}}",
+        ];
+        for i in &s {
+            assert_rule!(Rule::handlebars_compat, i);
+        }
+        // only outside of blocks by default
+        assert_rule!(Rule::handlebars, s[0]);
+        assert_rule!(Rule::handlebars, s[1]);
+        assert_not_rule!(Rule::handlebars, s[2]);
+    }
+
     #[test]
     fn test_raw_block() {
         let s = [
@@ -313,6 +331,7 @@ mod test {
             "[foo]",
             "@root/a/b",
             "nullable",
+            ".",
         ];
         for i in &s {
             assert_rule_match!(Rule::path, i);
@@ -1,3 +1,5 @@
+use std::iter::Peekable;
+
 use serde_json::value::Value as Json;
 
 use super::block_util::create_block;
@@ -6,10 +8,11 @@ use crate::block::{BlockContext, BlockParams};
 use crate::context::Context;
 use crate::error::RenderError;
 use crate::helpers::{HelperDef, HelperResult};
-use crate::json::value::to_json;
+use crate::json::value::{JsonItems, to_json};
 use crate::output::Output;
 use crate::registry::Registry;
 use crate::render::{Helper, RenderContext, Renderable};
+use crate::template::Template;
 use crate::util::copy_on_push_vec;
 
 fn update_block_context(
@@ -61,6 +64,60 @@ fn set_block_param<'rc>(
     Ok(())
 }
 
+// renders the items of a sequence inserted by `Context::insert_iter` as they
+// are pulled, one item ahead for `@last`
+fn render_items<'reg: 'rc, 'rc>(
+    t: &'rc Template,
+    items: JsonItems,
+    h: &Helper<'rc>,
+    r: &'reg Registry<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let mut items = items.peekable();
+    if items.peek().is_none() {
+        if let Some(else_template) = h.inverse() {
+            return else_template.render(r, ctx, rc, out);
+        }
+    }
+
+    rc.push_block(BlockContext::new());
+    let result = render_pulled_items(t, &mut items, h, r, ctx, rc, out);
+    // also when an item fails, to leave the block stack as it was
+    rc.pop_block();
+    result
+}
+
+fn render_pulled_items<'reg: 'rc, 'rc>(
+    t: &'rc Template,
+    items: &mut Peekable<JsonItems>,
+    h: &Helper<'rc>,
+    r: &'reg Registry<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let mut i = 0;
+    while let Some(v) = items.next() {
+        let v = v?;
+        rc.check_iteration()?;
+        if let Some(ref mut block) = rc.block_mut() {
+            let index = to_json(i);
+            block.set_local_var("first", to_json(i == 0));
+            block.set_local_var("last", to_json(items.peek().is_none()));
+            block.set_local_var("index", index.clone());
+
+            set_block_param(block, h, None, &index, &v)?;
+            block.set_base_value(v);
+        }
+
+        t.render(r, ctx, rc, out)?;
+        i += 1;
+    }
+    Ok(())
+}
+
 #[derive(Clone, Copy)]
 pub struct EachHelper;
 
@@ -79,6 +136,12 @@ impl HelperDef for EachHelper {
 
         let template = h.template();
 
+        if let Some(t) = template {
+            if let Some(items) = value.items() {
+                return render_items(t, items, h, r, ctx, rc, out);
+            }
+        }
+
         match template {
             Some(t) => match *value.value() {
                 Json::Array(ref list)
@@ -92,6 +155,7 @@ impl HelperDef for EachHelper {
                     let array_path = value.context_path();
 
                     for (i, v) in list.iter().enumerate().take(len) {
+                        rc.check_iteration()?;
                         if let Some(ref mut block) = rc.block_mut() {
                             let is_first = i == 0usize;
                             let is_last = i == len - 1;
@@ -122,6 +186,7 @@ impl HelperDef for EachHelper {
                     let obj_path = value.context_path();
 
                     for (i, (k, v)) in obj.iter().enumerate() {
+                        rc.check_iteration()?;
                         if let Some(ref mut block) = rc.block_mut() {
                             let is_first = i == 0usize;
                             let is_last = i == len - 1;
@@ -161,11 +226,19 @@ pub static EACH_HELPER: EachHelper = EachHelper;
 
 #[cfg(test)]
 mod test {
+    use crate::RenderErrorReason;
+    use crate::block::BlockContext;
+    use crate::context::Context;
+    use crate::output::StringOutput;
     use crate::registry::Registry;
+    use crate::render::{RenderContext, Renderable};
+    use crate::template::Template;
     use crate::testing::TestHandlebars;
     use serde_json::value::Value as Json;
     use std::collections::BTreeMap;
     use std::str::FromStr;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[test]
     fn test_empty_each() {
@@ -525,4 +598,110 @@ mod test {
 </ul>",
         );
     }
+
+    fn iter_context(pulled: &Arc<AtomicUsize>) -> Context {
+        let counter = pulled.clone();
+        let mut ctx = Context::wraps(json!({"sep": ","})).unwrap();
+        ctx.insert_iter("rows", move || {
+            let counter = counter.clone();
+            (0..3).map(move |i| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                json!({"id": i, "tags": ["a", "b"]})
+            })
+        });
+        ctx.insert_iter("empty", Vec::<u8>::new);
+        // maps with non-string keys fail to serialize
+        ctx.insert_iter("invalid", || vec![BTreeMap::from([((1, 2), 3)])]);
+        ctx
+    }
+
+    #[test]
+    fn test_each_iter() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let ctx = iter_context(&pulled);
+
+        let reg = Registry::new();
+        assert_eq!(
+            reg.render_template_with_context(
+                "{{#each rows as |row i|}}{{i}}{{../sep}}{{row.id}}{{#if @first}}F{{/if}}{{#if @last}}L{{/if}}{{#each tags}}{{this}}{{/each}} {{/each}}",
+                &ctx
+            )
+            .unwrap(),
+            "0,0Fab 1,1ab 2,2Lab "
+        );
+        assert_eq!(pulled.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_each_iter_empty() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let ctx = iter_context(&pulled);
+
+        let reg = Registry::new();
+        assert_eq!(
+            reg.render_template_with_context("{{#each empty}}x{{else}}none{{/each}}", &ctx)
+                .unwrap(),
+            "none"
+        );
+    }
+
+    #[test]
+    fn test_each_iter_collected() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let ctx = iter_context(&pulled);
+
+        let reg = Registry::new();
+        assert_eq!(
+            reg.render_template_with_context("{{len rows}} {{rows.[1].id}} {{len rows}}", &ctx)
+                .unwrap(),
+            "3 1 3"
+        );
+        // collected once
+        assert_eq!(pulled.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_each_iter_error() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let ctx = iter_context(&pulled);
+
+        let reg = Registry::new();
+        let error = |t| {
+            let err = reg.render_template_with_context(t, &ctx).unwrap_err();
+            assert!(
+                matches!(err.reason(), RenderErrorReason::SerdeError(_)),
+                "{t}: {err}"
+            );
+            err.reason().to_string()
+        };
+        // streamed or collected, uses of the sequence fail the same way
+        let streamed = error("{{#each invalid}}x{{/each}}");
+        assert_eq!(error("{{len invalid}}"), streamed);
+        assert_eq!(error("{{#if invalid}}x{{/if}}"), streamed);
+        assert_eq!(error("{{invalid}}"), streamed);
+        assert_eq!(error("{{invalid.[0]}}"), streamed);
+        assert_eq!(error("{{#with invalid.[0]}}x{{/with}}"), streamed);
+    }
+
+    #[test]
+    fn test_each_iter_error_pops_block() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let ctx = iter_context(&pulled);
+        let reg = Registry::new();
+        let template = Template::compile("{{#each invalid}}x{{/each}}").unwrap();
+
+        let mut rc = RenderContext::new(None);
+        let mut block = BlockContext::new();
+        block.set_local_var("outer", json!(true));
+        rc.push_block(block);
+        assert!(
+            template
+                .render(&reg, &ctx, &mut rc, &mut StringOutput::new())
+                .is_err()
+        );
+        assert_eq!(
+            rc.block().unwrap().get_local_var("outer"),
+            Some(&json!(true))
+        );
+    }
 }
@@ -177,4 +177,19 @@ mod test {
   baz",
         );
     }
+
+    #[test]
+    fn test_inverted_block_inside_block() {
+        let mut hbs = Registry::new();
+        let data = json!({"a": false, "b": false});
+        // `{{^if b}}` continues the chain like `{{else if b}}`
+        hbs.assert_render_template("{{#if a}}1{{^if b}}2{{^}}3{{/if}}", &data, "3");
+        hbs.assert_render_template("{{#if a}}1{{else if b}}2{{^}}3{{/if}}", &data, "3");
+
+        // it opens a block of its own in the Mustache compatibility mode
+        hbs.set_mustache_compat(true);
+        hbs.assert_render_template("{{#if a}}1{{^if b}}2{{/if}}{{^}}3{{/if}}", &data, "3");
+        hbs.assert_render_template("{{#if a}}1{{else if b}}2{{^}}3{{/if}}", &data, "3");
+        hbs.assert_render_template("{{#if a}}1{{else}}{{^if b}}2{{/if}}{{/if}}", &data, "2");
+    }
 }
@@ -0,0 +1,468 @@
+//! Layout helpers `extend`, `block` and `content`, like the handlebars-layouts
+//! plugin of handlebars.js
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::Value as Json;
+
+use crate::RenderErrorReason;
+use crate::block::{BlockContext, BlockParamHolder};
+use crate::context::{Context, merge_json};
+use crate::error::RenderError;
+use crate::helpers::{HelperDef, HelperResult};
+use crate::json::path::Path;
+use crate::json::value::ScopedJson;
+use crate::output::Output;
+use crate::partial::find_partial;
+use crate::registry::Registry;
+use crate::render::{Helper, RenderContext, Renderable};
+
+/// How `{{#content}}` combines with the default content of a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LayoutMode {
+    Replace,
+    Append,
+    Prepend,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct LayoutContent {
+    mode: LayoutMode,
+    content: String,
+}
+
+fn get_name<'a>(h: &'a Helper<'_>, helper: &'static str) -> Result<&'a str, RenderError> {
+    h.param(0)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex(helper, 0))?
+        .value()
+        .as_str()
+        .ok_or_else(|| RenderErrorReason::InvalidParamType("String").into())
+}
+
+fn render_block<'reg: 'rc, 'rc>(
+    h: &Helper<'rc>,
+    r: &'reg Registry<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+) -> Result<String, RenderError> {
+    let mut out = rc.buffer();
+    if let Some(t) = h.template() {
+        t.render(r, ctx, rc, &mut out)?;
+    }
+    Ok(out.into_string()?)
+}
+
+/// `{{#extend "layout" context? key=value}}...{{/extend}}`
+///
+/// Renders the layout partial, with the blocks of the layout replaced by the
+/// `{{#content}}` defined in the block. Inline partials defined in the block
+/// are available to the layout, and the rest of the block is its
+/// `@partial-block`.
+#[derive(Clone, Copy)]
+pub struct ExtendHelper;
+
+impl HelperDef for ExtendHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Registry<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let name = get_name(h, "extend")?;
+        if rc.is_current_template(name) {
+            return Err(RenderErrorReason::CannotIncludeSelf.into());
+        }
+        let layout = find_partial(rc, r, name)?
+            .ok_or_else(|| RenderErrorReason::PartialNotFound(name.to_owned()))?;
+
+        // context of the layout, like the one of a partial
+        let hash = h
+            .hash()
+            .iter()
+            .map(|(k, v)| (*k, v.value()))
+            .collect::<HashMap<&str, &Json>>();
+        let base = match h.param(1) {
+            Some(p) => merge_json(p.value(), &hash),
+            None => match rc.context() {
+                Some(rc_context) => merge_json(
+                    rc.evaluate2(&rc_context, &Path::current())?.as_json(),
+                    &hash,
+                ),
+                None => merge_json(rc.evaluate2(ctx, &Path::current())?.as_json(), &hash),
+            },
+        };
+
+        // the block is rendered in its own scope so its inline partials don't
+        // leak to the rest of the template
+        let scope = rc.block().cloned().unwrap_or_default();
+        rc.push_block(scope);
+        rc.push_layout();
+        let partial_block = match render_block(h, r, ctx, rc) {
+            Ok(partial_block) => partial_block,
+            Err(e) => {
+                rc.pop_layout();
+                rc.pop_block();
+                return Err(e);
+            }
+        };
+
+        let mut layout_block = BlockContext::new();
+        for (k, v) in &hash {
+            layout_block.set_block_param(k, BlockParamHolder::Value((*v).clone()));
+        }
+        if let Some(parent) = rc.block() {
+            layout_block
+                .local_variables_mut()
+                .clone_from(parent.local_variables());
+        }
+        layout_block.set_base_value(base);
+        rc.push_block(layout_block);
+        rc.push_partial_block(Some(partial_block), BTreeMap::new());
+
+        let current_template_before = rc.get_current_template_name();
+        let result = layout.render(r, ctx, rc, out);
+
+        let trailing_newline = rc.get_trailine_newline();
+        rc.pop_partial_block();
+        rc.pop_block();
+        rc.pop_layout();
+        rc.pop_block();
+        rc.set_trailing_newline(trailing_newline);
+        rc.set_current_template_name(current_template_before);
+
+        result
+    }
+}
+
+/// `{{#block "name"}}default{{/block}}`
+///
+/// A region of a layout, rendered with its default content unless the
+/// templates extending the layout define content for it.
+#[derive(Clone, Copy)]
+pub struct BlockHelper;
+
+impl HelperDef for BlockHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Registry<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let name = get_name(h, "block")?;
+        let default = render_block(h, r, ctx, rc)?;
+
+        // the layout closest to this block applies last, so a layout that
+        // fills a block with a block of its own keeps its markup around the
+        // content of the templates extending it
+        let content = rc
+            .layout_contents(name)
+            .fold(default, |mut current, c| match c.mode {
+                LayoutMode::Replace => c.content.clone(),
+                LayoutMode::Append => {
+                    current.push_str(&c.content);
+                    current
+                }
+                LayoutMode::Prepend => format!("{}{}", c.content, current),
+            });
+
+        out.write(&content)?;
+        Ok(())
+    }
+}
+
+/// `{{#content "name" mode="replace|append|prepend"}}...{{/content}}`
+///
+/// Content for a block of the layout extended by the enclosing
+/// `{{#extend}}`, it renders nothing. Without block, like
+/// `{{#if (content "name")}}`, it tells whether content is defined for the
+/// block.
+#[derive(Clone, Copy)]
+pub struct ContentHelper;
+
+impl HelperDef for ContentHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Registry<'reg>,
+        _: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let name = get_name(h, "content")?;
+        let defined = rc.layout_contents(name).next().is_some();
+        Ok(ScopedJson::Derived(Json::Bool(defined)))
+    }
+
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Registry<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        if !h.is_block() {
+            let defined = self.call_inner(h, r, ctx, rc)?;
+            out.write(&defined.render())?;
+            return Ok(());
+        }
+
+        let name = get_name(h, "content")?;
+        let mode = match h.hash_get("mode").map(|m| m.value()) {
+            None => LayoutMode::Replace,
+            Some(Json::String(m)) if m == "replace" => LayoutMode::Replace,
+            Some(Json::String(m)) if m == "append" => LayoutMode::Append,
+            Some(Json::String(m)) if m == "prepend" => LayoutMode::Prepend,
+            Some(_) => {
+                return Err(RenderErrorReason::HashTypeMismatchForName(
+                    "content",
+                    "mode".to_owned(),
+                    "\"replace\", \"append\" or \"prepend\"".to_owned(),
+                )
+                .into());
+            }
+        };
+
+        let content = render_block(h, r, ctx, rc)?;
+        rc.add_layout_content(name, LayoutContent { mode, content });
+        Ok(())
+    }
+}
+
+pub static EXTEND_HELPER: ExtendHelper = ExtendHelper;
+pub static BLOCK_HELPER: BlockHelper = BlockHelper;
+pub static CONTENT_HELPER: ContentHelper = ContentHelper;
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::RenderErrorReason;
+    use crate::context::Context;
+    use crate::error::RenderError;
+    use crate::helpers::{HelperDef, HelperResult};
+    use crate::output::{Output, StringOutput};
+    use crate::registry::Registry;
+    use crate::render::{Helper, RenderContext, Renderable};
+
+    fn layouts() -> Registry<'static> {
+        let mut hbs = Registry::new();
+        hbs.register_layout_helpers();
+        hbs.register_template_string(
+            "base",
+            "<title>{{#block \"title\"}}Site{{/block}}</title>\
+             {{#block \"head\"}}<style/>{{/block}}\
+             <main>{{#block \"body\"}}{{/block}}</main>\
+             {{#if (content \"sidebar\")}}<aside>{{#block \"sidebar\"}}{{/block}}</aside>{{/if}}",
+        )
+        .unwrap();
+        hbs
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut hbs = layouts();
+        hbs.register_template_string(
+            "page",
+            "{{#extend \"base\"}}\
+             {{#content \"title\"}}{{title}}{{/content}}\
+             {{#content \"head\" mode=\"append\"}}<script/>{{/content}}\
+             {{#content \"body\"}}<p>{{body}}</p>{{/content}}\
+             {{/extend}}",
+        )
+        .unwrap();
+
+        let data = json!({"title": "Home", "body": "hello"});
+        assert_eq!(
+            hbs.render("page", &data).unwrap(),
+            "<title>Home</title><style/><script/><main><p>hello</p></main>"
+        );
+        assert_eq!(
+            hbs.render("base", &data).unwrap(),
+            "<title>Site</title><style/><main></main>"
+        );
+    }
+
+    #[test]
+    fn test_modes() {
+        let mut hbs = layouts();
+        hbs.register_template_string(
+            "page",
+            "{{#extend \"base\"}}\
+             {{#content \"title\" mode=\"prepend\"}}Home - {{/content}}\
+             {{#content \"head\" mode=\"replace\"}}<link/>{{/content}}\
+             {{#content \"sidebar\"}}menu{{/content}}\
+             {{#content \"sidebar\" mode=\"append\"}}!{{/content}}\
+             {{/extend}}",
+        )
+        .unwrap();
+        assert_eq!(
+            hbs.render("page", &()).unwrap(),
+            "<title>Home - Site</title><link/><main></main><aside>menu!</aside>"
+        );
+
+        hbs.register_template_string(
+            "invalid",
+            "{{#extend \"base\"}}{{#content \"title\" mode=\"after\"}}x{{/content}}{{/extend}}",
+        )
+        .unwrap();
+        let err = hbs.render("invalid", &()).unwrap_err();
+        assert!(matches!(
+            err.reason(),
+            RenderErrorReason::HashTypeMismatchForName("content", _, _)
+        ));
+    }
+
+    #[test]
+    fn test_nested_layouts() {
+        let mut hbs = layouts();
+        hbs.register_template_string(
+            "section",
+            "{{#extend \"base\"}}\
+             {{#content \"title\" mode=\"append\"}} - Docs{{/content}}\
+             {{#content \"body\"}}<nav/>{{#block \"body\"}}no page{{/block}}{{/content}}\
+             {{/extend}}",
+        )
+        .unwrap();
+        hbs.register_template_string(
+            "page",
+            "{{#extend \"section\"}}\
+             {{#content \"title\" mode=\"prepend\"}}Install{{/content}}\
+             {{#content \"body\"}}steps{{/content}}\
+             {{/extend}}",
+        )
+        .unwrap();
+
+        assert_eq!(
+            hbs.render("page", &()).unwrap(),
+            "<title>InstallSite - Docs</title><style/><main><nav/>steps</main>"
+        );
+        assert_eq!(
+            hbs.render("section", &()).unwrap(),
+            "<title>Site - Docs</title><style/><main><nav/>no page</main>"
+        );
+    }
+
+    #[test]
+    fn test_context_and_partials() {
+        let mut hbs = Registry::new();
+        hbs.register_layout_helpers();
+        hbs.register_template_string(
+            "layout",
+            "{{title}}: {{> item}} {{> @partial-block}} {{#block \"b\"}}{{name}}{{/block}}",
+        )
+        .unwrap();
+        hbs.register_template_string(
+            "page",
+            "{{#with user}}{{#extend \"layout\" title=\"Users\"}}\
+             {{#*inline \"item\"}}<{{name}}>{{/inline}}rest \
+             {{#content \"b\" mode=\"append\"}}!{{/content}}{{/extend}}{{/with}}\
+             {{#if (content \"b\")}}leaked{{/if}}",
+        )
+        .unwrap();
+
+        let data = json!({"user": {"name": "alice"}});
+        assert_eq!(
+            hbs.render("page", &data).unwrap(),
+            "Users: <alice> rest  alice!"
+        );
+
+        hbs.register_template_string("leak", "{{> page}}{{> item}}")
+            .unwrap();
+        assert!(matches!(
+            hbs.render("leak", &data).unwrap_err().reason(),
+            RenderErrorReason::PartialNotFound(name) if name == "item"
+        ));
+    }
+
+    #[test]
+    fn test_missing_layout() {
+        let mut hbs = Registry::new();
+        hbs.register_layout_helpers();
+        hbs.register_template_string("page", "{{#extend \"nope\"}}{{/extend}}")
+            .unwrap();
+        assert!(matches!(
+            hbs.render("page", &()).unwrap_err().reason(),
+            RenderErrorReason::PartialNotFound(name) if name == "nope"
+        ));
+
+        hbs.register_template_string("self", "{{#extend \"self\"}}{{/extend}}")
+            .unwrap();
+        assert!(matches!(
+            hbs.render("self", &()).unwrap_err().reason(),
+            RenderErrorReason::CannotIncludeSelf
+        ));
+    }
+
+    #[test]
+    fn test_failed_content() {
+        fn fail(
+            _: &Helper<'_>,
+            _: &Registry<'_>,
+            _: &Context,
+            _: &mut RenderContext<'_, '_>,
+            _: &mut dyn Output,
+        ) -> HelperResult {
+            Err(RenderErrorReason::Other("fail".to_owned()).into())
+        }
+
+        // renders its block, or `failed` when it fails
+        struct Recover;
+
+        impl HelperDef for Recover {
+            fn call<'reg: 'rc, 'rc>(
+                &self,
+                h: &Helper<'rc>,
+                r: &'reg Registry<'reg>,
+                ctx: &'rc Context,
+                rc: &mut RenderContext<'reg, 'rc>,
+                out: &mut dyn Output,
+            ) -> HelperResult {
+                let mut block = StringOutput::new();
+                match h.template().map(|t| t.render(r, ctx, rc, &mut block)) {
+                    Some(Err(_)) => out.write("failed")?,
+                    _ => out.write(&block.into_string().map_err(RenderError::from)?)?,
+                }
+                Ok(())
+            }
+        }
+
+        let mut hbs = layouts();
+        hbs.register_helper("fail", Box::new(fail));
+        hbs.register_helper("recover", Box::new(Recover));
+        hbs.register_template_string(
+            "page",
+            "{{#extend \"base\"}}{{#content \"title\"}}{{fail}}{{/content}}{{/extend}}",
+        )
+        .unwrap();
+        assert!(matches!(
+            hbs.render("page", &()).unwrap_err().reason(),
+            RenderErrorReason::Other(msg) if msg == "fail"
+        ));
+
+        // the failed extend leaves no layout for the content after it
+        hbs.register_template_string(
+            "recovered",
+            "{{#recover}}{{> page}}{{/recover}} \
+             {{#content \"title\"}}leaked{{/content}}{{#block \"title\"}}Site{{/block}}",
+        )
+        .unwrap();
+        assert_eq!(hbs.render("recovered", &()).unwrap(), "failed Site");
+    }
+
+    #[test]
+    fn test_not_registered_by_default() {
+        let hbs = Registry::new();
+        let data = json!({"content": "c", "block": "b"});
+        assert_eq!(hbs.render_template("{{content}}", &data).unwrap(), "c");
+        assert!(matches!(
+            hbs.render_template("{{#block}}{{.}}{{/block}}", &data)
+                .unwrap_err()
+                .reason(),
+            RenderErrorReason::HelperNotFound(name) if name == "block"
+        ));
+    }
+}
@@ -7,36 +7,42 @@ use crate::json::value::JsonRender;
 use crate::output::Output;
 use crate::registry::Registry;
 use crate::render::{Helper, RenderContext};
-#[cfg(not(feature = "no_logging"))]
+#[cfg(all(not(feature = "no_logging"), not(feature = "tracing")))]
 use log::Level;
 #[cfg(not(feature = "no_logging"))]
 use std::str::FromStr;
+#[cfg(all(not(feature = "no_logging"), feature = "tracing"))]
+use tracing::Level;
 
 #[derive(Clone, Copy)]
 pub struct LogHelper;
 
 #[cfg(not(feature = "no_logging"))]
+fn params_to_log(h: &Helper<'_>) -> String {
+    h.params()
+        .iter()
+        .map(|p| {
+            if let Some(relative_path) = p.relative_path() {
+                format!("{}: {}", &relative_path, p.value().render())
+            } else {
+                p.value().render()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(all(not(feature = "no_logging"), not(feature = "tracing")))]
 impl HelperDef for LogHelper {
     fn call<'reg: 'rc, 'rc>(
         &self,
         h: &Helper<'rc>,
         _: &'reg Registry<'reg>,
         _: &'rc Context,
-        _: &mut RenderContext<'reg, 'rc>,
+        rc: &mut RenderContext<'reg, 'rc>,
         _: &mut dyn Output,
     ) -> HelperResult {
-        let param_to_log = h
-            .params()
-            .iter()
-            .map(|p| {
-                if let Some(relative_path) = p.relative_path() {
-                    format!("{}: {}", &relative_path, p.value().render())
-                } else {
-                    p.value().render()
-                }
-            })
-            .collect::<Vec<String>>()
-            .join(", ");
+        let param_to_log = params_to_log(h);
 
         let level = h
             .hash_get("level")
@@ -44,7 +50,10 @@ impl HelperDef for LogHelper {
             .unwrap_or("info");
 
         if let Ok(log_level) = Level::from_str(level) {
-            log!(log_level, "{}", param_to_log);
+            // `render_async` logs on its last render only
+            if !rc.skip_effects() {
+                log!(log_level, "{}", param_to_log);
+            }
         } else {
             return Err(RenderErrorReason::InvalidLoggingLevel(level.to_string()).into());
         }
@@ -52,6 +61,144 @@ impl HelperDef for LogHelper {
     }
 }
 
+/// With the `tracing` feature, `{{log}}` emits a `tracing` event. Its message
+/// is the parameters, and each other hash argument than `level` is a field of
+/// the event. A `message` hash argument is the `hash.message` field, to keep
+/// it apart from the message.
+#[cfg(all(not(feature = "no_logging"), feature = "tracing"))]
+impl HelperDef for LogHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Registry<'reg>,
+        _: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        _: &mut dyn Output,
+    ) -> HelperResult {
+        let param_to_log = params_to_log(h);
+
+        let level = h
+            .hash_get("level")
+            .and_then(|v| v.value().as_str())
+            .unwrap_or("info");
+        let level = Level::from_str(level)
+            .map_err(|_| RenderErrorReason::InvalidLoggingLevel(level.to_string()))?;
+        if rc.skip_effects() {
+            return Ok(());
+        }
+
+        let fields: Vec<_> = h
+            .hash()
+            .iter()
+            .filter(|(k, _)| **k != "level")
+            .map(|(k, v)| match *k {
+                "message" => ("hash.message", v.value()),
+                k => (k, v.value()),
+            })
+            .collect();
+        log_event::emit(level, &param_to_log, &fields);
+        Ok(())
+    }
+}
+
+/// `tracing` events with fields named at render time
+#[cfg(all(not(feature = "no_logging"), feature = "tracing"))]
+mod log_event {
+    use std::collections::HashMap;
+    use std::iter;
+    use std::sync::{LazyLock, Mutex, OnceLock, PoisonError};
+
+    use serde_json::Value as Json;
+    use tracing::field::{FieldSet, Value, display};
+    use tracing::level_filters::{LevelFilter, STATIC_MAX_LEVEL};
+    use tracing::{Event, Level, Metadata};
+    use tracing_core::callsite::{self, Callsite, Identifier};
+    use tracing_core::metadata::Kind;
+    use tracing_core::subscriber::Interest;
+
+    /// The callsite of the events at a level with some fields. The fields of
+    /// a callsite are static, so there is one for each set of hash keys used
+    /// with `{{log}}`, created on first use and never freed.
+    struct LogCallsite {
+        metadata: OnceLock<Metadata<'static>>,
+    }
+
+    impl Callsite for LogCallsite {
+        fn set_interest(&self, _: Interest) {}
+
+        fn metadata(&self) -> &Metadata<'_> {
+            self.metadata.get().expect("metadata is set on creation")
+        }
+    }
+
+    type Callsites = HashMap<(Level, Vec<String>), &'static LogCallsite>;
+
+    fn callsite(level: Level, keys: &[&str]) -> &'static LogCallsite {
+        static CALLSITES: LazyLock<Mutex<Callsites>> = LazyLock::new(Default::default);
+
+        let mut callsites = CALLSITES.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = (level, keys.iter().map(|k| (*k).to_owned()).collect());
+        callsites.entry(key).or_insert_with(|| {
+            let names: Vec<&'static str> = iter::once("message")
+                .chain(keys.iter().map(|k| &*Box::leak(Box::<str>::from(*k))))
+                .collect();
+            let callsite = Box::leak(Box::new(LogCallsite {
+                metadata: OnceLock::new(),
+            }));
+            let fields = FieldSet::new(Box::leak(names.into_boxed_slice()), Identifier(callsite));
+            let _ = callsite.metadata.set(Metadata::new(
+                concat!("event ", file!(), ":", line!()),
+                module_path!(),
+                level,
+                Some(file!()),
+                Some(line!()),
+                Some(module_path!()),
+                fields,
+                Kind::EVENT,
+            ));
+            callsite::register(callsite);
+            callsite
+        })
+    }
+
+    fn field_value(value: &Json) -> Box<dyn Value + '_> {
+        match value {
+            Json::Bool(b) => Box::new(*b),
+            Json::Number(n) => match (n.as_u64(), n.as_i64(), n.as_f64()) {
+                (Some(n), _, _) => Box::new(n),
+                (_, Some(n), _) => Box::new(n),
+                (_, _, Some(n)) => Box::new(n),
+                _ => Box::new(display(n)),
+            },
+            Json::String(s) => Box::new(s.as_str()),
+            _ => Box::new(display(value)),
+        }
+    }
+
+    /// Emits an event with `message` and a field for each of `fields`
+    pub(super) fn emit(level: Level, message: &str, fields: &[(&str, &Json)]) {
+        if level > STATIC_MAX_LEVEL || level > LevelFilter::current() {
+            return;
+        }
+        let keys: Vec<_> = fields.iter().map(|(k, _)| *k).collect();
+        let metadata = callsite(level, &keys).metadata();
+
+        tracing::dispatcher::get_default(|dispatch| {
+            if !dispatch.enabled(metadata) {
+                return;
+            }
+            let message = display(message);
+            let values: Vec<_> = fields.iter().map(|(_, v)| field_value(v)).collect();
+            let values: Vec<Option<&dyn Value>> = iter::once(&message as &dyn Value)
+                .chain(values.iter().map(|v| &**v as &dyn Value))
+                .map(Some)
+                .collect();
+            let values = metadata.fields().value_set_all(&values);
+            dispatch.event(&Event::new(metadata, &values));
+        });
+    }
+}
+
 #[cfg(feature = "no_logging")]
 impl HelperDef for LogHelper {
     fn call<'reg: 'rc, 'rc>(
@@ -102,6 +249,165 @@ mod test {
         assert!(r2.is_ok());
     }
 
+    #[cfg(all(feature = "tracing", not(feature = "no_logging")))]
+    mod tracing_subscriber {
+        use std::fmt::{Debug, Write};
+        use std::sync::Mutex;
+
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        struct Fields(String);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                let _ = write!(self.0, " {}={:?}", field.name(), value);
+            }
+        }
+
+        /// Records the events, with the spans they are in
+        #[derive(Default)]
+        pub(super) struct Recorder {
+            spans: Mutex<Vec<String>>,
+            stack: Mutex<Vec<usize>>,
+            pub(super) events: Mutex<Vec<String>>,
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut fields = Fields(span.metadata().name().to_owned());
+                span.record(&mut fields);
+                let mut spans = self.spans.lock().unwrap();
+                spans.push(fields.0);
+                Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                let spans = self.spans.lock().unwrap();
+                let mut line: Vec<_> = self
+                    .stack
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|i| spans[*i - 1].clone())
+                    .collect();
+                let mut fields = Fields(event.metadata().level().to_string());
+                event.record(&mut fields);
+                line.push(fields.0);
+                self.events.lock().unwrap().push(line.join(" > "));
+            }
+
+            fn enter(&self, span: &Id) {
+                self.stack.lock().unwrap().push(span.into_u64() as usize);
+            }
+
+            fn exit(&self, _: &Id) {
+                self.stack.lock().unwrap().pop();
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "tracing", not(feature = "no_logging")))]
+    fn test_log_tracing() {
+        use std::sync::Arc;
+
+        let mut handlebars = Registry::new();
+        handlebars
+            .register_template_string("item", "\n{{log name level=\"warn\" id=id}}")
+            .unwrap();
+        handlebars
+            .register_template_string("t", "{{#each this}}{{> item}}{{/each}}")
+            .unwrap();
+
+        let recorder = Arc::new(tracing_subscriber::Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            handlebars
+                .render("t", &serde_json::json!([{"name": "a", "id": 1}]))
+                .unwrap();
+        });
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            ["render template=\"t\" \
+                 > helper name=\"each\" template=\"t\" line=1 \
+                 > partial name=\"item\" template=\"t\" line=1 \
+                 > helper name=\"log\" template=\"item\" line=2 \
+                 > WARN message=name: a id=1"]
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "tracing", not(feature = "no_logging")))]
+    fn test_log_tracing_fields() {
+        use std::sync::Arc;
+
+        let mut handlebars = Registry::new();
+        handlebars
+            .register_template_string(
+                "t",
+                "{{#each this}}{{log \"item\" id=id name=name new=new tags=tags}}{{/each}}\
+                 {{log \"done\" level=\"debug\"}}",
+            )
+            .unwrap();
+
+        let recorder = Arc::new(tracing_subscriber::Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            handlebars
+                .render(
+                    "t",
+                    &serde_json::json!([
+                        {"id": 1, "name": "a", "new": true, "tags": ["x"]},
+                        {"id": -2, "name": "b", "new": false, "tags": []},
+                    ]),
+                )
+                .unwrap();
+        });
+        let events = recorder.events.lock().unwrap();
+        let events: Vec<_> = events
+            .iter()
+            .map(|e| e.rsplit(" > ").next().unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "INFO message=item id=1 name=\"a\" new=true tags=[\"x\"]",
+                "INFO message=item id=-2 name=\"b\" new=false tags=[]",
+                "DEBUG message=done",
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "tracing", not(feature = "no_logging")))]
+    fn test_log_tracing_message_field() {
+        use std::sync::Arc;
+
+        let handlebars = Registry::new();
+        let recorder = Arc::new(tracing_subscriber::Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            handlebars
+                .render_template("{{log \"x\" message=\"y\"}}", &())
+                .unwrap();
+        });
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| e.rsplit(" > ").next().unwrap())
+                .collect::<Vec<_>>(),
+            ["INFO message=x hash.message=\"y\""]
+        );
+    }
+
     #[test]
     #[cfg(feature = "no_logging")]
     fn test_log_helper() {
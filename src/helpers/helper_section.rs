@@ -0,0 +1,184 @@
+use serde_json::value::Value as Json;
+
+use super::block_util::create_block;
+use crate::RenderErrorReason;
+use crate::block::BlockContext;
+use crate::context::Context;
+use crate::helpers::{HelperDef, HelperResult};
+use crate::json::value::{PathAndJson, ScopedJson, to_json};
+use crate::output::Output;
+use crate::registry::Registry;
+use crate::render::{Helper, RenderContext, Renderable};
+use crate::util::copy_on_push_vec;
+
+/// Section, for blocks without helper like `{{#people}}...{{/people}}`
+///
+/// The block is rendered once for each item of a non-empty list, with
+/// `@index`, `@first` and `@last` set, once with the current context for
+/// `true`, and once with the value as context for other values. The inverse
+/// is rendered for `false`, `null`, an empty list or a missing value.
+#[derive(Clone, Copy)]
+pub(crate) struct SectionHelper;
+
+impl HelperDef for SectionHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Registry<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        // registered as `blockHelperMissing`, the section is on the value
+        // of the name of the block
+        let missing;
+        let value = match h.param(0) {
+            Some(value) => value,
+            None if h.is_block() => {
+                let value = match rc.context() {
+                    Some(rc_context) => {
+                        ScopedJson::Derived(rc.evaluate(&rc_context, h.name())?.as_json().clone())
+                    }
+                    None => rc.evaluate(ctx, h.name())?,
+                };
+                missing = PathAndJson::new(Some(h.name().to_owned()), value);
+                &missing
+            }
+            None => return Err(RenderErrorReason::ParamNotFoundForIndex("section", 0).into()),
+        };
+
+        let falsy = value.is_value_missing()
+            || matches!(value.value(), Json::Null | Json::Bool(false))
+            || matches!(value.value(), Json::Array(list) if list.is_empty());
+        if falsy {
+            return match h.inverse() {
+                Some(t) => t.render(r, ctx, rc, out),
+                None => Ok(()),
+            };
+        }
+
+        let Some(t) = h.template() else {
+            return Ok(());
+        };
+        match value.value() {
+            Json::Bool(true) => t.render(r, ctx, rc, out),
+            Json::Array(list) => {
+                let len = list.len();
+                for (i, v) in list.iter().enumerate() {
+                    let mut block = BlockContext::new();
+                    block.set_local_var("first", to_json(i == 0));
+                    block.set_local_var("last", to_json(i == len - 1));
+                    block.set_local_var("index", to_json(i));
+                    if let Some(p) = value.context_path() {
+                        *block.base_path_mut() = copy_on_push_vec(p, i.to_string());
+                    } else {
+                        block.set_base_value(v.clone());
+                    }
+
+                    rc.push_block(block);
+                    t.render(r, ctx, rc, out)?;
+                    rc.pop_block();
+                }
+                Ok(())
+            }
+            _ => {
+                rc.push_block(create_block(value));
+                t.render(r, ctx, rc, out)?;
+                rc.pop_block();
+                Ok(())
+            }
+        }
+    }
+}
+
+pub(crate) static SECTION_HELPER: SectionHelper = SectionHelper;
+
+#[cfg(test)]
+mod test {
+    use crate::error::RenderErrorReason;
+    use crate::registry::Registry;
+    use crate::testing::TestHandlebars;
+
+    #[test]
+    fn test_sections() {
+        let mut hbs = Registry::new();
+        hbs.set_mustache_compat(true);
+
+        let data = json!({
+            "t": true,
+            "f": false,
+            "list": [{"n": 1}, {"n": 2}],
+            "empty": [],
+            "obj": {"n": "o"},
+            "s": "str",
+            "n": 0,
+        });
+        hbs.assert_render_template(
+            "{{#t}}t{{n}}{{/t}}{{#f}}f{{/f}}{{#list}}{{n}}{{/list}}{{#empty}}e{{/empty}}\
+             {{#obj}}{{n}}{{/obj}}{{#s}}{{.}}{{/s}}{{#missing}}m{{/missing}}",
+            &data,
+            "t012ostr",
+        );
+        hbs.assert_render_template(
+            "{{^t}}t{{/t}}{{^f}}f{{/f}}{{^list}}l{{/list}}{{^empty}}e{{/empty}}\
+             {{^missing}}m{{/missing}}{{#f}}1{{else}}2{{/f}}",
+            &data,
+            "fem2",
+        );
+        // helpers still take precedence over sections
+        hbs.assert_render_template("{{#if f}}1{{else}}2{{/if}}{{^if t}}3{{/if}}", &data, "2");
+    }
+
+    #[test]
+    fn test_context_stack_lookup() {
+        let mut hbs = Registry::new();
+        hbs.set_mustache_compat(true);
+
+        let data = json!({
+            "a": {"b": {}},
+            "b": {"c": "root"},
+            "name": "outer",
+            "items": [{"x": 1}, {"x": 2, "name": "inner"}],
+        });
+        hbs.assert_render_template(
+            "{{#items}}{{x}}{{name}},{{/items}}{{#a}}[{{b.c}}]{{/a}}",
+            &data,
+            "1outer,2inner,[]",
+        );
+    }
+
+    #[test]
+    fn test_missing_partial() {
+        let mut hbs = Registry::new();
+        assert!(hbs.render_template("[{{> missing}}]", &json!({})).is_err());
+        hbs.set_mustache_compat(true);
+        hbs.assert_render_template("[{{> missing}}]", &json!({}), "[]");
+    }
+
+    #[test]
+    fn test_block_without_helper_fails_by_default() {
+        let hbs = Registry::new();
+        let err = hbs
+            .render_template("{{#list}}{{.}}{{/list}}", &json!({"list": [1]}))
+            .unwrap_err();
+        assert!(matches!(
+            err.reason(),
+            RenderErrorReason::HelperNotFound(name) if name == "list"
+        ));
+    }
+
+    #[test]
+    fn test_registered_section_helper() {
+        let mut hbs = Registry::new();
+        hbs.register_section_helper();
+
+        let data = json!({"list": [1, 2], "obj": {"n": "o"}, "f": false});
+        hbs.assert_render_template(
+            "{{#list}}{{@index}}{{.}}{{/list}}{{#obj}}{{n}}{{/obj}}{{#f}}f{{else}}e{{/f}}",
+            &data,
+            "0112oe",
+        );
+        // helpers still take precedence over sections
+        hbs.assert_render_template("{{#if list}}1{{/if}}", &data, "1");
+    }
+}
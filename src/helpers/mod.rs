@@ -172,9 +172,11 @@ mod block_util;
 mod helper_each;
 pub(crate) mod helper_extras;
 mod helper_if;
+pub(crate) mod helper_layout;
 mod helper_log;
 mod helper_lookup;
 mod helper_raw;
+pub(crate) mod helper_section;
 mod helper_with;
 #[cfg(feature = "script_helper")]
 pub(crate) mod scripting;
@@ -0,0 +1,549 @@
+use std::fmt;
+
+use serde::ser::{self, Serialize, Serializer};
+use serde_json::Map;
+use serde_json::value::{Value as Json, to_value};
+
+use crate::error::{RenderError, RenderErrorReason};
+
+/// Serialize the value at `path` of `value` to JSON, the keys of objects and
+/// indices of arrays as they would be in `serde_json::to_value(value)`.
+///
+/// Only the value at the path is converted, the other fields and items are
+/// serialized into nothing. As with JSON data, a non-numeric index into a
+/// sequence is an error and a path into a scalar is missing.
+pub(crate) fn lookup<T>(value: &T, path: &[String]) -> Result<Option<Json>, RenderError>
+where
+    T: Serialize + ?Sized,
+{
+    lookup_in(value, path).map_err(|e| match e {
+        Error::Index(p) => RenderErrorReason::InvalidJsonIndex(p).into(),
+        Error::Serde(e) => RenderErrorReason::SerdeError(e).into(),
+    })
+}
+
+fn lookup_in<T>(value: &T, path: &[String]) -> Result<Option<Json>, Error>
+where
+    T: Serialize + ?Sized,
+{
+    if path.is_empty() {
+        to_value(value).map(Some).map_err(Error::Serde)
+    } else {
+        value.serialize(PathSerializer { path })
+    }
+}
+
+fn parse_index(p: &str) -> Result<usize, Error> {
+    p.parse().map_err(|_| Error::Index(p.to_owned()))
+}
+
+// keys of maps as strings, like serde_json does for object keys
+fn key_string<T>(key: &T) -> Result<String, Error>
+where
+    T: Serialize + ?Sized,
+{
+    match to_value(key).map_err(Error::Serde)? {
+        Json::String(s) => Ok(s),
+        Json::Number(n) => Ok(n.to_string()),
+        Json::Bool(b) => Ok(b.to_string()),
+        _ => Err(ser::Error::custom("key must be a string")),
+    }
+}
+
+#[derive(Debug)]
+enum Error {
+    Index(String),
+    Serde(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Index(p) => write!(f, "invalid array index {p}"),
+            Error::Serde(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Serde(<serde_json::Error as ser::Error>::custom(msg))
+    }
+}
+
+// serializes the value at a non-empty path
+struct PathSerializer<'p> {
+    path: &'p [String],
+}
+
+impl<'p> PathSerializer<'p> {
+    // a compound value entered by the path
+    fn entries(&self) -> Compound<'p> {
+        Compound::Entry {
+            key: &self.path[0],
+            rest: &self.path[1..],
+            matched: false,
+            found: None,
+        }
+    }
+
+    fn items(&self) -> Result<Compound<'p>, Error> {
+        Ok(Compound::Item {
+            index: parse_index(&self.path[0])?,
+            rest: &self.path[1..],
+            next: 0,
+            found: None,
+        })
+    }
+
+    // the content of an enum variant, serialized as `{variant: content}`
+    fn variant(&self, variant: &str) -> Option<PathSerializer<'p>> {
+        (self.path[0] == variant).then(|| PathSerializer {
+            path: &self.path[1..],
+        })
+    }
+}
+
+macro_rules! scalars {
+    ($($method:ident($ty:ty)),*) => {
+        $(
+            fn $method(self, _v: $ty) -> Result<Self::Ok, Self::Error> {
+                Ok(None)
+            }
+        )*
+    };
+}
+
+impl<'p> Serializer for PathSerializer<'p> {
+    type Ok = Option<Json>;
+    type Error = Error;
+
+    type SerializeSeq = Compound<'p>;
+    type SerializeTuple = Compound<'p>;
+    type SerializeTupleStruct = Compound<'p>;
+    type SerializeTupleVariant = Compound<'p>;
+    type SerializeMap = Compound<'p>;
+    type SerializeStruct = Compound<'p>;
+    type SerializeStructVariant = Compound<'p>;
+
+    scalars!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_unit_struct(&'static str)
+    );
+
+    // bytes are serialized as an array of numbers
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let index = parse_index(&self.path[0])?;
+        Ok(v.get(index)
+            .filter(|_| self.path.len() == 1)
+            .map(|b| Json::from(*b)))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self.variant(variant) {
+            Some(s) => lookup_in(value, s.path),
+            None => Ok(None),
+        }
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        self.items()
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.items()
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.items()
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        match self.variant(variant) {
+            Some(s) if s.path.is_empty() => Ok(Compound::Items(Vec::with_capacity(len))),
+            Some(s) => s.items(),
+            None => Ok(Compound::Skip),
+        }
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(self.entries())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(self.entries())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        match self.variant(variant) {
+            Some(s) if s.path.is_empty() => Ok(Compound::Entries(Map::new(), None)),
+            Some(s) => Ok(s.entries()),
+            None => Ok(Compound::Skip),
+        }
+    }
+}
+
+enum Compound<'p> {
+    // off the path
+    Skip,
+    // the whole sequence, when the path ends at an enum variant
+    Items(Vec<Json>),
+    // the whole object, when the path ends at an enum variant, and the
+    // pending map key
+    Entries(Map<String, Json>, Option<String>),
+    // the item at `index`, then `rest` of the path in it
+    Item {
+        index: usize,
+        rest: &'p [String],
+        next: usize,
+        found: Option<Json>,
+    },
+    // the entry at `key`, then `rest` of the path in it
+    Entry {
+        key: &'p str,
+        rest: &'p [String],
+        matched: bool,
+        found: Option<Json>,
+    },
+}
+
+impl Compound<'_> {
+    fn item<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            Compound::Items(items) => items.push(to_value(value).map_err(Error::Serde)?),
+            Compound::Item {
+                index,
+                rest,
+                next,
+                found,
+            } => {
+                if next == index {
+                    *found = lookup_in(value, rest)?;
+                }
+                *next += 1;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn key<T>(&mut self, k: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            Compound::Entries(_, pending) => *pending = Some(key_string(k)?),
+            Compound::Entry { key, matched, .. } => *matched = key_string(k)? == *key,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn value<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            Compound::Entries(entries, pending) => {
+                if let Some(k) = pending.take() {
+                    entries.insert(k, to_value(value).map_err(Error::Serde)?);
+                }
+            }
+            Compound::Entry {
+                rest,
+                matched: true,
+                found,
+                ..
+            } => *found = lookup_in(value, rest)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn field<T>(&mut self, k: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            Compound::Entries(entries, _) => {
+                entries.insert(k.to_owned(), to_value(value).map_err(Error::Serde)?);
+            }
+            Compound::Entry {
+                key, rest, found, ..
+            } if *key == k => *found = lookup_in(value, rest)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Option<Json> {
+        match self {
+            Compound::Skip => None,
+            Compound::Items(items) => Some(Json::Array(items)),
+            Compound::Entries(entries, _) => Some(Json::Object(entries)),
+            Compound::Item { found, .. } | Compound::Entry { found, .. } => found,
+        }
+    }
+}
+
+macro_rules! items {
+    ($($trait:ident::$method:ident),*) => {
+        $(
+            impl ser::$trait for Compound<'_> {
+                type Ok = Option<Json>;
+                type Error = Error;
+
+                fn $method<T>(&mut self, value: &T) -> Result<(), Self::Error>
+                where
+                    T: Serialize + ?Sized,
+                {
+                    self.item(value)
+                }
+
+                fn end(self) -> Result<Self::Ok, Self::Error> {
+                    Ok(self.finish())
+                }
+            }
+        )*
+    };
+}
+
+items!(
+    SerializeSeq::serialize_element,
+    SerializeTuple::serialize_element,
+    SerializeTupleStruct::serialize_field,
+    SerializeTupleVariant::serialize_field
+);
+
+macro_rules! fields {
+    ($($trait:ident),*) => {
+        $(
+            impl ser::$trait for Compound<'_> {
+                type Ok = Option<Json>;
+                type Error = Error;
+
+                fn serialize_field<T>(
+                    &mut self,
+                    key: &'static str,
+                    value: &T,
+                ) -> Result<(), Self::Error>
+                where
+                    T: Serialize + ?Sized,
+                {
+                    self.field(key, value)
+                }
+
+                fn end(self) -> Result<Self::Ok, Self::Error> {
+                    Ok(self.finish())
+                }
+            }
+        )*
+    };
+}
+
+fields!(SerializeStruct, SerializeStructVariant);
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = Option<Json>;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.key(key)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.value(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use serde_derive::Serialize;
+    use serde_json::{Value as Json, to_value};
+
+    use super::lookup;
+    use crate::error::RenderErrorReason;
+
+    #[derive(Serialize)]
+    enum Shape {
+        Unit,
+        Newtype(u32),
+        Tuple(u32, u32),
+        Struct { w: u32 },
+    }
+
+    #[derive(Serialize)]
+    struct Data {
+        name: &'static str,
+        tags: Vec<&'static str>,
+        pair: (bool, Option<u8>),
+        counts: BTreeMap<u8, u32>,
+        shapes: Vec<Shape>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        missing: Option<u8>,
+    }
+
+    #[test]
+    fn test_lookup_matches_to_value() {
+        let data = Data {
+            name: "n",
+            tags: vec!["a", "b"],
+            pair: (true, Some(1)),
+            counts: [(1, 10), (2, 20)].into_iter().collect(),
+            shapes: vec![
+                Shape::Unit,
+                Shape::Newtype(1),
+                Shape::Tuple(2, 3),
+                Shape::Struct { w: 4 },
+            ],
+            missing: None,
+        };
+        let json = to_value(&data).unwrap();
+
+        let paths = [
+            "",
+            "name",
+            "name.0",
+            "tags",
+            "tags.1",
+            "tags.2",
+            "pair.0",
+            "pair.1",
+            "counts",
+            "counts.2",
+            "counts.3",
+            "shapes",
+            "shapes.0",
+            "shapes.0.Unit",
+            "shapes.1.Newtype",
+            "shapes.1.Tuple",
+            "shapes.2.Tuple",
+            "shapes.2.Tuple.1",
+            "shapes.3.Struct",
+            "shapes.3.Struct.w",
+            "shapes.3.Struct.h",
+            "missing",
+            "nothing.at.all",
+        ];
+        for path in paths {
+            let path: Vec<String> = path
+                .split('.')
+                .filter(|p| !p.is_empty())
+                .map(String::from)
+                .collect();
+            let expected = path
+                .iter()
+                .try_fold(&json, |v, p| match v {
+                    Json::Array(l) => l.get(p.parse::<usize>().unwrap()),
+                    Json::Object(m) => m.get(p),
+                    _ => None,
+                })
+                .cloned();
+            assert_eq!(lookup(&data, &path).unwrap(), expected, "{path:?}");
+        }
+    }
+
+    #[test]
+    fn test_lookup_invalid_index() {
+        let err = lookup(&vec![1, 2], &["first".to_owned()]).unwrap_err();
+        assert!(matches!(
+            err.reason(),
+            RenderErrorReason::InvalidJsonIndex(p) if p == "first"
+        ));
+    }
+}
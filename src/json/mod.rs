@@ -1,2 +1,3 @@
+pub(crate) mod lookup;
 pub(crate) mod path;
 pub(crate) mod value;
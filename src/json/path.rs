@@ -2,16 +2,19 @@ use std::iter::Peekable;
 
 use pest::Parser;
 use pest::iterators::Pair;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::RenderErrorReason;
 use crate::error::RenderError;
 use crate::grammar::{HandlebarsParser, Rule};
 
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[non_exhaustive]
 pub enum PathSeg {
     Named(String),
-    Ruled(Rule),
+    Ruled(#[cfg_attr(feature = "serde", serde(with = "crate::template_serde::rule"))] Rule),
 }
 
 /// Represents the Json path in templates.
@@ -19,6 +22,7 @@ pub enum PathSeg {
 /// It can be either a local variable like `@first`, `../@index`,
 /// or a normal relative path like `a/b/c`.
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Path {
     Relative((Vec<PathSeg>, String)),
     Local((usize, String, String)),
@@ -63,7 +67,6 @@ impl Path {
         Path::Relative((segs, name_segs.join("/")))
     }
 
-    // for test only
     pub(crate) fn segs(&self) -> Option<&[PathSeg]> {
         match self {
             Path::Relative((segs, _)) => Some(segs),
@@ -111,12 +114,16 @@ where
             Rule::path_up => {
                 path_stack.push(PathSeg::Ruled(Rule::path_up));
             }
-            Rule::path_id | Rule::path_raw_id => {
+            Rule::path_id => {
                 let name = n.as_str();
                 if name != "this" {
                     path_stack.push(PathSeg::Named(name.to_string()));
                 }
             }
+            // `[this]` is a key named `this`
+            Rule::path_raw_id => {
+                path_stack.push(PathSeg::Named(n.as_str().to_string()));
+            }
             _ => {}
         }
 
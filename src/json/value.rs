@@ -1,6 +1,11 @@
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
 use serde::Serialize;
 use serde_json::value::{Value as Json, to_value};
 
+use crate::error::{RenderError, RenderErrorReason};
+
 pub(crate) static DEFAULT_VALUE: Json = Json::Null;
 
 /// A JSON wrapper designed for handlebars internal use case
@@ -50,6 +55,120 @@ impl<'rc> ScopedJson<'rc> {
     }
 }
 
+/// Items of a sequence inserted by `Context::insert_iter`, serialized as they
+/// are pulled
+pub(crate) type JsonItems = Box<dyn Iterator<Item = Result<Json, RenderError>>>;
+
+type SerializedItems = Box<dyn Iterator<Item = Result<Json, serde_json::Error>>>;
+
+enum Source {
+    Value(Mutex<Option<Box<dyn FnOnce() -> Json + Send>>>),
+    Items(Box<dyn Fn() -> SerializedItems + Send + Sync>),
+}
+
+/// A JSON value computed by a closure the first time it's used, see
+/// `Context::insert_lazy` and `Context::insert_iter`
+pub struct LazyJson {
+    source: Source,
+    // the message of the serialization error of an item of a sequence
+    value: OnceLock<Result<Json, String>>,
+}
+
+impl LazyJson {
+    pub(crate) fn new<F>(f: F) -> LazyJson
+    where
+        F: FnOnce() -> Json + Send + 'static,
+    {
+        LazyJson {
+            source: Source::Value(Mutex::new(Some(Box::new(f)))),
+            value: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn from_items<F, I>(f: F) -> LazyJson
+    where
+        F: Fn() -> I + Send + Sync + 'static,
+        I: IntoIterator,
+        I::IntoIter: 'static,
+        I::Item: Serialize,
+    {
+        let items =
+            move || -> SerializedItems { Box::new(f().into_iter().map(|item| to_value(&item))) };
+        LazyJson {
+            source: Source::Items(Box::new(items)),
+            value: OnceLock::new(),
+        }
+    }
+
+    /// Return the value, computed by the first call
+    ///
+    /// The items of a sequence are collected into an array, `null` if one
+    /// of them fails to serialize. Renders fail with the error instead.
+    pub fn get(&self) -> &Json {
+        self.try_get().unwrap_or(&DEFAULT_VALUE)
+    }
+
+    /// Return the value, computed by the first call, or the error
+    /// serializing an item of a sequence
+    pub(crate) fn try_get(&self) -> Result<&Json, RenderError> {
+        let value = self.value.get_or_init(|| match &self.source {
+            Source::Value(compute) => {
+                // `null` if the closure panicked in an earlier call
+                let compute = compute.lock().ok().and_then(|mut f| f.take());
+                Ok(compute.map_or(Json::Null, |f| f()))
+            }
+            Source::Items(items) => items()
+                .collect::<Result<Vec<_>, _>>()
+                .map(Json::Array)
+                .map_err(|e| e.to_string()),
+        });
+        value.as_ref().map_err(|e| serde_error(e))
+    }
+
+    /// Fail with the error of the value, if it's computed already
+    pub(crate) fn check_computed(&self) -> Result<(), RenderError> {
+        match self.value.get() {
+            Some(Err(e)) => Err(serde_error(e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// A new iterator over the items, if it's a sequence inserted by
+    /// `Context::insert_iter`
+    pub(crate) fn items(&self) -> Option<JsonItems> {
+        match &self.source {
+            Source::Items(items) => {
+                Some(Box::new(items().map(|item| {
+                    item.map_err(|e| RenderErrorReason::SerdeError(e).into())
+                })))
+            }
+            Source::Value(_) => None,
+        }
+    }
+
+    /// Whether it's a sequence inserted by `Context::insert_iter`
+    pub(crate) fn is_items(&self) -> bool {
+        matches!(self.source, Source::Items(_))
+    }
+
+    /// Return the value if it's computed already
+    pub fn get_computed(&self) -> Option<&Json> {
+        self.value.get().and_then(|v| v.as_ref().ok())
+    }
+}
+
+fn serde_error(message: &str) -> RenderError {
+    RenderErrorReason::SerdeError(serde::ser::Error::custom(message)).into()
+}
+
+impl fmt::Debug for LazyJson {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyJson")
+            .field("value", &self.value.get())
+            .finish_non_exhaustive()
+    }
+}
+
 impl<'rc> From<Json> for ScopedJson<'rc> {
     fn from(v: Json) -> ScopedJson<'rc> {
         ScopedJson::Derived(v)
@@ -62,6 +181,9 @@ impl<'rc> From<Json> for ScopedJson<'rc> {
 pub struct PathAndJson<'rc> {
     relative_path: Option<String>,
     value: ScopedJson<'rc>,
+    // a sequence inserted by `Context::insert_iter` and its full path, its
+    // items are collected when the value is used
+    items: Option<(&'rc LazyJson, Vec<String>)>,
 }
 
 impl<'rc> PathAndJson<'rc> {
@@ -69,6 +191,20 @@ impl<'rc> PathAndJson<'rc> {
         PathAndJson {
             relative_path,
             value,
+            items: None,
+        }
+    }
+
+    /// A sequence inserted by `Context::insert_iter` at `path`
+    pub(crate) fn from_items(
+        relative_path: Option<String>,
+        items: &'rc LazyJson,
+        path: Vec<String>,
+    ) -> PathAndJson<'rc> {
+        PathAndJson {
+            relative_path,
+            value: ScopedJson::Missing,
+            items: Some((items, path)),
         }
     }
 
@@ -80,12 +216,35 @@ impl<'rc> PathAndJson<'rc> {
 
     /// Returns full path to this value if any
     pub fn context_path(&self) -> Option<&Vec<String>> {
-        self.value.context_path()
+        match &self.items {
+            Some((_, path)) => Some(path),
+            None => self.value.context_path(),
+        }
     }
 
     /// Returns the value
     pub fn value(&self) -> &Json {
-        self.value.as_json()
+        match &self.items {
+            Some((items, _)) => items.get(),
+            None => self.value.as_json(),
+        }
+    }
+
+    /// Returns the value, or the error collecting the items of a sequence
+    pub(crate) fn try_value(&self) -> Result<&Json, RenderError> {
+        match &self.items {
+            Some((items, _)) => items.try_get(),
+            None => Ok(self.value.as_json()),
+        }
+    }
+
+    /// Fail with the error of the items of a sequence collected since it was
+    /// looked up
+    pub(crate) fn check_computed(&self) -> Result<(), RenderError> {
+        match &self.items {
+            Some((items, _)) => items.check_computed(),
+            None => Ok(()),
+        }
     }
 
     /// Returns the value, if it is a constant. Otherwise returns None.
@@ -96,13 +255,19 @@ impl<'rc> PathAndJson<'rc> {
         }
     }
 
+    /// Returns the items of a sequence inserted by `Context::insert_iter`,
+    /// without collecting them
+    pub(crate) fn items(&self) -> Option<JsonItems> {
+        self.items.as_ref().and_then(|(items, _)| items.items())
+    }
+
     /// Test if value is missing
     pub fn is_value_missing(&self) -> bool {
-        self.value.is_missing()
+        self.items.is_none() && self.value.is_missing()
     }
 
     pub fn render(&self) -> String {
-        self.value.render()
+        self.value().render()
     }
 }
 
@@ -125,9 +125,25 @@
 //! ### Compatibility with original JavaScript version
 //!
 //! This implementation is **not fully compatible** with the original JavaScript version.
-//!
-//! First of all, mustache blocks are not supported. I suggest you to use `#if` and `#each` for
-//! the same functionality.
+//! The spec tests of handlebars.js are vendored in `tests/fixtures/handlebars` and run by
+//! `tests/handlebars_spec.rs`, which lists the cases that still differ. In summary:
+//!
+//! | Feature | Status |
+//! |---------|--------|
+//! | Expressions, paths, `this`, `..`, `@root`, literal segments `[a b]` | Supported |
+//! | HTML escaping, `{{{raw}}}` and `{{&raw}}` | Supported |
+//! | Blocks without helper `{{#list}}`, inverted sections `{{^list}}` | With [`Registry::register_section_helper`] |
+//! | `if`, `unless`, `each`, `with`, `lookup`, `log`, block params | Supported |
+//! | `@index`, `@key`, `@first`, `@last`, `@../index` | Supported |
+//! | Partials, partial blocks, inline partials, dynamic partials | Supported |
+//! | Whitespace control `~` and standalone lines, on tags and comments | Supported |
+//! | Subexpressions and hashes | Supported |
+//! | Truthiness of empty objects | Empty objects are falsy |
+//! | String literals and decimals as paths, like `{{"foo bar"}}` | Not supported |
+//! | `..` as a parameter, like `{{> p others=..}}` | Not supported |
+//! | `@partial-block` | Rendered in the context of the caller |
+//! | Properties of JavaScript values, like `length` of a string | Not supported |
+//! | Helpers and data as JavaScript functions | Use [`HelperDef`] |
 //!
 //! Feel free to file an issue on [github](https://github.com/sunng87/handlebars-rust/issues) if
 //! you find missing features.
@@ -187,6 +203,9 @@
 //! * Feature `rust-embed` enables template loading
 //!   `register_embed_templates` from embedded resources in rust struct
 //!   generated with `RustEmbed`.
+//! * Feature `serde` implements `Serialize` and `Deserialize` for compiled
+//!   templates, so they can be cached and registered with `register_template`
+//!   without parsing them again. See `VersionedTemplate`.
 //!
 //! ### Rendering Something
 //!
@@ -354,6 +373,9 @@
 //!   (See [the handlebarjs documentation](https://handlebarsjs.com/guide/builtin-helpers.html#lookup) on how to use this helper.)
 //! * `{{> ...}}` include template by its name
 //! * `{{log ...}}` log value with rust logger, default level: INFO. Currently you cannot change the level.
+//!   With the feature `tracing`, it emits a `tracing` event instead, with its
+//!   hash arguments as fields, and renders, partials and helpers run in
+//!   `tracing` spans with the template name and line.
 //! * Boolean helpers that can be used in `if` as subexpression, for example `{{#if (gt 2 1)}} ...`:
 //!   * `eq`
 //!   * `ne`
@@ -371,6 +393,29 @@
 //! Handlebars.js' partial system is fully supported in this implementation.
 //! Check [example](https://github.com/sunng87/handlebars-rust/blob/master/examples/partials.rs#L49) for details.
 //!
+//! In addition, a partial block can fill several named slots of the partial
+//! with the `{{#*slot "name"}}...{{/slot}}` decorator. The partial renders
+//! them with `{{> @slot.name}}`, which renders nothing when the slot is not
+//! defined, or `{{#> @slot.name}}fallback{{/@slot.name}}`. Like
+//! `@partial-block`, slots are rendered in the context of the caller.
+//!
+//! ```
+//! # use handlebars::Handlebars;
+//! let mut hbs = Handlebars::new();
+//! hbs.register_template_string(
+//!     "card",
+//!     "<div>{{#> @slot.header}}untitled{{/@slot.header}}|{{> @partial-block}}</div>",
+//! )
+//! .unwrap();
+//! let page = hbs
+//!     .render_template(
+//!         "{{#> card}}{{#*slot \"header\"}}<h1>{{title}}</h1>{{/slot}}body{{/card}}",
+//!         &serde_json::json!({"title": "Hi"}),
+//!     )
+//!     .unwrap();
+//! assert_eq!(page, "<div><h1>Hi</h1>|body</div>");
+//! ```
+//!
 //! ### String (or Case) Helpers
 //!
 //! [Handlebars] supports helpers for converting string cases for example converting a value to
@@ -414,41 +459,66 @@ extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
 
+pub use self::analysis::{DependencyGraph, PartialReference, TemplateReferences};
+pub use self::async_helper::{AsyncHelperDef, AsyncHelperFuture};
 pub use self::block::{BlockContext, BlockParamHolder, BlockParams};
-pub use self::context::Context;
+pub use self::context::{Context, ContextValue, SerializeValue};
+pub use self::coverage::Coverage;
 pub use self::decorators::DecoratorDef;
 pub use self::error::{RenderError, RenderErrorReason, TemplateError, TemplateErrorReason};
+pub use self::format::{FormatOptions, FormatOptionsBuilder, QuoteStyle, format_template};
 pub use self::helpers::{HelperDef, HelperResult};
 pub use self::json::path::{Path, PathSeg};
-pub use self::json::value::{JsonRender, JsonTruthy, PathAndJson, ScopedJson, to_json};
+pub use self::json::value::{JsonRender, JsonTruthy, LazyJson, PathAndJson, ScopedJson, to_json};
+pub use self::limits::{RenderLimit, RenderLimits, RenderLimitsBuilder};
 pub use self::local_vars::LocalVars;
+pub use self::observer::RenderObserver;
 pub use self::output::{Output, StringOutput, WriteOutput};
+pub use self::profile::{ProfileEntry, ProfileKind, RenderProfile};
 #[cfg(feature = "dir_source")]
 pub use self::registry::{DirectorySourceOptions, DirectorySourceOptionsBuilder};
 pub use self::registry::{EscapeFn, Registry as Handlebars, html_escape, no_escape};
 pub use self::render::{Decorator, Evaluable, Helper, RenderContext, Renderable};
+pub use self::render_options::{CancellationToken, RenderOptions, RenderOptionsBuilder};
 pub use self::template::Template;
+#[cfg(feature = "serde")]
+pub use self::template::VersionedTemplate;
 
 #[doc(hidden)]
 pub use self::serde_json::Value as JsonValue;
 
 #[macro_use]
 mod macros;
+mod analysis;
+mod async_helper;
 mod block;
 mod context;
+mod coverage;
 mod decorators;
+mod delimiters;
 mod error;
+mod format;
 mod grammar;
 mod helpers;
 mod json;
+mod limits;
 mod local_vars;
+#[doc(hidden)]
+pub mod macro_support;
+mod observer;
 mod output;
 mod partial;
+mod printer;
+mod profile;
+mod recovery;
 mod registry;
 mod render;
+mod render_options;
 mod sources;
 mod support;
 pub mod template;
+#[cfg(feature = "serde")]
+mod template_serde;
 mod util;
 
 /// Test helpers shared by this crate's tests and, behind the `testing` cargo
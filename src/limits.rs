@@ -0,0 +1,367 @@
+use std::cell::Cell;
+use std::fmt;
+use std::io::Error as IOError;
+use std::rc::Rc;
+use std::string::FromUtf8Error;
+
+use derive_builder::Builder;
+
+use crate::error::{RenderError, RenderErrorReason};
+use crate::output::{Output, StringOutput};
+
+/// Limits of the resources used by a render, for templates that are not
+/// trusted
+///
+/// A render exceeding a limit fails with `RenderErrorReason::LimitExceeded`.
+/// All limits are disabled by default. Set them for all renders with
+/// `Registry::set_render_limits`, or for a single one with
+/// `Registry::render_with_limits`.
+///
+/// ```
+/// use handlebars::{Handlebars, RenderErrorReason, RenderLimit, RenderLimitsBuilder};
+///
+/// let mut hbs = Handlebars::new();
+/// hbs.set_render_limits(
+///     RenderLimitsBuilder::default()
+///         .max_depth(16)
+///         .max_output_bytes(1 << 20)
+///         .build()
+///         .unwrap(),
+/// );
+/// hbs.register_template_string("ping", "{{> pong}}").unwrap();
+/// hbs.register_template_string("pong", "{{> ping}}").unwrap();
+///
+/// let err = hbs.render("ping", &()).unwrap_err();
+/// assert!(matches!(err.reason(), RenderErrorReason::LimitExceeded(RenderLimit::Depth(16))));
+/// ```
+#[non_exhaustive]
+#[derive(Builder, Clone, Debug, Default, PartialEq, Eq)]
+#[builder(default)]
+pub struct RenderLimits {
+    /// Maximum bytes written to the output, and to the buffers of content
+    /// rendered before being written, like partial blocks, slots, layout
+    /// blocks and subexpressions. Buffered content counts again once written.
+    #[builder(setter(strip_option))]
+    pub max_output_bytes: Option<usize>,
+    /// Maximum nesting of partials and block helpers
+    #[builder(setter(strip_option))]
+    pub max_depth: Option<usize>,
+    /// Maximum iterations of `{{#each}}` loops, all loops of the render
+    /// included
+    #[builder(setter(strip_option))]
+    pub max_iterations: Option<usize>,
+    /// Maximum template elements rendered, text and expressions alike. This
+    /// bounds the whole work of a render.
+    #[builder(setter(strip_option))]
+    pub max_steps: Option<u64>,
+}
+
+impl RenderLimits {
+    pub(crate) fn is_unlimited(&self) -> bool {
+        *self == RenderLimits::default()
+    }
+}
+
+/// A limit of `RenderLimits`, with its value
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderLimit {
+    OutputBytes(usize),
+    Depth(usize),
+    Iterations(usize),
+    Steps(u64),
+}
+
+impl fmt::Display for RenderLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderLimit::OutputBytes(max) => write!(f, "max_output_bytes ({max})"),
+            RenderLimit::Depth(max) => write!(f, "max_depth ({max})"),
+            RenderLimit::Iterations(max) => write!(f, "max_iterations ({max})"),
+            RenderLimit::Steps(max) => write!(f, "max_steps ({max})"),
+        }
+    }
+}
+
+/// Usage of the limited resources during a render
+#[derive(Debug)]
+pub(crate) struct LimitState {
+    limits: RenderLimits,
+    output_bytes: Cell<usize>,
+    depth: Cell<usize>,
+    iterations: Cell<usize>,
+    steps: Cell<u64>,
+}
+
+/// Steps and iterations counted by a render, carried over the passes of
+/// `render_async`
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct LimitUsage {
+    steps: u64,
+    iterations: usize,
+}
+
+fn exceeded(limit: RenderLimit) -> RenderError {
+    RenderErrorReason::LimitExceeded(limit).into()
+}
+
+impl LimitState {
+    pub(crate) fn new(limits: RenderLimits) -> LimitState {
+        LimitState {
+            limits,
+            output_bytes: Cell::new(0),
+            depth: Cell::new(0),
+            iterations: Cell::new(0),
+            steps: Cell::new(0),
+        }
+    }
+
+    pub(crate) fn usage(&self) -> LimitUsage {
+        LimitUsage {
+            steps: self.steps.get(),
+            iterations: self.iterations.get(),
+        }
+    }
+
+    /// Start counting from the usage of previous renders
+    pub(crate) fn resume(&self, usage: LimitUsage) {
+        self.steps.set(usage.steps);
+        self.iterations.set(usage.iterations);
+    }
+
+    /// Count a rendered element, and fail once the output went over its
+    /// limit
+    pub(crate) fn step(&self) -> Result<(), RenderError> {
+        self.check_output()?;
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        match self.limits.max_steps {
+            Some(max) if steps > max => Err(exceeded(RenderLimit::Steps(max))),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_output(&self) -> Result<(), RenderError> {
+        match self.limits.max_output_bytes {
+            Some(max) if self.output_bytes.get() > max => {
+                Err(exceeded(RenderLimit::OutputBytes(max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn count_output(&self, len: usize) -> Result<(), IOError> {
+        let written = self.output_bytes.get() + len;
+        self.output_bytes.set(written);
+        match self.limits.max_output_bytes {
+            Some(max) if written > max => {
+                Err(IOError::other(format!("output exceeds {max} bytes")))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn enter(&self) -> Result<(), RenderError> {
+        let depth = self.depth.get() + 1;
+        self.depth.set(depth);
+        match self.limits.max_depth {
+            Some(max) if depth > max => Err(exceeded(RenderLimit::Depth(max))),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn leave(&self) {
+        self.depth.set(self.depth.get().saturating_sub(1));
+    }
+
+    pub(crate) fn iteration(&self) -> Result<(), RenderError> {
+        let iterations = self.iterations.get() + 1;
+        self.iterations.set(iterations);
+        match self.limits.max_iterations {
+            Some(max) if iterations > max => Err(exceeded(RenderLimit::Iterations(max))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Output counting the bytes written, it fails once `max_output_bytes` is
+/// exceeded
+pub(crate) struct LimitedOutput<'a> {
+    inner: &'a mut dyn Output,
+    state: Rc<LimitState>,
+}
+
+impl<'a> LimitedOutput<'a> {
+    pub(crate) fn new(inner: &'a mut dyn Output, state: Rc<LimitState>) -> LimitedOutput<'a> {
+        LimitedOutput { inner, state }
+    }
+}
+
+impl Output for LimitedOutput<'_> {
+    fn write(&mut self, seg: &str) -> Result<(), IOError> {
+        self.state.count_output(seg.len())?;
+        self.inner.write(seg)
+    }
+}
+
+/// Buffer of content rendered before being written to the output, counting
+/// the bytes written against the limits of the render, if any
+pub(crate) struct BufferOutput {
+    buf: StringOutput,
+    state: Option<Rc<LimitState>>,
+}
+
+impl BufferOutput {
+    pub(crate) fn new(state: Option<Rc<LimitState>>) -> BufferOutput {
+        BufferOutput {
+            buf: StringOutput::new(),
+            state,
+        }
+    }
+
+    pub(crate) fn into_string(self) -> Result<String, FromUtf8Error> {
+        self.buf.into_string()
+    }
+}
+
+impl Output for BufferOutput {
+    fn write(&mut self, seg: &str) -> Result<(), IOError> {
+        if let Some(state) = &self.state {
+            state.count_output(seg.len())?;
+        }
+        self.buf.write(seg)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::registry::Registry;
+    use crate::{RenderErrorReason, RenderLimit, RenderLimits, RenderLimitsBuilder};
+
+    fn limit(hbs: &Registry<'_>, name: &str, data: &serde_json::Value) -> Option<RenderLimit> {
+        match hbs.render(name, data) {
+            Err(e) => match e.reason() {
+                RenderErrorReason::LimitExceeded(limit) => Some(*limit),
+                other => panic!("unexpected error {other:?}"),
+            },
+            Ok(_) => None,
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("ping", "{{> pong}}").unwrap();
+        hbs.register_template_string("pong", "{{> ping}}").unwrap();
+        hbs.register_template_string(
+            "nested",
+            "{{#if a}}{{#if a}}{{#if a}}x{{/if}}{{/if}}{{/if}}",
+        )
+        .unwrap();
+        hbs.register_template_string("each", "{{#each a}}{{#each ../a}}.{{/each}}{{/each}}")
+            .unwrap();
+        hbs.register_template_string("big", "{{#each a}}{{../s}}{{/each}}")
+            .unwrap();
+
+        let data = json!({"a": [1, 2, 3], "s": "0123456789"});
+        hbs.set_render_limits(
+            RenderLimitsBuilder::default()
+                .max_depth(3)
+                .max_iterations(10)
+                .max_output_bytes(25)
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(limit(&hbs, "ping", &data), Some(RenderLimit::Depth(3)));
+        assert_eq!(limit(&hbs, "nested", &data), None);
+        assert_eq!(
+            limit(&hbs, "each", &data),
+            Some(RenderLimit::Iterations(10))
+        );
+        assert_eq!(
+            limit(&hbs, "big", &data),
+            Some(RenderLimit::OutputBytes(25))
+        );
+
+        hbs.set_render_limits(RenderLimitsBuilder::default().max_steps(5).build().unwrap());
+        assert_eq!(limit(&hbs, "big", &data), None);
+        assert_eq!(limit(&hbs, "each", &data), Some(RenderLimit::Steps(5)));
+    }
+
+    #[test]
+    fn test_recursive_partial_depth() {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("t", "{{#*inline \"self\"}}.{{> self}}{{/inline}}{{> self}}")
+            .unwrap();
+        hbs.set_render_limits(RenderLimitsBuilder::default().max_depth(8).build().unwrap());
+
+        assert_eq!(limit(&hbs, "t", &json!({})), Some(RenderLimit::Depth(8)));
+    }
+
+    #[test]
+    fn test_buffered_output_bytes() {
+        let mut hbs = Registry::new();
+        hbs.register_layout_helpers();
+        // the content never reaches the output, it is buffered and dropped
+        hbs.register_template_string(
+            "partial-block",
+            "{{#> p}}{{#each a}}{{../s}}{{/each}}{{/p}}",
+        )
+        .unwrap();
+        hbs.register_template_string("p", "").unwrap();
+        hbs.register_template_string(
+            "slot",
+            "{{#> p}}{{#*slot \"x\"}}{{#each a}}{{../s}}{{/each}}{{/slot}}{{/p}}",
+        )
+        .unwrap();
+        hbs.register_template_string(
+            "layout",
+            "{{#extend \"p\"}}{{#content \"x\"}}{{#each a}}{{../s}}{{/each}}{{/content}}\
+             {{/extend}}",
+        )
+        .unwrap();
+
+        let data = json!({"a": [1, 2, 3], "s": "0123456789"});
+        hbs.set_render_limits(
+            RenderLimitsBuilder::default()
+                .max_output_bytes(25)
+                .build()
+                .unwrap(),
+        );
+        for name in ["partial-block", "slot", "layout"] {
+            assert_eq!(
+                limit(&hbs, name, &data),
+                Some(RenderLimit::OutputBytes(25)),
+                "{name}"
+            );
+        }
+
+        hbs.set_render_limits(RenderLimits::default());
+        for name in ["partial-block", "slot", "layout"] {
+            assert_eq!(limit(&hbs, name, &data), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_render_with_limits() {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("t", "{{#each this}}{{this}}{{/each}}")
+            .unwrap();
+        let limits = RenderLimitsBuilder::default()
+            .max_iterations(2)
+            .build()
+            .unwrap();
+
+        let err = hbs
+            .render_with_limits("t", &[1, 2, 3], &limits)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error rendering \"t\" line 1, col 1: Render limit exceeded: max_iterations (2)"
+        );
+        assert_eq!(hbs.render_with_limits("t", &[1, 2], &limits).unwrap(), "12");
+        assert_eq!(hbs.render("t", &[1, 2, 3]).unwrap(), "123");
+    }
+}
@@ -0,0 +1,149 @@
+//! Support for templates compiled by the `handlebars-macros` crate
+//!
+//! The macros parse templates at compile time and expand to calls of the
+//! functions below, which build the `Template` without parsing it again. This
+//! module is not part of the public API and may change at any time.
+
+use std::collections::HashMap;
+
+use serde_json::value::{Map, Value as Json};
+
+use crate::grammar::Rule;
+use crate::json::path::PathSeg;
+use crate::template::{
+    BlockParam, DecoratorTemplate, ExpressionSpans, HelperTemplate, Parameter, SourceSpan,
+    Subexpression, Template, TemplateElement, TemplateMapping,
+};
+
+pub fn template(
+    name: Option<&str>,
+    elements: Vec<TemplateElement>,
+    mapping: &[(usize, usize)],
+    spans: &[SourceSpan],
+) -> Template {
+    Template {
+        name: name.map(ToOwned::to_owned),
+        elements,
+        mapping: mapping
+            .iter()
+            .map(|&(line, col)| TemplateMapping(line, col))
+            .collect(),
+        spans: spans.to_vec(),
+    }
+}
+
+pub fn helper(
+    name: Parameter,
+    params: Vec<Parameter>,
+    hash: HashMap<String, Parameter>,
+    block_param: Option<BlockParam>,
+    block: bool,
+    spans: ExpressionSpans,
+    indent_before_write: bool,
+) -> HelperTemplate {
+    HelperTemplate {
+        name,
+        params,
+        hash,
+        block_param,
+        template: None,
+        inverse: None,
+        block,
+        chain: false,
+        spans,
+        indent_before_write,
+    }
+}
+
+pub fn decorator(
+    name: Parameter,
+    params: Vec<Parameter>,
+    hash: HashMap<String, Parameter>,
+    spans: ExpressionSpans,
+    indent_before_write: bool,
+) -> DecoratorTemplate {
+    DecoratorTemplate {
+        name,
+        params,
+        hash,
+        template: None,
+        indent: None,
+        spans,
+        indent_before_write,
+    }
+}
+
+pub fn subexpression(
+    name: Parameter,
+    params: Vec<Parameter>,
+    hash: HashMap<String, Parameter>,
+    spans: ExpressionSpans,
+) -> Subexpression {
+    let h = helper(name, params, hash, None, false, spans, false);
+    Subexpression {
+        element: Box::new(TemplateElement::Expression(Box::new(h))),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn spans(
+    element: SourceSpan,
+    open_tag: SourceSpan,
+    name: SourceSpan,
+    params: Vec<SourceSpan>,
+    hash: HashMap<String, SourceSpan>,
+    block_param: Option<SourceSpan>,
+    inverse_tag: Option<SourceSpan>,
+    close_tag: Option<SourceSpan>,
+) -> ExpressionSpans {
+    ExpressionSpans {
+        element,
+        open_tag,
+        name,
+        params,
+        hash,
+        block_param,
+        inverse_tag,
+        close_tag,
+    }
+}
+
+pub fn hash<T>(hash: Vec<(&str, T)>) -> HashMap<String, T> {
+    hash.into_iter().map(|(k, v)| (k.to_owned(), v)).collect()
+}
+
+pub fn helper_indent_before_write(h: &HelperTemplate) -> bool {
+    h.indent_before_write
+}
+
+pub fn decorator_indent_before_write(d: &DecoratorTemplate) -> bool {
+    d.indent_before_write
+}
+
+/// Name of the rule kept in a `PathSeg::Ruled`
+pub fn path_seg_rule(seg: &PathSeg) -> Option<&'static str> {
+    match seg {
+        PathSeg::Ruled(Rule::path_root) => Some("root"),
+        PathSeg::Ruled(Rule::path_up) => Some("up"),
+        PathSeg::Ruled(Rule::path_local) => Some("local"),
+        _ => None,
+    }
+}
+
+pub fn path_seg_ruled(rule: &str) -> PathSeg {
+    match rule {
+        "root" => PathSeg::Ruled(Rule::path_root),
+        "up" => PathSeg::Ruled(Rule::path_up),
+        "local" => PathSeg::Ruled(Rule::path_local),
+        other => unreachable!("unexpected path segment {other}"),
+    }
+}
+
+pub fn json_object(entries: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v))
+            .collect::<Map<String, Json>>(),
+    )
+}
@@ -0,0 +1,268 @@
+use serde_json::Value as Json;
+
+use crate::block::BlockContext;
+use crate::render::{Decorator, Helper};
+
+/// Callbacks on the steps of renders, to build step debuggers, tracing or
+/// audit logs
+///
+/// Set an observer with `Registry::set_render_observer`, it is called by all
+/// renders of the registry. All callbacks do nothing by default, so an
+/// observer only implements the ones it needs. The observer is shared by
+/// concurrent renders, use interior mutability to record steps.
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+///
+/// use handlebars::{Handlebars, JsonValue, RenderObserver};
+///
+/// #[derive(Default)]
+/// struct MissingPaths(Mutex<Vec<String>>);
+///
+/// impl RenderObserver for MissingPaths {
+///     fn lookup(&self, path: &str, value: Option<&JsonValue>) {
+///         if value.is_none() {
+///             self.0.lock().unwrap().push(path.to_owned());
+///         }
+///     }
+/// }
+///
+/// let missing = Arc::new(MissingPaths::default());
+/// let mut hbs = Handlebars::new();
+/// hbs.set_render_observer(Some(missing.clone()));
+///
+/// hbs.render_template("{{name}} {{nmae}}", &serde_json::json!({"name": "n"}))
+///     .unwrap();
+/// assert_eq!(*missing.0.lock().unwrap(), ["nmae"]);
+/// ```
+pub trait RenderObserver {
+    /// The template rendered by the registry starts rendering
+    fn enter_template(&self, _name: &str) {}
+
+    fn leave_template(&self, _name: &str) {}
+
+    /// A partial starts rendering, with `{{> name}}` or `{{#> name}}`
+    fn enter_partial(&self, _name: &str) {}
+
+    fn leave_partial(&self, _name: &str) {}
+
+    /// A helper is called, in a mustache, a block or a subexpression. Its
+    /// parameters and hash are evaluated.
+    fn helper(&self, _helper: &Helper<'_>) {}
+
+    /// A decorator is called, like `{{*inline}}` or `{{#*slot}}`. Its
+    /// parameters and hash are evaluated.
+    fn decorator(&self, _decorator: &Decorator<'_>) {}
+
+    /// A path is looked up, `value` is `None` when it is missing
+    fn lookup(&self, _path: &str, _value: Option<&Json>) {}
+
+    /// A block context is pushed, by a block helper or a partial
+    fn push_block(&self, _block: &BlockContext<'_>) {}
+
+    fn pop_block(&self, _block: &BlockContext<'_>) {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::{Value as Json, json};
+
+    use super::RenderObserver;
+    use crate::RenderErrorReason;
+    use crate::block::BlockContext;
+    use crate::registry::Registry;
+    use crate::render::{Decorator, Helper};
+
+    #[derive(Default)]
+    struct Steps(Mutex<Vec<String>>);
+
+    impl Steps {
+        fn push(&self, step: String) {
+            self.0.lock().unwrap().push(step);
+        }
+
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    impl RenderObserver for Steps {
+        fn enter_template(&self, name: &str) {
+            self.push(format!("enter {name}"));
+        }
+
+        fn leave_template(&self, name: &str) {
+            self.push(format!("leave {name}"));
+        }
+
+        fn enter_partial(&self, name: &str) {
+            self.push(format!("enter > {name}"));
+        }
+
+        fn leave_partial(&self, name: &str) {
+            self.push(format!("leave > {name}"));
+        }
+
+        fn helper(&self, h: &Helper<'_>) {
+            let params: Vec<_> = h.params().iter().map(|p| p.value().to_string()).collect();
+            let hash: Vec<_> = h
+                .hash()
+                .iter()
+                .map(|(k, v)| format!("{k}={}", v.value()))
+                .collect();
+            self.push(format!("helper {} {params:?} {hash:?}", h.name()));
+        }
+
+        fn decorator(&self, d: &Decorator<'_>) {
+            let params: Vec<_> = d.params().iter().map(|p| p.value().to_string()).collect();
+            self.push(format!("decorator {} {params:?}", d.name()));
+        }
+
+        fn lookup(&self, path: &str, value: Option<&Json>) {
+            match value {
+                Some(value) => self.push(format!("lookup {path} = {value}")),
+                None => self.push(format!("lookup {path} missing")),
+            }
+        }
+
+        fn push_block(&self, block: &BlockContext<'_>) {
+            self.push(format!("push {:?}", block.base_path()));
+        }
+
+        fn pop_block(&self, block: &BlockContext<'_>) {
+            self.push(format!("pop {:?}", block.base_path()));
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let steps = Arc::new(Steps::default());
+        let mut hbs = Registry::new();
+        hbs.set_render_observer(Some(steps.clone()));
+        hbs.register_template_string("user", "{{name}}{{age}}")
+            .unwrap();
+        hbs.register_template_string(
+            "t",
+            "{{#with user}}{{> user}}{{/with}}{{eq (len tags) 1 strict=true}}",
+        )
+        .unwrap();
+
+        let data = json!({"user": {"name": "n"}, "tags": ["a"]});
+        assert_eq!(hbs.render("t", &data).unwrap(), "ntrue");
+        assert_eq!(
+            steps.take(),
+            [
+                "enter t",
+                "lookup user = {\"name\":\"n\"}",
+                "helper with [\"{\\\"name\\\":\\\"n\\\"}\"] []",
+                "push [\"user\"]",
+                "push []",
+                "enter > user",
+                "lookup name = \"n\"",
+                "lookup age missing",
+                "leave > user",
+                "pop []",
+                "pop [\"user\"]",
+                "lookup tags = [\"a\"]",
+                "helper len [\"[\\\"a\\\"]\"] []",
+                "helper eq [\"1\", \"1\"] [\"strict=true\"]",
+                "leave t",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unset_observer() {
+        let steps = Arc::new(Steps::default());
+        let mut hbs = Registry::new();
+        hbs.set_render_observer(Some(steps.clone()));
+        hbs.register_template_string("t", "{{#if a}}{{a}}{{/if}}")
+            .unwrap();
+
+        hbs.render("t", &json!({"a": 1})).unwrap();
+        assert!(!steps.take().is_empty());
+
+        hbs.set_render_observer(None);
+        hbs.render("t", &json!({"a": 1})).unwrap();
+        assert!(steps.take().is_empty());
+    }
+
+    #[test]
+    fn test_observe_partial_block() {
+        let steps = Arc::new(Steps::default());
+        let mut hbs = Registry::new();
+        hbs.set_render_observer(Some(steps.clone()));
+        hbs.register_template_string("layout", "[{{> @partial-block}}]")
+            .unwrap();
+        hbs.register_template_string("t", "{{#> layout}}{{a}}{{/layout}}")
+            .unwrap();
+
+        assert_eq!(hbs.render("t", &json!({"a": 1})).unwrap(), "[1]");
+        assert_eq!(
+            steps.take(),
+            [
+                "enter t",
+                "push []",
+                "lookup a = 1",
+                "enter > layout",
+                "leave > layout",
+                "pop []",
+                "leave t",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observe_decorators() {
+        let steps = Arc::new(Steps::default());
+        let mut hbs = Registry::new();
+        hbs.set_render_observer(Some(steps.clone()));
+        hbs.register_template_string("t", "{{#*inline name}}{{a}}{{/inline}}{{> x}}")
+            .unwrap();
+
+        assert_eq!(hbs.render("t", &json!({"name": "x", "a": 1})).unwrap(), "1");
+        assert_eq!(
+            steps.take(),
+            [
+                "enter t",
+                "lookup name = \"x\"",
+                "decorator inline [\"\\\"x\\\"\"]",
+                "push []",
+                "enter > x",
+                "lookup a = 1",
+                "leave > x",
+                "pop []",
+                "leave t",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observe_failed_partial() {
+        let steps = Arc::new(Steps::default());
+        let mut hbs = Registry::new();
+        hbs.set_render_observer(Some(steps.clone()));
+        hbs.register_template_string("p", "{{> missing}}").unwrap();
+        hbs.register_template_string("t", "{{> p}}").unwrap();
+
+        let err = hbs.render("t", &json!({})).unwrap_err();
+        assert!(matches!(
+            err.reason(),
+            RenderErrorReason::PartialNotFound(name) if name == "missing"
+        ));
+        // every step entered is left
+        assert_eq!(
+            steps.take(),
+            [
+                "enter t",
+                "push []",
+                "enter > p",
+                "leave > p",
+                "pop []",
+                "leave t",
+            ]
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde_json::Value as Json;
 
@@ -6,14 +6,23 @@ use crate::block::BlockContext;
 use crate::context::{Context, merge_json};
 use crate::error::RenderError;
 use crate::output::Output;
+use crate::profile::ProfileKind;
 use crate::registry::Registry;
 use crate::render::{Decorator, RenderContext, Renderable};
 use crate::template::Template;
-use crate::{Path, RenderErrorReason, StringOutput};
+use crate::{Path, RenderErrorReason};
 
 pub(crate) const PARTIAL_BLOCK: &str = "@partial-block";
+pub(crate) const SLOT_PREFIX: &str = "@slot.";
 
-fn find_partial<'reg: 'rc, 'rc>(
+/// Rendered content of a partial block, and of the slots defined in it
+#[derive(Clone, Debug)]
+pub(crate) struct PartialBlockContent {
+    pub(crate) content: Option<String>,
+    pub(crate) slots: BTreeMap<String, String>,
+}
+
+pub(crate) fn find_partial<'reg: 'rc, 'rc>(
     rc: &RenderContext<'reg, 'rc>,
     r: &'reg Registry<'reg>,
     name: &str,
@@ -39,6 +48,20 @@ pub fn expand_partial<'reg: 'rc, 'rc>(
     ctx: &'rc Context,
     rc: &mut RenderContext<'reg, 'rc>,
     out: &mut dyn Output,
+) -> Result<(), RenderError> {
+    // recursive partials are bounded by the depth limit
+    rc.enter_nested()?;
+    let result = expand_partial_in(d, r, ctx, rc, out);
+    rc.leave_nested();
+    result
+}
+
+fn expand_partial_in<'reg: 'rc, 'rc>(
+    d: &Decorator<'rc>,
+    r: &'reg Registry<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
+    out: &mut dyn Output,
 ) -> Result<(), RenderError> {
     let tname = d.name();
 
@@ -64,6 +87,19 @@ pub fn expand_partial<'reg: 'rc, 'rc>(
             // no partial_block for this scope
             Err(RenderErrorReason::PartialBlockNotFound.into())
         }
+    } else if let Some(slot) = tname.strip_prefix(SLOT_PREFIX) {
+        if let Some(content) = rc.get_slot(slot) {
+            out.write(content)?;
+            Ok(())
+        } else if let Some(fallback) = d.template() {
+            let result = fallback.render(r, ctx, rc, out);
+            rc.set_current_template_name(current_template_before);
+            rc.set_indent_string(indent_before);
+            result
+        } else {
+            // slots are optional
+            Ok(())
+        }
     } else {
         // normal partial
         let original_partial = find_partial(rc, r, tname)?;
@@ -72,6 +108,8 @@ pub fn expand_partial<'reg: 'rc, 'rc>(
             partial
         } else if let Some(inner_template) = d.template() {
             inner_template
+        } else if r.mustache_compat() {
+            return Ok(());
         } else {
             return Err(RenderErrorReason::PartialNotFound(tname.to_owned()).into());
         };
@@ -107,29 +145,49 @@ pub fn expand_partial<'reg: 'rc, 'rc>(
             if let Some(relative_path) = p.relative_path() {
                 // path as parameter provided
                 if let Some(rc_context) = rc.context() {
-                    merge_json(
+                    Some(merge_json(
                         rc.evaluate(&rc_context, relative_path)?.as_json(),
                         &hash_ctx,
-                    )
+                    ))
                 } else {
-                    merge_json(rc.evaluate(ctx, relative_path)?.as_json(), &hash_ctx)
+                    Some(merge_json(
+                        rc.evaluate(ctx, relative_path)?.as_json(),
+                        &hash_ctx,
+                    ))
                 }
             } else {
                 // literal provided
-                merge_json(p.value(), &hash_ctx)
+                Some(merge_json(p.value(), &hash_ctx))
             }
+        } else if rc.is_value_path(ctx) {
+            // the data rendered from a `ContextValue` is navigated from the
+            // current path instead of being converted whole, the hash is in
+            // the block params
+            None
         } else {
             // use current path
             if let Some(rc_context) = rc.context() {
-                merge_json(
+                Some(merge_json(
                     rc.evaluate2(&rc_context, &Path::current())?.as_json(),
                     &hash_ctx,
-                )
+                ))
             } else {
-                merge_json(rc.evaluate2(ctx, &Path::current())?.as_json(), &hash_ctx)
+                Some(merge_json(
+                    rc.evaluate2(ctx, &Path::current())?.as_json(),
+                    &hash_ctx,
+                ))
             }
         };
-        partial_include_block.set_base_value(merged_context);
+        match merged_context {
+            Some(merged_context) => partial_include_block.set_base_value(merged_context),
+            None => {
+                if let Some(parent) = rc.block() {
+                    partial_include_block
+                        .base_path_mut()
+                        .clone_from(parent.base_path());
+                }
+            }
+        }
 
         // Push partial's context block (doesn't clear parent blocks)
         // This allows inline partials from parent blocks to remain accessible
@@ -139,22 +197,33 @@ pub fn expand_partial<'reg: 'rc, 'rc>(
         // the template itself
         if original_partial.is_some() {
             if let Some(current_parital_block) = d.template() {
-                let mut tmp_out = StringOutput::new();
+                let mut tmp_out = rc.buffer();
                 // render will also eval the block, so any inline directives will be
-                // evaluated
-                current_parital_block.render(r, ctx, rc, &mut tmp_out)?;
-                rc.push_partial_block(Some(tmp_out.into_string()?));
+                // evaluated, and `{{#*slot}}` directives collected
+                let enclosing_slots = rc.start_slots();
+                let rendered = current_parital_block.render(r, ctx, rc, &mut tmp_out);
+                let slots = rc.finish_slots(enclosing_slots);
+                rendered?;
+                rc.push_partial_block(Some(tmp_out.into_string()?), slots);
             } else {
-                rc.push_partial_block(None);
+                rc.push_partial_block(None, BTreeMap::new());
             }
         } else {
-            rc.push_partial_block(None);
+            rc.push_partial_block(None, BTreeMap::new());
         }
 
         // indent
         rc.set_indent_string(d.indent().cloned());
 
-        let result = partial.render(r, ctx, rc, out);
+        rc.observe(|o| o.enter_partial(tname));
+        #[cfg(feature = "tracing")]
+        let source_before = rc.enter_source_template(partial.name.as_deref());
+        let result = rc.profiled(ProfileKind::Partial, tname, |rc| {
+            partial.render(r, ctx, rc, out)
+        });
+        #[cfg(feature = "tracing")]
+        rc.leave_source_template(source_before);
+        rc.observe(|o| o.leave_partial(tname));
 
         // cleanup
         let trailing_newline = rc.get_trailine_newline();
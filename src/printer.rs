@@ -0,0 +1,510 @@
+use std::collections::HashMap;
+
+use serde_json::value::Value as Json;
+
+use crate::support::str::{ends_with_empty_line, starts_with_empty_line};
+use crate::template::TemplateElement::{
+    Comment, DecoratorBlock, DecoratorExpression, Expression, HelperBlock, HtmlExpression,
+    PartialBlock, PartialExpression, RawString,
+};
+use crate::template::{
+    BlockParam, DecoratorTemplate, HelperTemplate, Parameter, Subexpression, Template,
+    TemplateElement, sorted_hash,
+};
+
+impl Template {
+    /// Print this template back to handlebars source
+    ///
+    /// The output is semantically equivalent to the source this template was
+    /// compiled from: compiling it again renders the same result. It is not
+    /// byte-identical to the original source, because the information below
+    /// is resolved at compile time and not kept in the `Template`:
+    ///
+    /// * whitespace control (`~`) and standalone line removal, which are
+    ///   already applied to the surrounding `RawString` elements,
+    /// * comment style, `{{!-- --}}` is only used when the text requires it,
+    /// * quoting of string literals, which are always printed double-quoted,
+    /// * order of hash arguments, which are printed sorted by key.
+    ///
+    /// ```
+    /// use handlebars::Template;
+    ///
+    /// let t = Template::compile("{{#each items as |item|}}{{ item.name }}{{/each}}").unwrap();
+    /// assert_eq!(t.to_source(), "{{#each items as |item|}}{{item.name}}{{/each}}");
+    /// ```
+    pub fn to_source(&self) -> String {
+        let mut printer = Printer::default();
+        printer.template(self);
+        printer.buf
+    }
+}
+
+/// Print a single parameter, e.g. the name expression of a dynamic partial
+pub(crate) fn parameter_to_source(p: &Parameter) -> String {
+    let mut printer = Printer::default();
+    printer.parameter(p);
+    printer.buf
+}
+
+#[derive(Default)]
+struct Printer {
+    buf: String,
+    // set when the last tag written could be treated as a standalone line on
+    // re-parse, the parser will then strip the first line break of the text
+    // that follows
+    standalone_candidate: bool,
+    // set when the last tag written was a standalone line in the original
+    // source, a line break is written after it to keep it standalone
+    pending_line_break: bool,
+    tag_start: usize,
+}
+
+impl Printer {
+    fn template(&mut self, t: &Template) {
+        for e in &t.elements {
+            self.element(e);
+        }
+    }
+
+    fn element(&mut self, e: &TemplateElement) {
+        match e {
+            RawString(text) => self.raw_string(text),
+            Expression(ht) => {
+                self.open_tag("");
+                self.expression(ht);
+                self.close_tag("", false);
+            }
+            HtmlExpression(ht) => {
+                self.open_tag("{");
+                self.expression(ht);
+                self.close_tag("}", false);
+            }
+            HelperBlock(ht) => self.helper_block(ht),
+            DecoratorExpression(dt) => {
+                self.start_standalone(dt.indent_before_write);
+                self.open_tag("*");
+                self.decorator(dt, false);
+                self.close_tag("", true);
+                self.keep_standalone(dt.indent_before_write);
+            }
+            PartialExpression(dt) => {
+                self.start_standalone(dt.indent_before_write);
+                if let Some(ref indent) = dt.indent {
+                    if !self.buf.ends_with(indent.as_str()) {
+                        self.buf.push_str(indent);
+                    }
+                }
+                self.open_tag(">");
+                self.decorator(dt, true);
+                self.close_tag("", true);
+                self.keep_standalone(dt.indent_before_write);
+            }
+            DecoratorBlock(dt) => self.decorator_block(dt, "#*", false),
+            PartialBlock(dt) => self.decorator_block(dt, "#>", true),
+            Comment(text) => {
+                self.open_tag("");
+                if text.contains("}}") || text.starts_with("--") {
+                    self.buf.push_str("!--");
+                    self.buf.push_str(text);
+                    self.buf.push_str("--");
+                } else {
+                    self.buf.push('!');
+                    self.buf.push_str(text);
+                }
+                self.close_tag("", true);
+            }
+        }
+    }
+
+    fn raw_string(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.standalone_candidate && (self.pending_line_break || starts_with_empty_line(text)) {
+            // compensate the line break that is going to be stripped
+            self.buf.push('\n');
+        }
+        self.standalone_candidate = false;
+        self.pending_line_break = false;
+
+        let mut rest = text;
+        while let Some(idx) = rest.find("{{") {
+            self.buf.push_str(&rest[..idx]);
+            self.buf.push_str("\\{{");
+            rest = &rest[idx + 2..];
+        }
+        self.buf.push_str(rest);
+    }
+
+    fn open_tag(&mut self, prefix: &str) {
+        if self.pending_line_break {
+            self.buf.push('\n');
+            self.pending_line_break = false;
+        }
+        // backslashes right before a tag are read as an escape, double the
+        // last one to keep it literal
+        if self.buf.ends_with('\\') {
+            self.buf.push('\\');
+        }
+        self.standalone_candidate = false;
+        self.tag_start = self.buf.len();
+        self.buf.push_str("{{");
+        self.buf.push_str(prefix);
+    }
+
+    fn close_tag(&mut self, suffix: &str, standalone: bool) {
+        let line_start = ends_with_empty_line(&self.buf[..self.tag_start]);
+        self.buf.push_str(suffix);
+        self.buf.push_str("}}");
+        self.standalone_candidate = standalone && line_start;
+    }
+
+    // a tag that was standalone in the original source must start its own
+    // line. Break the line if the previous tag is standalone as well, the
+    // line break is then stripped again on parse.
+    fn start_standalone(&mut self, was_standalone: bool) {
+        if self.pending_line_break
+            || (was_standalone && self.standalone_candidate && !ends_with_empty_line(&self.buf))
+        {
+            self.buf.push('\n');
+            self.pending_line_break = false;
+        }
+    }
+
+    fn keep_standalone(&mut self, was_standalone: bool) {
+        self.pending_line_break = self.standalone_candidate && was_standalone;
+    }
+
+    fn helper_block(&mut self, ht: &HelperTemplate) {
+        if let Some(text) = raw_block_content(ht) {
+            self.open_tag("{{");
+            self.expression(ht);
+            self.close_tag("}}", true);
+            self.standalone_candidate = false;
+            self.buf.push_str(text);
+            self.open_tag("{{/");
+            self.name(&ht.name);
+            self.close_tag("}}", true);
+            return;
+        }
+
+        // an inverted section `{{^name}}` only has the inverse template
+        let inverted = ht.template.is_none() && ht.inverse.is_some() && !ht.chain;
+
+        self.start_standalone(ht.indent_before_write);
+        self.open_tag(if inverted { "^" } else { "#" });
+        self.expression(ht);
+        self.close_tag("", true);
+        self.keep_standalone(ht.indent_before_write);
+
+        if let Some(ref t) = ht.template {
+            self.template(t);
+        }
+
+        let mut inverse = ht.inverse.as_ref();
+        if inverted {
+            if let Some(t) = inverse.take() {
+                self.template(t);
+            }
+        }
+        while let Some(t) = inverse {
+            match chained_node(t) {
+                Some(node) => {
+                    self.open_tag("else ");
+                    self.expression(node);
+                    self.close_tag("", true);
+                    self.keep_standalone(node.indent_before_write);
+                    if let Some(ref t) = node.template {
+                        self.template(t);
+                    }
+                    inverse = node.inverse.as_ref();
+                }
+                None => {
+                    self.open_tag("else");
+                    self.close_tag("", true);
+                    self.template(t);
+                    inverse = None;
+                }
+            }
+        }
+
+        self.open_tag("/");
+        self.name(&ht.name);
+        self.close_tag("", true);
+    }
+
+    fn decorator_block(&mut self, dt: &DecoratorTemplate, prefix: &str, partial: bool) {
+        self.start_standalone(dt.indent_before_write);
+        self.open_tag(prefix);
+        self.decorator(dt, partial);
+        self.close_tag("", true);
+        self.keep_standalone(dt.indent_before_write);
+
+        if let Some(ref t) = dt.template {
+            self.template(t);
+        }
+
+        self.open_tag("/");
+        match dt.name {
+            Parameter::Subexpression(_) => {}
+            ref name if partial => self.partial_name(name),
+            ref name => self.name(name),
+        }
+        self.close_tag("", true);
+    }
+
+    fn expression(&mut self, ht: &HelperTemplate) {
+        self.name(&ht.name);
+        self.params(&ht.params, &ht.hash);
+        match ht.block_param {
+            Some(BlockParam::Single(ref p)) => {
+                self.buf.push_str(" as |");
+                self.parameter(p);
+                self.buf.push('|');
+            }
+            Some(BlockParam::Pair((ref p1, ref p2))) => {
+                self.buf.push_str(" as |");
+                self.parameter(p1);
+                self.buf.push(' ');
+                self.parameter(p2);
+                self.buf.push('|');
+            }
+            None => {}
+        }
+    }
+
+    fn decorator(&mut self, dt: &DecoratorTemplate, partial: bool) {
+        if partial {
+            self.buf.push(' ');
+            self.partial_name(&dt.name);
+        } else {
+            self.name(&dt.name);
+        }
+        self.params(&dt.params, &dt.hash);
+    }
+
+    fn partial_name(&mut self, p: &Parameter) {
+        match p {
+            Parameter::Name(n)
+                if !n.chars().all(|c| {
+                    c.is_alphanumeric() || matches!(c, '-' | '_' | '@' | '?' | '/' | '.')
+                }) =>
+            {
+                self.buf.push('[');
+                self.buf.push_str(n);
+                self.buf.push(']');
+            }
+            p => self.name(p),
+        }
+    }
+
+    fn params(&mut self, params: &[Parameter], hash: &HashMap<String, Parameter>) {
+        for p in params {
+            self.buf.push(' ');
+            self.parameter(p);
+        }
+        for (k, p) in sorted_hash(hash) {
+            self.buf.push(' ');
+            self.buf.push_str(k);
+            self.buf.push('=');
+            self.parameter(p);
+        }
+    }
+
+    fn name(&mut self, p: &Parameter) {
+        self.parameter(p);
+    }
+
+    fn parameter(&mut self, p: &Parameter) {
+        match p {
+            Parameter::Name(n) => self.buf.push_str(n),
+            Parameter::Path(path) => self.buf.push_str(path.raw()),
+            Parameter::Literal(j) => self.literal(j),
+            Parameter::Subexpression(s) => self.subexpression(s),
+        }
+    }
+
+    fn subexpression(&mut self, s: &Subexpression) {
+        self.buf.push('(');
+        if let Expression(ref ht) = *s.as_element() {
+            self.name(&ht.name);
+            self.params(&ht.params, &ht.hash);
+        }
+        self.buf.push(')');
+    }
+
+    fn literal(&mut self, j: &Json) {
+        // serde_json prints a compact form that is accepted by the literal
+        // grammar, including nested arrays and objects
+        self.buf.push_str(&j.to_string());
+    }
+}
+
+/// Returns the inverse template's chained `else if` node, if any.
+fn chained_node(t: &Template) -> Option<&HelperTemplate> {
+    match t.elements.as_slice() {
+        [HelperBlock(node)] if node.chain => Some(node),
+        _ => None,
+    }
+}
+
+/// Raw blocks are parsed into plain helper blocks. Print the block as a raw
+/// block again when its content would otherwise need escaping.
+fn raw_block_content(ht: &HelperTemplate) -> Option<&str> {
+    if ht.inverse.is_some() || ht.block_param.is_some() {
+        return None;
+    }
+    match ht.template.as_ref().map(|t| t.elements.as_slice()) {
+        Some([RawString(text)]) if text.contains("{{") && !text.contains("{{{{") => Some(text),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::template::{
+        DecoratorTemplate, ExpressionSpans, HelperTemplate, Template, TemplateElement,
+        TemplateOptions, TemplateVisitorMut, walk_decorator_mut, walk_helper_mut,
+        walk_template_mut,
+    };
+
+    // source positions and empty text left by stripped lines are expected
+    // to change
+    struct Normalize;
+
+    impl TemplateVisitorMut for Normalize {
+        fn visit_template_mut(&mut self, t: &mut Template) {
+            t.mapping.clear();
+            t.spans.clear();
+            t.elements
+                .retain(|e| !matches!(e, TemplateElement::RawString(s) if s.is_empty()));
+            walk_template_mut(self, t);
+        }
+
+        fn visit_helper_mut(&mut self, h: &mut HelperTemplate) {
+            h.spans = ExpressionSpans::default();
+            walk_helper_mut(self, h);
+        }
+
+        fn visit_decorator_mut(&mut self, d: &mut DecoratorTemplate) {
+            d.spans = ExpressionSpans::default();
+            walk_decorator_mut(self, d);
+        }
+    }
+
+    fn assert_round_trip(source: &str, expected: &str) {
+        assert_round_trip_with(source, expected, &TemplateOptions::default());
+    }
+
+    fn assert_round_trip_with(source: &str, expected: &str, options: &TemplateOptions) {
+        let mut t = Template::compile2(source, options.clone()).unwrap();
+        let printed = t.to_source();
+        assert_eq!(printed, expected);
+
+        let mut reparsed = Template::compile2(&printed, options.clone()).unwrap();
+        Normalize.visit_template_mut(&mut t);
+        Normalize.visit_template_mut(&mut reparsed);
+        assert_eq!(t.elements, reparsed.elements, "printed: {printed:?}");
+    }
+
+    #[test]
+    fn test_to_source_mustache_compat() {
+        let options = TemplateOptions {
+            mustache_compat: true,
+            ..Default::default()
+        };
+        assert_round_trip_with(
+            "{{^if a}}1{{/if}}{{^ b}}2{{else}}3{{/b}}",
+            "{{^if a}}1{{/if}}{{#b}}3{{else}}2{{/b}}",
+            &options,
+        );
+        assert_round_trip_with(
+            "{{#a}}{{^b}}x{{/b}}{{/a}}",
+            "{{#a}}{{^b}}x{{/b}}{{/a}}",
+            &options,
+        );
+    }
+
+    #[test]
+    fn test_to_source() {
+        let cases = [
+            ("hello {{name}}!", "hello {{name}}!"),
+            ("{{{ html }}} {{&amp}}", "{{{html}}} {{{amp}}}"),
+            (
+                "{{foo 1 'a\"b' k=true m=null}}",
+                "{{foo 1 \"a\\\"b\" k=true m=null}}",
+            ),
+            (
+                "{{foo (bar baz x=1) [1, 2]}}",
+                "{{foo (bar baz x=1) [1,2]}}",
+            ),
+            (
+                "{{#each items as |v k|}}{{k}}={{v}}{{/each}}",
+                "{{#each items as |v k|}}{{k}}={{v}}{{/each}}",
+            ),
+            (
+                "{{#if a}}1{{else if b}}2{{else if c}}3{{else}}4{{/if}}",
+                "{{#if a}}1{{else if b}}2{{else if c}}3{{else}}4{{/if}}",
+            ),
+            (
+                "{{#unless a}}1{{^}}2{{/unless}}",
+                "{{#unless a}}1{{else}}2{{/unless}}",
+            ),
+            (
+                "{{#if a}}1{{^ if b}}2{{/if}}",
+                "{{#if a}}1{{else if b}}2{{/if}}",
+            ),
+            (
+                "{{> (lookup this 'p') ctx k=v}}",
+                "{{> (lookup this \"p\") ctx k=v}}",
+            ),
+            (
+                "{{#> layout}}body{{/layout}}",
+                "{{#> layout}}body{{/layout}}",
+            ),
+            (
+                "{{> 'a b'}}{{#> [c d]}}d{{/[c d]}}{{> e/f.g}}",
+                "{{> [a b]}}{{#> [c d]}}d{{/[c d]}}{{> e/f.g}}",
+            ),
+            ("{{~! a ~}}{{~!-- b }} --~}}", "{{! a }}{{!-- b }} --}}"),
+            (
+                "{{#*inline \"p\"}}x{{/inline}}",
+                "{{#*inline \"p\"}}x{{/inline}}",
+            ),
+            ("{{* deco 1}}", "{{*deco 1}}"),
+            (
+                "{{! short }}{{!-- a }} b --}}",
+                "{{! short }}{{!-- a }} b --}}",
+            ),
+            (
+                "{{{{raw}}}}{{x}}{{{{/raw}}}}",
+                "{{{{raw}}}}{{x}}{{{{/raw}}}}",
+            ),
+            (r"\{{escaped}} \\{{x}}", r"\{{escaped}} \\{{x}}"),
+            ("a  {{~ b ~}}  c", "a{{b}}c"),
+        ];
+
+        for (source, expected) in cases {
+            assert_round_trip(source, expected);
+        }
+    }
+
+    #[test]
+    fn test_to_source_standalone() {
+        assert_round_trip("{{#if a}}\n  x\n{{/if}}\n", "{{#if a}}\n  x\n{{/if}}");
+        assert_round_trip("{{#if a}}\n\nx\n{{/if}}", "{{#if a}}\n\nx\n{{/if}}");
+        assert_round_trip("<div>\n  {{> p}}\n</div>", "<div>\n  {{> p}}\n</div>");
+        assert_round_trip(
+            "{{#*inline \"p\"}}\n  x\n{{/inline}}\n{{> p}}\n",
+            "{{#*inline \"p\"}}\n  x\n{{/inline}}\n{{> p}}",
+        );
+    }
+
+    #[test]
+    fn test_to_source_generated() {
+        let mut t = Template::compile("{{a}}").unwrap();
+        t.elements
+            .push(TemplateElement::RawString("{{b}}".to_owned()));
+        assert_eq!(t.to_source(), "{{a}}\\{{b}}");
+    }
+}
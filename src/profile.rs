@@ -0,0 +1,347 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::time::{Duration, Instant};
+
+/// What a `RenderProfile` entry measures
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProfileKind {
+    /// The rendered template
+    Template,
+    /// A partial included by `{{> name}}` or `{{#> name}}`
+    Partial,
+    /// A helper call, in a mustache, a block or a subexpression
+    Helper,
+    /// A decorator call, like `{{*inline}}`
+    Decorator,
+}
+
+impl ProfileKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ProfileKind::Template => "template",
+            ProfileKind::Partial => "partial",
+            ProfileKind::Helper => "helper",
+            ProfileKind::Decorator => "decorator",
+        }
+    }
+}
+
+impl fmt::Display for ProfileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Call count and wall time of a template, partial, helper or decorator in a
+/// render
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProfileEntry {
+    pub calls: u64,
+    /// Time spent in the calls, including nested calls. Recursive calls are
+    /// only counted once.
+    pub total: Duration,
+    /// Time spent in the calls, excluding the nested templates, partials,
+    /// helpers and decorators
+    pub self_time: Duration,
+}
+
+/// Timing report of a render, returned by `Registry::render_with_profile`
+///
+/// `Display` prints it as a table, slowest entries first. `to_folded`
+/// exports it for flamegraph tools.
+///
+/// ```
+/// use handlebars::{Handlebars, ProfileKind, handlebars_helper};
+///
+/// handlebars_helper!(upper: |s: String| s.to_uppercase());
+///
+/// let mut hbs = Handlebars::new();
+/// hbs.register_helper("upper", Box::new(upper));
+/// hbs.register_template_string("row", "<li>{{upper this}}</li>").unwrap();
+/// hbs.register_template_string("page", "{{#each this}}{{> row}}{{/each}}").unwrap();
+///
+/// let (output, profile) = hbs.render_with_profile("page", &["a", "b"]).unwrap();
+/// assert_eq!(output, "<li>A</li><li>B</li>");
+/// assert_eq!(profile.get(ProfileKind::Partial, "row").unwrap().calls, 2);
+/// println!("{profile}");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RenderProfile {
+    entries: BTreeMap<(ProfileKind, String), ProfileEntry>,
+    // self time of each stack of calls
+    stacks: BTreeMap<String, Duration>,
+}
+
+impl RenderProfile {
+    /// The entry of a template, partial, helper or decorator, if it was called
+    pub fn get(&self, kind: ProfileKind, name: &str) -> Option<&ProfileEntry> {
+        self.entries.get(&(kind, name.to_owned()))
+    }
+
+    /// All entries, ordered by kind and name
+    pub fn entries(&self) -> impl Iterator<Item = (ProfileKind, &str, &ProfileEntry)> {
+        self.entries
+            .iter()
+            .map(|((kind, name), e)| (*kind, name.as_str(), e))
+    }
+
+    /// Export in the folded stacks format of flamegraph tools like
+    /// `inferno-flamegraph` or `flamegraph.pl`
+    ///
+    /// Each line is a stack of calls, `template:page;partial:row;helper:upper`,
+    /// and the time spent in its last call, excluding nested calls, in
+    /// microseconds.
+    pub fn to_folded(&self) -> String {
+        let mut folded = String::new();
+        for (stack, time) in &self.stacks {
+            let _ = writeln!(folded, "{stack} {}", time.as_micros());
+        }
+        folded
+    }
+}
+
+impl fmt::Display for RenderProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<_> = self.entries().collect();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.2.total));
+
+        let width = entries
+            .iter()
+            .map(|(_, name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(4);
+        writeln!(
+            f,
+            "{:<9}  {:<width$}  {:>8}  {:>12}  {:>12}",
+            "kind", "name", "calls", "total ms", "self ms"
+        )?;
+        for (kind, name, e) in entries {
+            writeln!(
+                f,
+                "{:<9}  {:<width$}  {:>8}  {:>12.3}  {:>12.3}",
+                kind.as_str(),
+                name,
+                e.calls,
+                e.total.as_secs_f64() * 1000.0,
+                e.self_time.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    kind: ProfileKind,
+    name: String,
+    start: Instant,
+    // time spent in nested calls
+    nested: Duration,
+}
+
+/// Records the calls of a render into a `RenderProfile`
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+    stack: Vec<Frame>,
+    profile: RenderProfile,
+}
+
+impl Profiler {
+    pub(crate) fn enter(&mut self, kind: ProfileKind, name: &str) {
+        self.stack.push(Frame {
+            kind,
+            name: name.to_owned(),
+            start: Instant::now(),
+            nested: Duration::ZERO,
+        });
+    }
+
+    pub(crate) fn exit(&mut self) {
+        let Some(frame) = self.stack.pop() else {
+            return;
+        };
+        let elapsed = frame.start.elapsed();
+        let self_time = elapsed.saturating_sub(frame.nested);
+        if let Some(parent) = self.stack.last_mut() {
+            parent.nested += elapsed;
+        }
+
+        let mut stack = String::new();
+        for f in self.stack.iter().chain(std::iter::once(&frame)) {
+            if !stack.is_empty() {
+                stack.push(';');
+            }
+            stack.push_str(f.kind.as_str());
+            stack.push(':');
+            // separators of the folded format
+            stack.extend(f.name.chars().map(|c| {
+                if c == ';' || c.is_whitespace() {
+                    '_'
+                } else {
+                    c
+                }
+            }));
+        }
+        *self.profile.stacks.entry(stack).or_default() += self_time;
+
+        let recursive = self
+            .stack
+            .iter()
+            .any(|f| f.kind == frame.kind && f.name == frame.name);
+        let entry = self
+            .profile
+            .entries
+            .entry((frame.kind, frame.name))
+            .or_default();
+        entry.calls += 1;
+        entry.self_time += self_time;
+        if !recursive {
+            entry.total += elapsed;
+        }
+    }
+
+    pub(crate) fn into_profile(self) -> RenderProfile {
+        self.profile
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use serde_json::json;
+
+    use super::{ProfileKind, Profiler, RenderProfile};
+    use crate::context::Context;
+    use crate::output::StringOutput;
+    use crate::registry::Registry;
+    use crate::render::{RenderContext, Renderable};
+    use crate::{RenderErrorReason, Template};
+
+    fn profile_page() -> RenderProfile {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("item", "{{#if this}}{{this}}{{/if}}")
+            .unwrap();
+        hbs.register_template_string(
+            "page",
+            "{{#*inline \"x\"}}{{/inline}}{{#each items}}{{> item}}{{/each}}{{len (lookup items 0)}}",
+        )
+        .unwrap();
+
+        let data = json!({"items": ["a", "b", ""]});
+        let (out, profile) = hbs.render_with_profile("page", &data).unwrap();
+        assert_eq!(out, "ab1");
+        profile
+    }
+
+    #[test]
+    fn test_profile_calls() {
+        let profile = profile_page();
+
+        let calls = |kind, name| profile.get(kind, name).map(|e| e.calls);
+        assert_eq!(calls(ProfileKind::Template, "page"), Some(1));
+        assert_eq!(calls(ProfileKind::Partial, "item"), Some(3));
+        assert_eq!(calls(ProfileKind::Helper, "each"), Some(1));
+        assert_eq!(calls(ProfileKind::Helper, "if"), Some(3));
+        assert_eq!(calls(ProfileKind::Helper, "lookup"), Some(1));
+        assert_eq!(calls(ProfileKind::Helper, "len"), Some(1));
+        assert_eq!(calls(ProfileKind::Decorator, "inline"), Some(1));
+        assert_eq!(profile.entries().count(), 7);
+    }
+
+    #[test]
+    fn test_profile_times() {
+        let profile = profile_page();
+
+        let page = profile.get(ProfileKind::Template, "page").unwrap();
+        let each = profile.get(ProfileKind::Helper, "each").unwrap();
+        assert!(page.total >= each.total);
+        assert!(each.total >= each.self_time);
+    }
+
+    #[test]
+    fn test_profile_folded() {
+        let profile = profile_page();
+
+        let folded = profile.to_folded();
+        let stacks: Vec<_> = folded
+            .lines()
+            .map(|l| l.rsplit_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(
+            stacks,
+            [
+                "template:page",
+                "template:page;decorator:inline",
+                "template:page;helper:each",
+                "template:page;helper:each;partial:item",
+                "template:page;helper:each;partial:item;helper:if",
+                "template:page;helper:len",
+                "template:page;helper:lookup",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_profile_table() {
+        let profile = profile_page();
+
+        let table = profile.to_string();
+        let mut lines = table.lines();
+        assert!(lines.next().unwrap().starts_with("kind       name"));
+        assert!(lines.next().unwrap().starts_with("template   page"));
+        assert_eq!(lines.count(), 6);
+    }
+
+    #[test]
+    fn test_profile_failed_render() {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("item", "{{#if this}}{{this}}{{else}}{{> missing}}{{/if}}")
+            .unwrap();
+        let template = Template::compile("{{#each this}}{{> item}}{{/each}}").unwrap();
+        let ctx = Context::wraps(json!(["a", "", "b"])).unwrap();
+
+        let profiler = Rc::new(RefCell::new(Profiler::default()));
+        let mut rc = RenderContext::new(None);
+        rc.set_profiler(Some(profiler.clone()));
+        let err = template
+            .render(&hbs, &ctx, &mut rc, &mut StringOutput::new())
+            .unwrap_err();
+        assert!(matches!(
+            err.reason(),
+            RenderErrorReason::PartialNotFound(name) if name == "missing"
+        ));
+        drop(rc);
+
+        // the calls left by the error are closed and recorded
+        let profiler = Rc::try_unwrap(profiler).unwrap().into_inner();
+        assert!(profiler.stack.is_empty());
+        let profile = profiler.into_profile();
+        let calls = |kind, name| profile.get(kind, name).map(|e| e.calls);
+        assert_eq!(calls(ProfileKind::Helper, "each"), Some(1));
+        assert_eq!(calls(ProfileKind::Partial, "item"), Some(2));
+        assert_eq!(calls(ProfileKind::Helper, "if"), Some(2));
+    }
+
+    #[test]
+    fn test_recursion() {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("tree", "{{#each children}}{{> node}}{{/each}}")
+            .unwrap();
+        hbs.register_template_string("node", "{{name}}{{> tree}}")
+            .unwrap();
+
+        let data = json!({"children": [{"name": "a", "children": [{"name": "b"}]}]});
+        let (out, profile) = hbs.render_with_profile("tree", &data).unwrap();
+        assert_eq!(out, "ab");
+
+        let node = profile.get(ProfileKind::Partial, "node").unwrap();
+        let tree = profile.get(ProfileKind::Template, "tree").unwrap();
+        assert_eq!(node.calls, 2);
+        assert!(node.total <= tree.total);
+    }
+}
@@ -0,0 +1,616 @@
+//! Error-recovering compilation
+//!
+//! The grammar stops at the first syntax error. To report all errors of a
+//! template at once, its tags are scanned and checked one by one before
+//! parsing:
+//!
+//! * each tag is parsed on its own, wrapped in a block when it's an `else`
+//!   or when the checks of the compiler need its closing tag,
+//! * blocks are matched with their closing tags on a stack, which finds
+//!   unclosed blocks, mismatched and unexpected closing tags.
+//!
+//! Faulty tags are blanked with spaces, so lines and columns of the rest of
+//! the source don't move, and the repaired source is compiled by the
+//! regular parser. Errors it still reports are handled the same way until
+//! it succeeds.
+
+use std::ops::Range;
+
+use pest::Parser;
+use pest::error::InputLocation;
+
+use crate::delimiters;
+use crate::error::{TemplateError, TemplateErrorReason};
+use crate::grammar::{HandlebarsParser, Rule};
+use crate::support::str::{line_col, offset_of};
+use crate::template::{SourceSpan, Template, TemplateOptions, partial_name};
+
+/// Compile `source`, returning a template of its valid parts and all the
+/// errors found
+pub(crate) fn compile(source: &str, options: TemplateOptions) -> (Template, Vec<TemplateError>) {
+    let translation = match delimiters::translate(source, &options.delimiters) {
+        Ok(translation) => translation,
+        Err(e) => {
+            let start = e
+                .pos()
+                .map_or(source.len(), |(line, col)| offset_of(source, line, col));
+            let end = source[start..]
+                .find('\n')
+                .map_or(source.len(), |i| start + i);
+            let mut template = Template::new();
+            template.name = options.name.clone();
+            return (
+                template,
+                vec![e.in_template(options.name()).with_span(start..end)],
+            );
+        }
+    };
+    let translated = translation.as_ref().map_or(source, |t| t.source.as_str());
+
+    let mut recovery = Recovery {
+        source: translated,
+        tags: Vec::new(),
+        blanked: Vec::new(),
+        blocks: Vec::new(),
+        errors: Vec::new(),
+        mustache_compat: options.mustache_compat,
+    };
+    recovery.scan();
+    let mut template = recovery.parse(&options);
+    if let Some(translation) = &translation {
+        translation.restore(&mut template, source);
+    }
+
+    let mut errors = recovery.errors;
+    errors.sort_by_key(|(_, offset, _)| *offset);
+    let errors = errors
+        .into_iter()
+        .map(|(e, offset, span)| {
+            let (offset, span) = match &translation {
+                Some(t) => (
+                    t.original_span(SourceSpan::between(translated, offset, offset), source)
+                        .start,
+                    t.original_span(
+                        SourceSpan::between(translated, span.start, span.end),
+                        source,
+                    )
+                    .range(),
+                ),
+                None => (offset, span),
+            };
+            let (line, col) = line_col(source, offset);
+            e.at(source, line, col)
+                .in_template(options.name())
+                .with_span(span)
+        })
+        .collect();
+    (template, errors)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    Helper,
+    Decorator,
+    Partial,
+}
+
+enum Tag<'a> {
+    Open(Rule, BlockKind, &'a str),
+    Else(Rule),
+    Close(&'a str),
+    Comment,
+    Other,
+}
+
+struct Block {
+    kind: BlockKind,
+    name: String,
+    // the open tag, then `else` tags
+    tags: Vec<Range<usize>>,
+    // an invalid open tag is reported and blanked, the block is only kept
+    // to blank its other tags
+    valid: bool,
+}
+
+struct Recovery<'a> {
+    source: &'a str,
+    tags: Vec<Range<usize>>,
+    blanked: Vec<Range<usize>>,
+    blocks: Vec<Block>,
+    // error, offset and span of the faulty tag
+    errors: Vec<(TemplateError, usize, Range<usize>)>,
+    // `{{^name}}` opens an inverted section instead of continuing a chain
+    mustache_compat: bool,
+}
+
+impl Recovery<'_> {
+    fn scan(&mut self) {
+        let source = self.source;
+        let mut pos = 0;
+        while let Some(start) = delimiters::find_default_tag(&source[pos..]).map(|i| i + pos) {
+            let after = &source[start + 2..];
+            if after.starts_with("{{") {
+                pos = self.raw_block(start);
+                continue;
+            }
+            match delimiters::tag_len(after, "}}") {
+                Some(len) => {
+                    let span = start..start + len + 4;
+                    pos = span.end;
+                    self.tag(span);
+                }
+                None => {
+                    self.unclosed(start..start + 2, "}}");
+                    pos = start + 2;
+                }
+            }
+        }
+        while let Some(block) = self.blocks.pop() {
+            self.unclosed_block(block);
+        }
+    }
+
+    /// Check the raw block at `start`, and return the position after it
+    fn raw_block(&mut self, start: usize) -> usize {
+        let source = self.source;
+        let end = source[start + 4..]
+            .find("}}}}")
+            .map(|l| start + 4 + l + 4)
+            .and_then(|c| source[c..].find("{{{{/").map(|l| c + l + 5))
+            .and_then(|e| source[e..].find("}}}}").map(|l| e + l + 4));
+        let Some(end) = end else {
+            self.unclosed(start..start + 4, "{{{{/");
+            return start + 4;
+        };
+
+        let span = start..end;
+        self.tags.push(span.clone());
+        if let Err((e, offset)) = self.check_compile(&span, "", "") {
+            self.error(e, offset, span);
+        }
+        end
+    }
+
+    fn tag(&mut self, span: Range<usize>) {
+        self.tags.push(span.clone());
+        // `{{^name}}` continues the chain of the enclosing block, unless in
+        // the Mustache compatibility mode
+        let inverted_section = self.mustache_compat || self.blocks.is_empty();
+        match classify(&self.source[span.clone()], inverted_section) {
+            Tag::Comment => {}
+            Tag::Other => {
+                if let Err((e, offset)) = self.check_compile(&span, "", "") {
+                    self.error(e, offset, span);
+                }
+            }
+            Tag::Open(rule, kind, name) => {
+                let close = match kind {
+                    BlockKind::Helper => format!("{{{{/{name}}}}}"),
+                    BlockKind::Decorator | BlockKind::Partial => "{{/}}".to_owned(),
+                };
+                let checked = self
+                    .check_rule(rule, &span)
+                    .and_then(|_| self.check_compile(&span, "", &close));
+                let valid = match checked {
+                    Ok(()) => true,
+                    Err((e, offset)) => {
+                        self.error(e, offset, span.clone());
+                        false
+                    }
+                };
+                self.blocks.push(Block {
+                    kind,
+                    name: name.to_owned(),
+                    tags: vec![span],
+                    valid,
+                });
+            }
+            Tag::Else(rule) => {
+                let in_helper = self
+                    .blocks
+                    .last()
+                    .is_some_and(|b| b.kind == BlockKind::Helper);
+                if !in_helper {
+                    let e = syntax_error("`else` is not in a helper block");
+                    self.error(e, span.start, span);
+                    return;
+                }
+                let checked = self
+                    .check_rule(rule, &span)
+                    .and_then(|_| self.check_compile(&span, "{{#x}}", "{{/x}}"));
+                match checked {
+                    Ok(()) => self.blocks.last_mut().unwrap().tags.push(span),
+                    Err((e, offset)) => self.error(e, offset, span),
+                }
+            }
+            Tag::Close(name) => self.close(span, name),
+        }
+    }
+
+    fn close(&mut self, span: Range<usize>, name: &str) {
+        if let Err((e, offset)) = self.check_rule(Rule::helper_block_end, &span).or_else(|e| {
+            self.check_rule(Rule::partial_block_end, &span)
+                .map_err(|_| e)
+        }) {
+            self.error(e, offset, span);
+            return;
+        }
+
+        let matching = |b: &Block| match b.kind {
+            BlockKind::Helper => b.name == name,
+            BlockKind::Decorator | BlockKind::Partial => {
+                name.is_empty() || partial_name(&b.name) == partial_name(name)
+            }
+        };
+        if let Some(idx) = self.blocks.iter().rposition(matching) {
+            // blocks opened inside the closed one are not closed
+            while self.blocks.len() > idx + 1 {
+                let block = self.blocks.pop().unwrap();
+                self.unclosed_block(block);
+            }
+            let block = self.blocks.pop().unwrap();
+            if !block.valid {
+                self.blank_block(&block);
+                self.blank(span);
+            }
+            return;
+        }
+
+        match self.blocks.pop() {
+            // the closing tag is taken as the one of the current block,
+            // which is dropped
+            Some(block) => {
+                if block.valid {
+                    let reason = if block.kind == BlockKind::Helper {
+                        TemplateErrorReason::MismatchingClosedHelper(
+                            block.name.clone(),
+                            name.to_owned(),
+                        )
+                    } else {
+                        TemplateErrorReason::MismatchingClosedDecorator(
+                            block.name.clone(),
+                            name.to_owned(),
+                        )
+                    };
+                    self.error(TemplateError::of(reason), span.start, span.clone());
+                }
+                self.blank_block(&block);
+                self.blank(span);
+            }
+            None => {
+                let reason = TemplateErrorReason::UnexpectedClosingTag(name.to_owned());
+                self.error(TemplateError::of(reason), span.start, span);
+            }
+        }
+    }
+
+    /// Parse the tag at `span` with `rule`, the error offset is relative to
+    /// the source
+    fn check_rule(&self, rule: Rule, span: &Range<usize>) -> Result<(), (TemplateError, usize)> {
+        let tag = &self.source[span.clone()];
+        match HandlebarsParser::parse(rule, tag) {
+            Ok(mut pairs) => {
+                let end = pairs.next().map_or(0, |p| p.as_span().end());
+                if end == tag.len() {
+                    Ok(())
+                } else {
+                    Err((syntax_error("unexpected end of tag"), span.start + end))
+                }
+            }
+            Err(e) => {
+                let offset = match e.location {
+                    InputLocation::Pos(pos) => pos,
+                    InputLocation::Span((start, _)) => start,
+                };
+                let e = syntax_error(&e.variant.message());
+                Err((e, span.start + offset))
+            }
+        }
+    }
+
+    /// Compile the tag at `span` between `prefix` and `suffix`, for the
+    /// checks done by the compiler
+    ///
+    /// Errors of the wrapping tags are ignored.
+    fn check_compile(
+        &self,
+        span: &Range<usize>,
+        prefix: &str,
+        suffix: &str,
+    ) -> Result<(), (TemplateError, usize)> {
+        let tag = &self.source[span.clone()];
+        let wrapped = format!("{prefix}{tag}{suffix}");
+        let options = TemplateOptions {
+            mustache_compat: self.mustache_compat,
+            ..Default::default()
+        };
+        let Err(e) = Template::parse(&wrapped, options) else {
+            return Ok(());
+        };
+        let offset = e
+            .pos()
+            .map_or(prefix.len(), |(line, col)| offset_of(&wrapped, line, col));
+        let in_tag =
+            offset >= prefix.len() && (offset < prefix.len() + tag.len() || suffix.is_empty());
+        if in_tag {
+            Err((e, span.start + (offset - prefix.len()).min(tag.len())))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Compile the repaired source, blanking the tags the parser still
+    /// fails on
+    fn parse(&mut self, options: &TemplateOptions) -> Template {
+        loop {
+            let e = match Template::parse(&self.repaired(), options.clone()) {
+                Ok(template) => return template,
+                Err(e) => e,
+            };
+            let offset = e.pos().map_or(self.source.len(), |(line, col)| {
+                offset_of(self.source, line, col)
+            });
+            let tag = self
+                .tags
+                .iter()
+                .find(|t| t.contains(&offset) && !self.blanked.contains(t))
+                .cloned();
+            match tag {
+                Some(span) => self.error(e, offset, span),
+                None => {
+                    self.errors.push((e, offset, offset..offset));
+                    let mut template = Template::new();
+                    template.name = options.name.clone();
+                    return template;
+                }
+            }
+        }
+    }
+
+    fn unclosed(&mut self, span: Range<usize>, expected: &str) {
+        let e = syntax_error(&format!("unclosed tag, expected {expected:?}"));
+        self.error(e, span.start, span);
+    }
+
+    fn unclosed_block(&mut self, block: Block) {
+        if block.valid {
+            let e = TemplateError::of(TemplateErrorReason::UnclosedBlock(block.name.clone()));
+            self.errors
+                .push((e, block.tags[0].start, block.tags[0].clone()));
+        }
+        self.blank_block(&block);
+    }
+
+    /// Report an error and blank its tag
+    fn error(&mut self, e: TemplateError, offset: usize, span: Range<usize>) {
+        self.errors.push((e, offset, span.clone()));
+        self.blank(span);
+    }
+
+    fn blank_block(&mut self, block: &Block) {
+        for span in &block.tags {
+            self.blank(span.clone());
+        }
+    }
+
+    fn blank(&mut self, span: Range<usize>) {
+        if !self.blanked.contains(&span) {
+            self.blanked.push(span);
+        }
+    }
+
+    /// The source with blanked tags replaced by spaces, one per char so
+    /// lines and columns are kept
+    fn repaired(&self) -> String {
+        self.source
+            .char_indices()
+            .map(|(i, c)| {
+                if c != '\n' && self.blanked.iter().any(|span| span.contains(&i)) {
+                    ' '
+                } else {
+                    c
+                }
+            })
+            .collect()
+    }
+}
+
+fn classify(tag: &str, inverted_section: bool) -> Tag<'_> {
+    let body = tag[2..tag.len() - 2].trim_start();
+    let body = body.strip_prefix('~').unwrap_or(body).trim_start();
+
+    if body.starts_with('!') {
+        Tag::Comment
+    } else if let Some(rest) = body.strip_prefix('#') {
+        let rest = rest.trim_start();
+        if let Some(rest) = rest.strip_prefix('>') {
+            Tag::Open(
+                Rule::partial_block_start,
+                BlockKind::Partial,
+                first_token(rest),
+            )
+        } else if let Some(rest) = rest.strip_prefix('*') {
+            Tag::Open(
+                Rule::decorator_block_start,
+                BlockKind::Decorator,
+                first_token(rest),
+            )
+        } else {
+            Tag::Open(
+                Rule::helper_block_start,
+                BlockKind::Helper,
+                first_token(rest),
+            )
+        }
+    } else if let Some(rest) = body.strip_prefix('^') {
+        match first_token(rest) {
+            "" => Tag::Else(Rule::invert_tag),
+            name if inverted_section => {
+                Tag::Open(Rule::inverted_block_start, BlockKind::Helper, name)
+            }
+            _ => Tag::Else(Rule::invert_chain_tag),
+        }
+    } else if let Some(rest) = body.strip_prefix('/') {
+        Tag::Close(first_token(rest))
+    } else if let Some(rest) = body
+        .strip_prefix("else")
+        .filter(|rest| rest.is_empty() || rest.starts_with(|c: char| c.is_whitespace() || c == '~'))
+    {
+        if first_token(rest).is_empty() {
+            Tag::Else(Rule::invert_tag)
+        } else {
+            Tag::Else(Rule::invert_chain_tag)
+        }
+    } else {
+        Tag::Other
+    }
+}
+
+/// The name at the start of `s`, a bracketed or quoted partial name
+/// included
+fn first_token(s: &str) -> &str {
+    let s = s.trim_start();
+    let len = match s.chars().next() {
+        Some('[') => s.find(']').map_or(s.len(), |i| i + 1),
+        Some('\'') => {
+            let mut prev = '\'';
+            s.char_indices()
+                .skip(1)
+                .find(|&(_, c)| {
+                    let end = c == '\'' && prev != '\\';
+                    prev = c;
+                    end
+                })
+                .map_or(s.len(), |(i, _)| i + 1)
+        }
+        _ => s
+            .find(|c: char| c.is_whitespace() || c == '~')
+            .unwrap_or(s.len()),
+    };
+    &s[..len]
+}
+
+fn syntax_error(message: &str) -> TemplateError {
+    TemplateError::of(TemplateErrorReason::InvalidSyntax(message.to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::error::TemplateErrorReason;
+    use crate::template::{Template, TemplateElement};
+
+    #[test]
+    fn test_all_errors() {
+        let source = "{{#if a}}\n{{foo bar=}}\n{{/each}}\n{{/with}}{{else}}\n{{#each b}}";
+        let (_, errors) = Template::compile_recovering(source);
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.reason().to_string(), e.pos().unwrap(), e.span().unwrap()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "invalid handlebars syntax: expected helper_parameter".to_owned(),
+                    (2, 11),
+                    10..22
+                ),
+                (
+                    "helper \"if\" was opened, but \"each\" is closing".to_owned(),
+                    (3, 1),
+                    23..32
+                ),
+                (
+                    "\"with\" is closing, but no block is open".to_owned(),
+                    (4, 1),
+                    33..42
+                ),
+                (
+                    "invalid handlebars syntax: `else` is not in a helper block".to_owned(),
+                    (4, 10),
+                    42..50
+                ),
+                ("block \"each\" is not closed".to_owned(), (5, 1), 51..62),
+            ]
+        );
+        assert!(errors.iter().all(|e| e.name().unwrap() == "Unnamed"));
+    }
+
+    #[test]
+    fn test_partial_template() {
+        let source = "{{#each a}}\n{{#if b}}{{x}}\n{{/each}}{{y}}";
+        let (t, errors) = Template::compile_recovering_with_name(source, "t".to_owned());
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].reason(),
+            TemplateErrorReason::UnclosedBlock(name) if name == "if"
+        ));
+        assert_eq!(errors[0].name().unwrap(), "t");
+
+        assert_eq!(t.name.as_deref(), Some("t"));
+        let TemplateElement::HelperBlock(ref each) = t.elements[0] else {
+            panic!("expected each block, got {:?}", t.elements[0]);
+        };
+        let inner = each.template.as_ref().unwrap();
+        assert!(
+            inner
+                .elements
+                .iter()
+                .any(|e| matches!(e, TemplateElement::Expression(_)))
+        );
+        // positions are the ones of the original source
+        assert_eq!(t.mapping[1].0, 3);
+        assert_eq!(t.mapping[1].1, 10);
+    }
+
+    #[test]
+    fn test_no_errors() {
+        for source in [
+            "hello {{name}}",
+            "{{#if a}}x{{else if b}}y{{^}}z{{/if}}",
+            "{{#> layout}}{{#*inline \"a\"}}b{{/inline}}{{/}}",
+            "{{#> 'a b'}}c{{/[a b]}}",
+            "{{!-- {{#if}} --}}{{{{raw}}}}{{#if}}{{{{/raw}}}}",
+            "\\{{#if}} {{~^ section ~}}a{{/section}}",
+            "{{#if a}}{{~^ if b ~}}a{{/if}}",
+        ] {
+            let (t, errors) = Template::compile_recovering(source);
+            assert!(errors.is_empty(), "{source}: {errors:?}");
+            assert_eq!(t, Template::compile(source).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parser_errors() {
+        let (t, errors) = Template::compile_recovering("{{#if}}a{{else}}b{{else if c}}d{{/if}}");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span(), Some(17..30));
+        assert_eq!(t.elements.len(), 1);
+    }
+
+    #[test]
+    fn test_unclosed_tags() {
+        let (t, errors) = Template::compile_recovering("{{foo}} {{bar");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].pos(), Some((1, 9)));
+        assert_eq!(errors[0].span(), Some(8..10));
+        assert!(matches!(t.elements[0], TemplateElement::Expression(_)));
+    }
+
+    #[test]
+    fn test_custom_delimiters() {
+        let source = "{{=<% %>=}}\n<%#if a%>{{b}}<%/each%>";
+        let (t, errors) = Template::compile_recovering(source);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].pos(), Some((2, 15)));
+        assert_eq!(errors[0].span(), Some(26..35));
+        // blanked tags are left as whitespaces
+        assert_eq!(
+            t.elements.last(),
+            Some(&TemplateElement::RawString(format!(
+                "{0}{{{{b}}}}{0}",
+                " ".repeat(9)
+            )))
+        );
+    }
+}
@@ -1,21 +1,31 @@
 use std::borrow::Cow;
-use std::collections::{BTreeMap, HashMap};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::AsRef;
 use std::fmt::{self, Debug, Formatter};
 use std::io::{Error as IoError, Write};
 use std::path::Path;
+use std::rc::Rc;
 use std::sync::Arc;
 
 use serde::Serialize;
 
-use crate::context::Context;
+use crate::analysis::{self, DependencyGraph, TemplateReferences};
+use crate::async_helper::{AsyncCalls, AsyncHelper, AsyncHelperDef};
+use crate::context::{Context, ContextValue};
+use crate::coverage::{Coverage, CoverageRecorder};
 use crate::decorators::{self, DecoratorDef};
+use crate::delimiters::Delimiters;
 #[cfg(feature = "script_helper")]
 use crate::error::ScriptError;
 use crate::error::{RenderError, RenderErrorReason, TemplateError};
 use crate::helpers::{self, HelperDef};
+use crate::limits::{LimitState, LimitedOutput, RenderLimits};
+use crate::observer::RenderObserver;
 use crate::output::{Output, StringOutput, WriteOutput};
-use crate::render::{RenderContext, Renderable};
+use crate::profile::{Profiler, RenderProfile};
+use crate::render::RenderContext;
+use crate::render_options::RenderOptions;
 use crate::sources::{FileSource, Source};
 use crate::support::str::{self, StringWriter};
 use crate::template::{Template, TemplateOptions};
@@ -71,6 +81,12 @@ pub struct Registry<'reg> {
     dev_mode: bool,
     recursive_lookup: bool,
     prevent_indent: bool,
+    known_helpers_only: bool,
+    delimiters: Delimiters,
+    mustache_compat: bool,
+    render_limits: RenderLimits,
+    coverage: Option<Coverage>,
+    observer: Option<Arc<dyn RenderObserver + Send + Sync + 'reg>>,
     #[cfg(feature = "script_helper")]
     pub(crate) engine: Arc<Engine>,
 
@@ -93,6 +109,11 @@ impl Debug for Registry<'_> {
     }
 }
 
+// name of the rendered template in profiles and traces
+pub(crate) fn root_template_name<'a>(name: Option<&'a str>, template: &'a Template) -> &'a str {
+    name.or(template.name.as_deref()).unwrap_or("<anonymous>")
+}
+
 impl Default for Registry<'_> {
     fn default() -> Self {
         Self::new()
@@ -121,6 +142,47 @@ pub struct DirectorySourceOptions {
 
 #[cfg(feature = "dir_source")]
 impl DirectorySourceOptions {
+    /// Name of the template registered for the file `tpl_path` by
+    /// [`Registry::register_templates_directory`] with `dir_path`, `None` when
+    /// the file is not loaded as a template
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use handlebars::DirectorySourceOptions;
+    ///
+    /// let options = DirectorySourceOptions::default();
+    /// let name = options.template_name(Path::new("templates"), Path::new("templates/a/b.hbs"));
+    /// assert_eq!(name.as_deref(), Some("a/b"));
+    /// ```
+    pub fn template_name(&self, dir_path: &Path, tpl_path: &Path) -> Option<String> {
+        // Checks if extension matches
+        if !tpl_path
+            .to_string_lossy()
+            .ends_with(self.tpl_extension.as_str())
+        {
+            return None;
+        }
+        // Rejects any hidden or temporary files.
+        let stem = tpl_path.file_stem()?;
+        if self.ignore_file(&stem.to_string_lossy()) {
+            return None;
+        }
+
+        let tpl_name = tpl_path
+            .strip_prefix(dir_path)
+            .ok()?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        Some(
+            tpl_name
+                .strip_suffix(self.tpl_extension.as_str())
+                .map(|s| s.to_owned())
+                .unwrap_or(tpl_name),
+        )
+    }
+
     fn ignore_file(&self, name: &str) -> bool {
         self.ignored_as_hidden_file(name) || self.ignored_as_temporary_file(name)
     }
@@ -159,6 +221,12 @@ impl<'reg> Registry<'reg> {
             dev_mode: false,
             recursive_lookup: false,
             prevent_indent: false,
+            known_helpers_only: false,
+            delimiters: Delimiters::default(),
+            mustache_compat: false,
+            render_limits: RenderLimits::default(),
+            coverage: None,
+            observer: None,
             #[cfg(feature = "script_helper")]
             engine: Arc::new(rhai_engine()),
             #[cfg(feature = "script_helper")]
@@ -192,6 +260,7 @@ impl<'reg> Registry<'reg> {
         self.register_string_helpers();
 
         self.register_decorator("inline", Box::new(decorators::INLINE_DECORATOR));
+        self.register_decorator("slot", Box::new(decorators::SLOT_DECORATOR));
         self
     }
 
@@ -271,6 +340,206 @@ impl<'reg> Registry<'reg> {
         self.prevent_indent
     }
 
+    /// Enable or disable checking helpers on template registration
+    ///
+    /// This is the equivalent of the `knownHelpersOnly` compile option of
+    /// handlebars.js. When enabled, registering a template string or file
+    /// fails with `TemplateErrorReason::UnknownHelper` or
+    /// `TemplateErrorReason::UnknownDecorator` if the template calls a helper
+    /// or decorator that is not registered yet. So helpers and decorators have
+    /// to be registered before templates using them.
+    ///
+    /// Templates registered with `register_template` are not checked. Use
+    /// `validate` to check all registered templates, including their partials.
+    pub fn set_known_helpers_only(&mut self, enabled: bool) {
+        self.known_helpers_only = enabled;
+    }
+
+    /// Return state for `known_helpers_only` option, default to `false`.
+    pub fn known_helpers_only(&self) -> bool {
+        self.known_helpers_only
+    }
+
+    /// Change the delimiters of template tags, from `{{` and `}}` to
+    /// `open` and `close`, for templates registered or rendered from source
+    /// afterwards
+    ///
+    /// This is useful to write templates of files that contain literal
+    /// braces, like LaTeX documents or other handlebars templates. All tags
+    /// use the new delimiters, for example `<%#if a%>` or `<%{html}%>` with
+    /// `<%` and `%>`, and text is output as it is: `{{` and backslashes have
+    /// no special meaning in text.
+    ///
+    /// Delimiters can also be changed inside a template with the set
+    /// delimiters tag of Mustache, like `{{=<% %>=}}`, for the rest of the
+    /// template.
+    ///
+    /// Returns `TemplateErrorReason::InvalidDelimiters` if a delimiter is
+    /// empty, or contains whitespaces or `=`.
+    pub fn set_delimiters(&mut self, open: &str, close: &str) -> Result<(), TemplateError> {
+        self.delimiters = Delimiters::new(open, close)?;
+        Ok(())
+    }
+
+    /// Return the open and close delimiters of template tags, default to
+    /// `{{` and `}}`.
+    pub fn delimiters(&self) -> (&str, &str) {
+        (&self.delimiters.open, &self.delimiters.close)
+    }
+
+    /// Enable or disable Mustache compatibility mode
+    ///
+    /// Templates written for Mustache mostly render the same with
+    /// handlebars, except for a few semantic differences. With this mode
+    /// enabled:
+    ///
+    /// * a block like `{{#section}}` or `{{^section}}`, with no registered
+    ///   helper of that name and no parameter, is always a section on the
+    ///   value of `section`, even when a `blockHelperMissing` helper is
+    ///   registered. The block is rendered for each item of a non-empty
+    ///   list, or once with the value as context for other values than
+    ///   `false` and `null`, and the inverse in other cases.
+    /// * `{{^section}}` opens an inverted section inside a block too, where
+    ///   it continues the else chain of the block otherwise, like
+    ///   `{{else section}}`. This applies to templates registered or
+    ///   rendered from source after the mode is enabled.
+    /// * names are resolved through the stack of contexts, like
+    ///   `set_recursive_lookup`, and the first part of a dotted name like
+    ///   `a.b` is searched that way too.
+    /// * a missing partial renders nothing instead of failing.
+    ///
+    /// Lambdas are not supported, as data is plain JSON. The Mustache spec
+    /// cases that still differ are listed in `tests/mustache_spec.rs`.
+    pub fn set_mustache_compat(&mut self, enabled: bool) {
+        self.mustache_compat = enabled;
+    }
+
+    /// Return state for `mustache_compat` option, default to `false`.
+    pub fn mustache_compat(&self) -> bool {
+        self.mustache_compat
+    }
+
+    /// Set the resource limits of renders, see `RenderLimits`
+    ///
+    /// Use `render_with_limits` for other limits in a single render.
+    pub fn set_render_limits(&mut self, limits: RenderLimits) {
+        self.render_limits = limits;
+    }
+
+    /// Return the resource limits of renders, unlimited by default
+    pub fn render_limits(&self) -> &RenderLimits {
+        &self.render_limits
+    }
+
+    /// Collect the coverage of the templates rendered by this registry, see
+    /// `Coverage`
+    ///
+    /// The templates registered so far, and the ones registered later, are
+    /// added to the report with no hit. Set `None` to stop collecting.
+    pub fn set_coverage(&mut self, coverage: Option<Coverage>) {
+        if let Some(coverage) = &coverage {
+            for (name, t) in &self.templates {
+                coverage.declare(name, t);
+            }
+        }
+        self.coverage = coverage;
+    }
+
+    /// Return the coverage collector of this registry, if any
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Set the observer of the renders of this registry, see
+    /// `RenderObserver`
+    ///
+    /// Set `None` to remove it.
+    pub fn set_render_observer(
+        &mut self,
+        observer: Option<Arc<dyn RenderObserver + Send + Sync + 'reg>>,
+    ) {
+        self.observer = observer;
+    }
+
+    /// Check all registered templates against registered helpers, decorators
+    /// and templates
+    ///
+    /// Returns every problem found as a `TemplateError`, with the name of the
+    /// template and the position of the element it is found in:
+    ///
+    /// * `TemplateErrorReason::UnknownHelper` for a helper call, like
+    ///   `{{#eahc items}}`, that has no registered helper. A simple mustache
+    ///   like `{{name}}` is a value lookup when no helper is registered with
+    ///   this name, so it is never reported.
+    /// * `TemplateErrorReason::UnknownDecorator` for a decorator call with no
+    ///   registered decorator.
+    /// * `TemplateErrorReason::UnknownPartial` for a partial included by name,
+    ///   that is neither a registered template nor an inline partial defined
+    ///   in any registered template. Partial blocks are not reported, they
+    ///   render their content when the partial is missing.
+    ///
+    /// Helpers registered at render time by decorators, and `helperMissing`
+    /// hooks, are not taken into account.
+    pub fn validate(&self) -> Result<(), Vec<TemplateError>> {
+        let mut names: Vec<_> = self.templates.keys().collect();
+        names.sort();
+
+        let inline_partials: Vec<_> = self
+            .templates
+            .values()
+            .flat_map(|t| t.references().inline_partials)
+            .collect();
+        let partials: BTreeSet<&str> = names
+            .iter()
+            .map(|n| n.as_str())
+            .chain(inline_partials.iter().map(String::as_str))
+            .collect();
+
+        let errors: Vec<_> = names
+            .into_iter()
+            .flat_map(|name| {
+                analysis::unknown_references(self, &self.templates[name], Some(&partials))
+                    .into_iter()
+                    .map(move |(reason, pos)| {
+                        let e = TemplateError::of(reason);
+                        let e = match pos {
+                            Some((line, col)) => e.at_pos(line, col),
+                            None => e,
+                        };
+                        e.in_template(name.to_owned())
+                    })
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn check_known_helpers(
+        &self,
+        name: &str,
+        tpl_str: &str,
+        template: Template,
+    ) -> Result<Template, TemplateError> {
+        if self.known_helpers_only {
+            if let Some((reason, pos)) = analysis::unknown_references(self, &template, None)
+                .into_iter()
+                .next()
+            {
+                let e = TemplateError::of(reason);
+                let e = match pos {
+                    Some((line, col)) => e.at(tpl_str, line, col),
+                    None => e,
+                };
+                return Err(e.in_template(name.to_owned()));
+            }
+        }
+        Ok(template)
+    }
+
     /// Register a `Template`
     ///
     /// This is infallible since the template has already been parsed and
@@ -280,12 +549,16 @@ impl<'reg> Registry<'reg> {
     /// Dev mode doesn't apply for pre-compiled template because it's lifecycle
     /// is not managed by the registry.
     pub fn register_template(&mut self, name: &str, tpl: Template) {
+        if let Some(coverage) = &self.coverage {
+            coverage.declare(name, &tpl);
+        }
         self.templates.insert(name.to_string(), tpl);
     }
 
     /// Register a template string
     ///
-    /// Returns `TemplateError` if there is syntax error on parsing the template.
+    /// Returns `TemplateError` if there is syntax error on parsing the template,
+    /// or if it uses unknown helpers with `known_helpers_only` enabled.
     pub fn register_template_string<S>(
         &mut self,
         name: &str,
@@ -300,8 +573,11 @@ impl<'reg> Registry<'reg> {
                 name: Some(name.to_owned()),
                 is_partial: false,
                 prevent_indent: self.prevent_indent,
+                delimiters: self.delimiters.clone(),
+                mustache_compat: self.mustache_compat,
             },
         )?;
+        let template = self.check_known_helpers(name, tpl_str.as_ref(), template)?;
         self.register_template(name, template);
         Ok(())
     }
@@ -386,35 +662,9 @@ impl<'reg> Registry<'reg> {
             .min_depth(1)
             .into_iter()
             .filter_map(|e| e.ok().map(|e| e.into_path()))
-            // Checks if extension matches
-            .filter(|tpl_path| {
-                tpl_path
-                    .to_string_lossy()
-                    .ends_with(options.tpl_extension.as_str())
-            })
-            // Rejects any hidden or temporary files.
-            .filter(|tpl_path| {
-                tpl_path
-                    .file_stem()
-                    .map(|stem| !options.ignore_file(&stem.to_string_lossy()))
-                    .unwrap_or(false)
-            })
             .filter_map(|tpl_path| {
-                tpl_path
-                    .strip_prefix(dir_path)
-                    .ok()
-                    .map(|tpl_canonical_name| {
-                        let tpl_name = tpl_canonical_name
-                            .components()
-                            .map(|component| component.as_os_str().to_string_lossy())
-                            .collect::<Vec<_>>()
-                            .join("/");
-
-                        tpl_name
-                            .strip_suffix(options.tpl_extension.as_str())
-                            .map(|s| s.to_owned())
-                            .unwrap_or(tpl_name)
-                    })
+                options
+                    .template_name(dir_path, &tpl_path)
                     .map(|tpl_canonical_name| (tpl_canonical_name, tpl_path))
             });
 
@@ -510,6 +760,18 @@ impl<'reg> Registry<'reg> {
         self.helpers.insert(name.to_string(), def.into());
     }
 
+    /// Register an async helper
+    ///
+    /// Templates calling async helpers are rendered with `render_async` or
+    /// `render_to_write_async`. See `AsyncHelperDef` for details.
+    pub fn register_async_helper(
+        &mut self,
+        name: &str,
+        def: Box<dyn AsyncHelperDef + Send + Sync + 'reg>,
+    ) {
+        self.register_helper(name, Box::new(AsyncHelper { def }));
+    }
+
     /// Unregister a helper
     pub fn unregister_helper(&mut self, name: &str) {
         self.helpers.remove(name);
@@ -636,8 +898,11 @@ impl<'reg> Registry<'reg> {
                             name: Some(name.to_owned()),
                             prevent_indent: self.prevent_indent,
                             is_partial: false,
+                            delimiters: self.delimiters.clone(),
+                            mustache_compat: self.mustache_compat,
                         },
                     )
+                    .and_then(|t| self.check_known_helpers(name, tpl_str.as_ref(), t))
                 })
                 .map(Cow::Owned)
                 .map_err(RenderError::from);
@@ -687,6 +952,13 @@ impl<'reg> Registry<'reg> {
         self.helpers.contains_key(name)
     }
 
+    /// Return the names of all registered helpers, built-in ones included
+    pub fn get_helper_names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.helpers.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
     /// Return a registered decorator
     #[inline]
     pub(crate) fn get_decorator(
@@ -705,6 +977,24 @@ impl<'reg> Registry<'reg> {
         &self.templates
     }
 
+    /// Collect the paths, helpers, decorators and partials used by a
+    /// registered template, `None` if no template is registered with this
+    /// name.
+    ///
+    /// Unlike `Template::references`, a simple mustache like `{{upper}}` is
+    /// reported as a helper when a helper is registered with that name.
+    pub fn analyze(&self, name: &str) -> Option<TemplateReferences> {
+        self.get_template(name)
+            .map(|t| analysis::collect_references(t, &|n| self.has_helper(n)))
+    }
+
+    /// Build the graph of partial inclusions between registered templates
+    ///
+    /// Use `DependencyGraph::cycles` to find templates including each other.
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        DependencyGraph::from_registry(self)
+    }
+
     /// Unregister all templates
     pub fn clear_templates(&mut self) {
         self.templates.clear();
@@ -735,11 +1025,92 @@ impl<'reg> Registry<'reg> {
         template: Cow<'_, Template>,
         ctx: &Context,
         output: &mut impl Output,
+    ) -> Result<(), RenderError> {
+        self.render_resolved_template_with(
+            name,
+            template,
+            ctx,
+            None,
+            output,
+            &RenderOptions::default(),
+            |_| {},
+        )
+    }
+
+    /// Render with options, and a hook to set up the render context
+    #[allow(clippy::too_many_arguments)]
+    fn render_resolved_template_with(
+        &self,
+        name: Option<&str>,
+        template: Cow<'_, Template>,
+        ctx: &Context,
+        value: Option<&dyn ContextValue>,
+        output: &mut impl Output,
+        options: &RenderOptions,
+        setup: impl FnOnce(&mut RenderContext<'_, '_>),
+    ) -> Result<(), RenderError> {
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::info_span!("render", template = root_template_name(name, &template)).entered();
+        let recorder = self.coverage.as_ref().map(|_| {
+            let root = name.or(template.name.as_deref());
+            Rc::new(RefCell::new(CoverageRecorder::new(root)))
+        });
+        let setup = |rc: &mut RenderContext<'_, '_>| {
+            rc.set_cancellation_token(options.cancellation_token.clone());
+            rc.set_coverage(recorder.clone());
+            setup(rc);
+        };
+
+        let limits = options.limits.as_ref().unwrap_or(&self.render_limits);
+        let result = if limits.is_unlimited() {
+            self.render_resolved_template_in(name, template, ctx, value, output, setup)
+        } else {
+            let state = Rc::new(LimitState::new(limits.clone()));
+            let mut limited_output = LimitedOutput::new(output, state.clone());
+            let result = self.render_resolved_template_in(
+                name,
+                template,
+                ctx,
+                value,
+                &mut limited_output,
+                |rc| {
+                    rc.set_limits(Some(state.clone()));
+                    setup(rc);
+                },
+            );
+            // writes over the limit fail with an IO error, report the limit
+            state.check_output().and(result)
+        };
+
+        // failed renders are covered up to the error
+        if let (Some(coverage), Some(recorder)) = (&self.coverage, recorder) {
+            if let Ok(recorder) = Rc::try_unwrap(recorder) {
+                coverage.merge(recorder.into_inner());
+            }
+        }
+        result
+    }
+
+    fn render_resolved_template_in(
+        &self,
+        name: Option<&str>,
+        template: Cow<'_, Template>,
+        ctx: &Context,
+        value: Option<&dyn ContextValue>,
+        output: &mut dyn Output,
+        setup: impl FnOnce(&mut RenderContext<'_, '_>),
     ) -> Result<(), RenderError> {
         if !self.dev_mode {
             let mut render_context = RenderContext::new(template.name.as_ref());
             render_context.set_recursive_lookup(self.recursive_lookup);
-            return template.render(self, ctx, &mut render_context, output);
+            render_context.set_mustache_compat(self.mustache_compat);
+            render_context.set_observer(self.observer.as_deref().map(|o| o as _));
+            if let Some(value) = value {
+                render_context.set_root_value(ctx, value);
+            }
+            setup(&mut render_context);
+            return render_context.render_root(self, &template, ctx, output, name);
         }
 
         let dev_mode_templates;
@@ -755,8 +1126,14 @@ impl<'reg> Registry<'reg> {
 
         render_context.set_dev_mode_templates(Some(&dev_mode_templates));
         render_context.set_recursive_lookup(self.recursive_lookup);
+        render_context.set_mustache_compat(self.mustache_compat);
+        render_context.set_observer(self.observer.as_deref().map(|o| o as _));
+        if let Some(value) = value {
+            render_context.set_root_value(ctx, value);
+        }
+        setup(&mut render_context);
 
-        template.render(self, ctx, &mut render_context, output)
+        render_context.render_root(self, template, ctx, output, name)
     }
 
     #[inline]
@@ -793,6 +1170,255 @@ impl<'reg> Registry<'reg> {
         output.into_string().map_err(RenderError::from)
     }
 
+    /// Render a registered template calling async helpers into a string
+    ///
+    /// The template is rendered as many times as needed to await the async
+    /// helpers it calls, see `AsyncHelperDef`. Sync helpers work unchanged.
+    pub async fn render_async<T>(&self, name: &str, data: &T) -> Result<String, RenderError>
+    where
+        T: Serialize,
+    {
+        self.render_async_with_options(name, data, &RenderOptions::default())
+            .await
+    }
+
+    /// Render a registered template calling async helpers into a string, with
+    /// the options of this render, see `RenderOptions`
+    ///
+    /// The limits and the cancellation token apply to all the renders of the
+    /// template, and the steps and iterations of all of them count against
+    /// the same limits.
+    pub async fn render_async_with_options<T>(
+        &self,
+        name: &str,
+        data: &T,
+        options: &RenderOptions,
+    ) -> Result<String, RenderError>
+    where
+        T: Serialize,
+    {
+        let mut output = StringOutput::new();
+        let ctx = Context::wraps(data)?;
+        self.render_async_to_output(name, &ctx, &mut output, options)
+            .await?;
+        output.into_string().map_err(RenderError::from)
+    }
+
+    /// Render a registered template calling async helpers, and write data to
+    /// the `std::io::Write`
+    ///
+    /// Data are written once all async helpers are awaited.
+    pub async fn render_to_write_async<T, W>(
+        &self,
+        name: &str,
+        data: &T,
+        writer: W,
+    ) -> Result<(), RenderError>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let mut output = WriteOutput::new(writer);
+        let ctx = Context::wraps(data)?;
+        self.render_async_to_output(name, &ctx, &mut output, &RenderOptions::default())
+            .await
+    }
+
+    async fn render_async_to_output<O>(
+        &self,
+        name: &str,
+        ctx: &Context,
+        output: &mut O,
+        options: &RenderOptions,
+    ) -> Result<(), RenderError>
+    where
+        O: Output,
+    {
+        let calls = Arc::new(AsyncCalls::default());
+        loop {
+            let mut buffer = StringOutput::new();
+            let result = self.render_async_pass(name, ctx, &mut buffer, options, &calls);
+            // a render may fail on the `null` rendered for a call not
+            // awaited yet, so errors are reported once all calls are awaited
+            if !calls.resolve().await? {
+                result?;
+                // the render made no call, it is the output unless it
+                // skipped side effects
+                if self.observer.is_none() && self.coverage.is_none() && !calls.skipped_effects() {
+                    output.write(&buffer.into_string()?)?;
+                    return Ok(());
+                }
+                break;
+            }
+        }
+
+        calls.begin_final_pass();
+        self.render_async_pass(name, ctx, output, options, &calls)
+    }
+
+    /// A render of `render_async`, the ones before the last one skip side
+    /// effects
+    fn render_async_pass(
+        &self,
+        name: &str,
+        ctx: &Context,
+        output: &mut impl Output,
+        options: &RenderOptions,
+        calls: &Arc<AsyncCalls>,
+    ) -> Result<(), RenderError> {
+        calls.begin_pass();
+        let mut limits = None;
+        let result = self.render_resolved_template_with(
+            Some(name),
+            self.get_or_load_template(name)?,
+            ctx,
+            None,
+            output,
+            options,
+            |rc| {
+                rc.set_async_calls(Some(calls.clone()));
+                if let Some(state) = rc.limits() {
+                    state.resume(calls.limit_usage());
+                    limits = Some(state.clone());
+                }
+                if !calls.is_final_pass() {
+                    rc.set_observer(None);
+                    rc.set_coverage(None);
+                }
+            },
+        );
+        if let Some(state) = limits {
+            calls.set_limit_usage(state.usage());
+        }
+        result
+    }
+
+    /// Render a registered template with other resource limits than the ones
+    /// of the registry, see `RenderLimits`
+    pub fn render_with_limits<T>(
+        &self,
+        name: &str,
+        data: &T,
+        limits: &RenderLimits,
+    ) -> Result<String, RenderError>
+    where
+        T: Serialize,
+    {
+        let options = RenderOptions {
+            limits: Some(limits.clone()),
+            ..Default::default()
+        };
+        self.render_with_options(name, data, &options)
+    }
+
+    /// Render a registered template with the options of this render, see
+    /// `RenderOptions`
+    pub fn render_with_options<T>(
+        &self,
+        name: &str,
+        data: &T,
+        options: &RenderOptions,
+    ) -> Result<String, RenderError>
+    where
+        T: Serialize,
+    {
+        let mut output = StringOutput::new();
+        let ctx = Context::wraps(data)?;
+        self.render_resolved_template_with(
+            Some(name),
+            self.get_or_load_template(name)?,
+            &ctx,
+            None,
+            &mut output,
+            options,
+            |_| {},
+        )?;
+        output.into_string().map_err(RenderError::from)
+    }
+
+    /// Render a registered template with the options of this render, and
+    /// write data to the `std::io::Write`
+    ///
+    /// With a `CancellationToken` in the options, the render can be stopped
+    /// while data are written, when the client disconnects for instance.
+    pub fn render_to_write_with_options<T, W>(
+        &self,
+        name: &str,
+        data: &T,
+        writer: W,
+        options: &RenderOptions,
+    ) -> Result<(), RenderError>
+    where
+        T: Serialize,
+        W: Write,
+    {
+        let mut output = WriteOutput::new(writer);
+        let ctx = Context::wraps(data)?;
+        self.render_resolved_template_with(
+            Some(name),
+            self.get_or_load_template(name)?,
+            &ctx,
+            None,
+            &mut output,
+            options,
+            |_| {},
+        )
+    }
+
+    /// Render a registered template, and time the templates, partials,
+    /// helpers and decorators called by the render, see `RenderProfile`
+    pub fn render_with_profile<T>(
+        &self,
+        name: &str,
+        data: &T,
+    ) -> Result<(String, RenderProfile), RenderError>
+    where
+        T: Serialize,
+    {
+        let profiler = Rc::new(RefCell::new(Profiler::default()));
+        let mut output = StringOutput::new();
+        let ctx = Context::wraps(data)?;
+        self.render_resolved_template_with(
+            Some(name),
+            self.get_or_load_template(name)?,
+            &ctx,
+            None,
+            &mut output,
+            &RenderOptions::default(),
+            |rc| rc.set_profiler(Some(profiler.clone())),
+        )?;
+        let output = output.into_string().map_err(RenderError::from)?;
+        let profile = Rc::try_unwrap(profiler)
+            .expect("the render context sharing the profiler is dropped")
+            .into_inner()
+            .into_profile();
+        Ok((output, profile))
+    }
+
+    /// Render a registered template with data navigated by the paths of the
+    /// template, see `ContextValue`
+    ///
+    /// Unlike `render`, only the values used by the template are converted
+    /// to JSON. Partials without context parameter keep navigating the data
+    /// by path. A value given to a helper is converted whole, so is the root
+    /// data in `{{#with this}}` or `{{#each this}}`. Helpers and decorators
+    /// reading `Context::data` of the root context get `null`, they get the
+    /// data through `RenderContext::evaluate` or their parameters.
+    pub fn render_value(&self, name: &str, data: &dyn ContextValue) -> Result<String, RenderError> {
+        let mut output = StringOutput::new();
+        let ctx = Context::null();
+        self.render_resolved_template_with(
+            Some(name),
+            self.get_or_load_template(name)?,
+            &ctx,
+            Some(data),
+            &mut output,
+            &RenderOptions::default(),
+            |_| {},
+        )?;
+        output.into_string().map_err(RenderError::from)
+    }
+
     /// Render a registered template with reused context
     pub fn render_with_context(&self, name: &str, ctx: &Context) -> Result<String, RenderError> {
         let mut output = StringOutput::new();
@@ -846,6 +1472,8 @@ impl<'reg> Registry<'reg> {
             template_string,
             TemplateOptions {
                 prevent_indent: self.prevent_indent,
+                delimiters: self.delimiters.clone(),
+                mustache_compat: self.mustache_compat,
                 ..Default::default()
             },
         )
@@ -872,6 +1500,8 @@ impl<'reg> Registry<'reg> {
             template_string,
             TemplateOptions {
                 prevent_indent: self.prevent_indent,
+                delimiters: self.delimiters.clone(),
+                mustache_compat: self.mustache_compat,
                 ..Default::default()
             },
         )
@@ -896,6 +1526,75 @@ impl<'reg> Registry<'reg> {
         self.render_template_with_context_to_write(template_string, &ctx, writer)
     }
 
+    /// Register the layout helpers `extend`, `block` and `content`
+    ///
+    /// They are not registered by default, because they would take over
+    /// values with the same names, like `{{content}}`.
+    ///
+    /// A layout marks its replaceable regions with
+    /// `{{#block "name"}}default{{/block}}`. A template extends it with
+    /// `{{#extend "layout"}}...{{/extend}}`, which renders the layout partial
+    /// with the `{{#content "name"}}...{{/content}}` defined in its block.
+    /// `mode="append"` or `mode="prepend"` keeps the default content of the
+    /// block instead of replacing it, and `(content "name")` tells whether
+    /// content is defined for a block. Layouts can extend other layouts.
+    ///
+    /// ```
+    /// use handlebars::Handlebars;
+    ///
+    /// let mut hbs = Handlebars::new();
+    /// hbs.register_layout_helpers();
+    /// hbs.register_template_string(
+    ///     "layout",
+    ///     "<h1>{{#block \"title\"}}Site{{/block}}</h1>{{#block \"body\"}}{{/block}}",
+    /// )
+    /// .unwrap();
+    /// hbs.register_template_string(
+    ///     "page",
+    ///     "{{#extend \"layout\"}}\
+    ///      {{#content \"title\" mode=\"append\"}} - Home{{/content}}\
+    ///      {{#content \"body\"}}Welcome {{name}}{{/content}}\
+    ///      {{/extend}}",
+    /// )
+    /// .unwrap();
+    ///
+    /// let page = hbs.render("page", &serde_json::json!({"name": "Ann"})).unwrap();
+    /// assert_eq!(page, "<h1>Site - Home</h1>Welcome Ann");
+    /// ```
+    pub fn register_layout_helpers(&mut self) {
+        use helpers::helper_layout::{BLOCK_HELPER, CONTENT_HELPER, EXTEND_HELPER};
+
+        self.register_helper("extend", Box::new(EXTEND_HELPER));
+        self.register_helper("block", Box::new(BLOCK_HELPER));
+        self.register_helper("content", Box::new(CONTENT_HELPER));
+    }
+
+    /// Register a `blockHelperMissing` helper rendering a block without
+    /// helper nor parameter, like `{{#people}}...{{/people}}`, as a section
+    /// on the value of its name, like handlebars.js does
+    ///
+    /// The block is rendered once for each item of a non-empty list, once
+    /// with the value as context for other values than `false` and `null`,
+    /// and the inverse in other cases. Without it, such a block fails with
+    /// `HelperNotFound`, except in Mustache compatibility mode.
+    ///
+    /// ```
+    /// use handlebars::Handlebars;
+    ///
+    /// let mut hbs = Handlebars::new();
+    /// hbs.register_section_helper();
+    /// let data = serde_json::json!({"people": [{"name": "a"}, {"name": "b"}]});
+    /// let output = hbs
+    ///     .render_template("{{#people}}{{name}}{{/people}}", &data)
+    ///     .unwrap();
+    /// assert_eq!(output, "ab");
+    /// ```
+    pub fn register_section_helper(&mut self) {
+        use helpers::helper_section::SECTION_HELPER;
+
+        self.register_helper("blockHelperMissing", Box::new(SECTION_HELPER));
+    }
+
     #[cfg(feature = "string_helpers")]
     #[inline]
     fn register_string_helpers(&mut self) {
@@ -918,7 +1617,7 @@ impl<'reg> Registry<'reg> {
 #[cfg(test)]
 mod test {
     use crate::context::Context;
-    use crate::error::{RenderError, RenderErrorReason};
+    use crate::error::{RenderError, RenderErrorReason, TemplateErrorReason};
     use crate::helpers::HelperDef;
     use crate::output::Output;
     use crate::registry::Registry;
@@ -1534,6 +2233,77 @@ mod test {
         dir.close().unwrap();
     }
 
+    #[test]
+    fn test_known_helpers_only() {
+        let mut r = Registry::new();
+        r.set_known_helpers_only(true);
+
+        let e = r
+            .register_template_string("t", "<ul>\n{{#eahc items}}{{this}}{{/eahc}}\n</ul>")
+            .unwrap_err();
+        assert!(matches!(
+            e.reason(),
+            TemplateErrorReason::UnknownHelper(h) if h == "eahc"
+        ));
+        assert_eq!(e.pos(), Some((2, 1)));
+        assert_eq!(e.name(), Some(&"t".to_owned()));
+        assert!(!r.has_template("t"));
+
+        // a simple mustache is a value lookup
+        r.register_template_string("t", "{{#each items}}{{name}}{{/each}}")
+            .unwrap();
+        r.register_template_string("u", "{{*deco}}").unwrap_err();
+
+        r.register_helper("eahc", Box::new(DummyHelper));
+        r.register_template_string("t", "{{#eahc items}}{{this}}{{/eahc}}")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut r = Registry::new();
+        r.register_template_string("base", "{{> header}}{{> content}}{{> footer}}")
+            .unwrap();
+        r.register_template_string(
+            "page",
+            "{{#*inline \"content\"}}{{upper title}}{{/inline}}\n{{> base}}\n{{#> sidebar}}none{{/sidebar}}",
+        )
+        .unwrap();
+        r.register_template_string("header", "{{#if title}}{{title}}{{else unlss x}}{{/if}}")
+            .unwrap();
+
+        let errors = r.validate().unwrap_err();
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|e| (e.name().unwrap().as_str(), e.pos(), e.reason().to_string()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    "base",
+                    Some((1, 26)),
+                    "partial \"footer\" is not registered".to_owned()
+                ),
+                (
+                    "header",
+                    Some((1, 1)),
+                    "helper \"unlss\" is not registered".to_owned()
+                ),
+                (
+                    "page",
+                    Some((1, 23)),
+                    "helper \"upper\" is not registered".to_owned()
+                ),
+            ]
+        );
+
+        r.register_template_string("footer", "").unwrap();
+        r.register_helper("unlss", Box::new(DummyHelper));
+        r.register_helper("upper", Box::new(DummyHelper));
+        assert!(r.validate().is_ok());
+    }
+
     #[test]
     #[cfg(feature = "script_helper")]
     fn test_engine_access() {
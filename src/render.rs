@@ -1,18 +1,28 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::{BTreeMap, VecDeque};
 use std::fmt;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use serde_json::value::Value as Json;
 
+use crate::async_helper::AsyncCalls;
 use crate::block::BlockContext;
-use crate::context::Context;
+use crate::context::{Context, ContextValue};
+use crate::coverage::CoverageRecorder;
 use crate::error::RenderError;
 use crate::helpers::HelperDef;
+use crate::helpers::helper_layout::LayoutContent;
 use crate::json::path::Path;
-use crate::json::value::{JsonRender, PathAndJson, ScopedJson};
-use crate::output::{Output, StringOutput};
-use crate::registry::Registry;
+use crate::json::value::{JsonRender, LazyJson, PathAndJson, ScopedJson};
+use crate::limits::{BufferOutput, LimitState};
+use crate::observer::RenderObserver;
+use crate::output::Output;
+use crate::partial::PartialBlockContent;
+use crate::profile::{ProfileKind, Profiler};
+use crate::registry::{Registry, root_template_name};
+use crate::render_options::CancellationToken;
 use crate::support;
 use crate::support::str::newline_matcher;
 use crate::template::TemplateElement::{
@@ -23,7 +33,7 @@ use crate::template::{
     BlockParam, DecoratorTemplate, HelperTemplate, Parameter, Template, TemplateElement,
     TemplateMapping,
 };
-use crate::{RenderErrorReason, partial};
+use crate::{RenderErrorReason, helpers, partial};
 
 const HELPER_MISSING: &str = "helperMissing";
 const BLOCK_HELPER_MISSING: &str = "blockHelperMissing";
@@ -45,8 +55,14 @@ pub struct RenderContext<'reg: 'rc, 'rc> {
     // when rendering partials, store rendered string into partial_block_stack
     // for `@partial-block` referencing. If it's a `{{> partial}}`, push `None`
     // to stack and report error when child partial try to referencing
-    // `@partial-block`.
-    partial_block_stack: VecDeque<Option<String>>,
+    // `@partial-block`. The slots defined in the partial block by
+    // `{{#*slot}}` are stored along with it for `@slot.name` referencing.
+    partial_block_stack: VecDeque<PartialBlockContent>,
+    // slots of the partial block being rendered, taken when it is pushed
+    pending_slots: Option<BTreeMap<String, String>>,
+    // contents defined by `{{#content}}` for the blocks of the layouts being
+    // extended, one frame per `{{#extend}}`, the innermost first
+    layouts: VecDeque<BTreeMap<String, Vec<LayoutContent>>>,
     local_helpers: BTreeMap<String, Rc<dyn HelperDef + Send + Sync + 'rc>>,
     /// current template name
     current_template: Option<&'rc String>,
@@ -69,6 +85,25 @@ pub struct RenderContext<'reg: 'rc, 'rc> {
     // Threads the recursive_lookup state from the registry down
     // through to the context navigation layer
     recursive_lookup: bool,
+    // Mustache lookup through the stack of contexts
+    mustache_compat: bool,
+    // calls to async helpers, when rendering with `render_async`
+    async_calls: Option<Arc<AsyncCalls>>,
+    // usage of the resources limited by `RenderLimits`, shared by copies of
+    // the context
+    limits: Option<Rc<LimitState>>,
+    cancellation_token: Option<CancellationToken>,
+    // timing of the render, when rendering with `render_with_profile`
+    profiler: Option<Rc<RefCell<Profiler>>>,
+    // rendered elements and branches, when the registry collects coverage
+    coverage: Option<Rc<RefCell<CoverageRecorder>>>,
+    observer: Option<&'reg dyn RenderObserver>,
+    // the data of the root context, when rendering with `render_value`
+    root_value: Option<(&'rc Context, &'rc dyn ContextValue)>,
+    // the template whose elements are rendered, in the span fields of
+    // partials and helpers. Unlike `current_template`, it is kept in blocks.
+    #[cfg(feature = "tracing")]
+    source_template: Option<&'rc str>,
 }
 
 impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
@@ -80,6 +115,17 @@ impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
         let modified_context = None;
         RenderContext {
             partial_block_stack: VecDeque::new(),
+            async_calls: None,
+            limits: None,
+            cancellation_token: None,
+            profiler: None,
+            coverage: None,
+            observer: None,
+            root_value: None,
+            #[cfg(feature = "tracing")]
+            source_template: root_template.map(String::as_str),
+            pending_slots: None,
+            layouts: VecDeque::new(),
             local_helpers: BTreeMap::new(),
             current_template: None,
             root_template,
@@ -92,19 +138,23 @@ impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
             modified_context,
             dev_mode_templates: None,
             recursive_lookup: false,
+            mustache_compat: false,
         }
     }
 
     /// Push a block context into render context stack. This is typically
     /// called when you entering a block scope.
     pub fn push_block(&mut self, block: BlockContext<'rc>) {
+        self.observe(|o| o.push_block(&block));
         self.blocks.push_front(block);
     }
 
     /// Pop and drop current block context.
     /// This is typically called when leaving a block scope.
     pub fn pop_block(&mut self) {
-        self.blocks.pop_front();
+        if let Some(block) = self.blocks.pop_front() {
+            self.observe(|o| o.pop_block(&block));
+        }
     }
 
     /// Remove all blocks
@@ -162,13 +212,36 @@ impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
         context: &'rc Context,
         path: &Path,
     ) -> Result<ScopedJson<'rc>, RenderError> {
-        match path {
+        let value = self.root_value_of(context);
+        let result = match path {
             Path::Local((level, name, _)) => Ok(self
                 .get_local_var(*level, name)
                 .map_or_else(|| ScopedJson::Missing, |v| ScopedJson::Derived(v.clone()))),
+            Path::Relative((segs, _)) if self.mustache_compat => {
+                context.navigate_context_stack(segs, &self.blocks, value)
+            }
             Path::Relative((segs, _)) => {
-                context.navigate(segs, &self.blocks, self.recursive_lookup)
+                context.navigate(segs, &self.blocks, self.recursive_lookup, value)
+            }
+        };
+        // the current context evaluated by partials is not a lookup of the
+        // template
+        if let (Ok(value), false) = (&result, path.raw().is_empty()) {
+            self.observe(|o| o.lookup(path.raw(), (!value.is_missing()).then(|| value.as_json())));
+        }
+        result
+    }
+
+    /// The sequence inserted by `Context::insert_iter` at `path`, when the
+    /// path is evaluated in `context`
+    fn items_at(&self, context: &'rc Context, path: &Path) -> Option<(&'rc LazyJson, Vec<String>)> {
+        match path {
+            Path::Relative((segs, _)) if self.root_value_of(context).is_none() => {
+                let items = context.items_at(segs, &self.blocks)?;
+                self.observe(|o| o.lookup(path.raw(), Some(items.0.get())));
+                Some(items)
             }
+            _ => None,
         }
     }
 
@@ -184,8 +257,15 @@ impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
         None
     }
 
-    pub(crate) fn push_partial_block(&mut self, partial_block: Option<String>) {
-        self.partial_block_stack.push_front(partial_block);
+    pub(crate) fn push_partial_block(
+        &mut self,
+        partial_block: Option<String>,
+        slots: BTreeMap<String, String>,
+    ) {
+        self.partial_block_stack.push_front(PartialBlockContent {
+            content: partial_block,
+            slots,
+        });
     }
 
     pub(crate) fn pop_partial_block(&mut self) {
@@ -193,7 +273,63 @@ impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
     }
 
     pub(crate) fn peek_partial_block(&self) -> Option<&Option<String>> {
-        self.partial_block_stack.front()
+        self.partial_block_stack.front().map(|b| &b.content)
+    }
+
+    /// Rendered content of a slot of the current partial block
+    pub(crate) fn get_slot(&self, name: &str) -> Option<&str> {
+        self.partial_block_stack
+            .front()
+            .and_then(|b| b.slots.get(name))
+            .map(String::as_str)
+    }
+
+    /// Start collecting the slots of a partial block, returns the slots
+    /// collected for an enclosing partial block to give back to
+    /// `finish_slots`
+    pub(crate) fn start_slots(&mut self) -> Option<BTreeMap<String, String>> {
+        self.pending_slots.replace(BTreeMap::new())
+    }
+
+    pub(crate) fn finish_slots(
+        &mut self,
+        enclosing: Option<BTreeMap<String, String>>,
+    ) -> BTreeMap<String, String> {
+        std::mem::replace(&mut self.pending_slots, enclosing).unwrap_or_default()
+    }
+
+    /// Add a slot to the partial block being rendered, ignored outside of
+    /// partial blocks
+    pub(crate) fn add_slot(&mut self, name: String, content: String) {
+        if let Some(slots) = self.pending_slots.as_mut() {
+            slots.insert(name, content);
+        }
+    }
+
+    pub(crate) fn push_layout(&mut self) {
+        self.layouts.push_front(BTreeMap::new());
+    }
+
+    pub(crate) fn pop_layout(&mut self) {
+        self.layouts.pop_front();
+    }
+
+    /// Add content to a block of the layout currently extended, ignored
+    /// outside of `{{#extend}}`
+    pub(crate) fn add_layout_content(&mut self, name: &str, content: LayoutContent) {
+        if let Some(frame) = self.layouts.front_mut() {
+            frame.entry(name.to_owned()).or_default().push(content);
+        }
+    }
+
+    /// Contents of a layout block in the order they apply: from the outermost
+    /// `{{#extend}}` to the innermost one, in definition order
+    pub(crate) fn layout_contents(&self, name: &str) -> impl Iterator<Item = &LayoutContent> {
+        self.layouts
+            .iter()
+            .rev()
+            .filter_map(move |frame| frame.get(name))
+            .flatten()
     }
 
     pub(crate) fn set_indent_string(&mut self, indent: Option<Cow<'rc, str>>) {
@@ -316,6 +452,185 @@ impl<'reg: 'rc, 'rc> RenderContext<'reg, 'rc> {
     pub fn set_recursive_lookup(&mut self, enabled: bool) {
         self.recursive_lookup = enabled;
     }
+
+    pub(crate) fn set_mustache_compat(&mut self, enabled: bool) {
+        self.mustache_compat = enabled;
+    }
+
+    pub(crate) fn set_async_calls(&mut self, calls: Option<Arc<AsyncCalls>>) {
+        self.async_calls = calls;
+    }
+
+    pub(crate) fn async_calls(&self) -> Option<&AsyncCalls> {
+        self.async_calls.as_deref()
+    }
+
+    /// Whether the side effects of helpers, like logs, are skipped: this is
+    /// a pass of `render_async` before all the async helpers are awaited
+    pub(crate) fn skip_effects(&self) -> bool {
+        self.async_calls().is_some_and(AsyncCalls::skip_effect)
+    }
+
+    pub(crate) fn set_limits(&mut self, limits: Option<Rc<LimitState>>) {
+        self.limits = limits;
+    }
+
+    pub(crate) fn limits(&self) -> Option<&Rc<LimitState>> {
+        self.limits.as_ref()
+    }
+
+    pub(crate) fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.cancellation_token = token;
+    }
+
+    pub(crate) fn set_profiler(&mut self, profiler: Option<Rc<RefCell<Profiler>>>) {
+        self.profiler = profiler;
+    }
+
+    pub(crate) fn set_coverage(&mut self, coverage: Option<Rc<RefCell<CoverageRecorder>>>) {
+        self.coverage = coverage;
+    }
+
+    #[inline]
+    fn cover_template(&self, t: &Template) {
+        if let Some(coverage) = &self.coverage {
+            coverage.borrow_mut().template(t);
+        }
+    }
+
+    #[inline]
+    fn cover_element(&self, t: &Template, idx: usize) {
+        if let Some(coverage) = &self.coverage {
+            coverage.borrow_mut().element(t, idx);
+        }
+    }
+
+    /// Set the template whose elements are rendered, returns the previous
+    /// one. Templates without name, like inline partials, keep it.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn enter_source_template(&mut self, name: Option<&'rc str>) -> Option<&'rc str> {
+        let before = self.source_template;
+        self.source_template = name.or(before);
+        before
+    }
+
+    #[cfg(feature = "tracing")]
+    pub(crate) fn leave_source_template(&mut self, before: Option<&'rc str>) {
+        self.source_template = before;
+    }
+
+    pub(crate) fn set_observer(&mut self, observer: Option<&'reg dyn RenderObserver>) {
+        self.observer = observer;
+    }
+
+    /// Navigate `ctx` through `value`, when rendering a `ContextValue`
+    pub(crate) fn set_root_value(&mut self, ctx: &'rc Context, value: &'rc dyn ContextValue) {
+        self.root_value = Some((ctx, value));
+    }
+
+    /// The value `context` is navigated through, if it's rendered from a
+    /// `ContextValue`
+    fn root_value_of(&self, context: &Context) -> Option<&'rc dyn ContextValue> {
+        // contexts set by decorators or partials hold their data
+        self.root_value
+            .filter(|(root, _)| std::ptr::eq(*root, context))
+            .map(|(_, value)| value)
+    }
+
+    /// Whether the current context is a path of the `ContextValue` rendered,
+    /// rather than JSON data
+    pub(crate) fn is_value_path(&self, context: &Context) -> bool {
+        self.modified_context.is_none()
+            && self.root_value_of(context).is_some()
+            && self.block().is_none_or(|b| b.base_value().is_none())
+    }
+
+    #[inline]
+    pub(crate) fn observe(&self, f: impl FnOnce(&dyn RenderObserver)) {
+        if let Some(observer) = self.observer {
+            f(observer);
+        }
+    }
+
+    /// Render the template rendered by the registry
+    pub(crate) fn render_root(
+        &mut self,
+        registry: &'reg Registry<'reg>,
+        template: &'rc Template,
+        ctx: &'rc Context,
+        out: &mut dyn Output,
+        name: Option<&str>,
+    ) -> Result<(), RenderError> {
+        let name = root_template_name(name, template);
+        self.observe(|o| o.enter_template(name));
+        let result = self.profiled(ProfileKind::Template, name, |rc| {
+            template.render(registry, ctx, rc, out)
+        });
+        self.observe(|o| o.leave_template(name));
+        result
+    }
+
+    /// Run `f` as a call of a template, partial, helper or decorator, timed
+    /// when the render is profiled
+    #[inline]
+    pub(crate) fn profiled<T>(
+        &mut self,
+        kind: ProfileKind,
+        name: &str,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let Some(profiler) = self.profiler.clone() else {
+            return f(self);
+        };
+        profiler.borrow_mut().enter(kind, name);
+        let result = f(self);
+        profiler.borrow_mut().exit();
+        result
+    }
+
+    #[inline]
+    fn check_cancelled(&self) -> Result<(), RenderError> {
+        match &self.cancellation_token {
+            Some(token) if token.is_cancelled() => Err(RenderErrorReason::Cancelled.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check for cancellation before rendering a template element, and count
+    /// it against `RenderLimits`
+    #[inline]
+    pub(crate) fn check_step(&self) -> Result<(), RenderError> {
+        self.check_cancelled()?;
+        self.limits.as_ref().map_or(Ok(()), |l| l.step())
+    }
+
+    /// Check for cancellation before a loop iteration, and count it against
+    /// `RenderLimits`
+    #[inline]
+    pub(crate) fn check_iteration(&self) -> Result<(), RenderError> {
+        self.check_cancelled()?;
+        self.limits.as_ref().map_or(Ok(()), |l| l.iteration())
+    }
+
+    /// Enter a partial or a block helper, checking the nesting depth against
+    /// `RenderLimits`. Must be paired with `leave_nested`.
+    #[inline]
+    pub(crate) fn enter_nested(&self) -> Result<(), RenderError> {
+        self.limits.as_ref().map_or(Ok(()), |l| l.enter())
+    }
+
+    #[inline]
+    pub(crate) fn leave_nested(&self) {
+        if let Some(l) = &self.limits {
+            l.leave();
+        }
+    }
+
+    /// A buffer for content rendered before being written to the output,
+    /// counted against `RenderLimits`
+    pub(crate) fn buffer(&self) -> BufferOutput {
+        BufferOutput::new(self.limits.clone())
+    }
 }
 
 impl fmt::Debug for RenderContext<'_, '_> {
@@ -325,6 +640,8 @@ impl fmt::Debug for RenderContext<'_, '_> {
             .field("blocks", &self.blocks)
             .field("modified_context", &self.modified_context)
             .field("partial_block_stack", &self.partial_block_stack)
+            .field("pending_slots", &self.pending_slots)
+            .field("layouts", &self.layouts)
             .field("root_template", &self.root_template)
             .field("current_template", &self.current_template)
             .field("disable_escape", &self.disable_escape)
@@ -375,6 +692,15 @@ impl<'reg: 'rc, 'rc> Helper<'rc> {
         })
     }
 
+    /// Fail with the error collecting a sequence inserted by
+    /// `Context::insert_iter` that the call used as a value
+    fn check_computed(&self) -> Result<(), RenderError> {
+        self.params
+            .iter()
+            .chain(self.hash.values())
+            .try_for_each(PathAndJson::check_computed)
+    }
+
     /// Returns helper name
     pub fn name(&self) -> &str {
         &self.name
@@ -582,7 +908,7 @@ pub trait Renderable {
         ctx: &'rc Context,
         rc: &mut RenderContext<'reg, 'rc>,
     ) -> Result<String, RenderError> {
-        let mut so = StringOutput::new();
+        let mut so = rc.buffer();
         self.render(registry, ctx, rc, &mut so)?;
         so.into_string()
             .map_err(|e| RenderErrorReason::from(e).into())
@@ -599,6 +925,16 @@ pub trait Evaluable {
     ) -> Result<(), RenderError>;
 }
 
+#[cfg(feature = "tracing")]
+fn helper_span(ht: &HelperTemplate, rc: &RenderContext<'_, '_>) -> tracing::Span {
+    tracing::debug_span!(
+        "helper",
+        name = ht.name.as_name(),
+        template = rc.source_template,
+        line = ht.spans.element.start_pos.0
+    )
+}
+
 #[inline]
 fn call_helper_for_value<'reg: 'rc, 'rc>(
     hd: &dyn HelperDef,
@@ -606,13 +942,28 @@ fn call_helper_for_value<'reg: 'rc, 'rc>(
     r: &'reg Registry<'reg>,
     ctx: &'rc Context,
     rc: &mut RenderContext<'reg, 'rc>,
+) -> Result<PathAndJson<'rc>, RenderError> {
+    rc.observe(|o| o.helper(ht));
+    let result = rc.profiled(ProfileKind::Helper, ht.name(), |rc| {
+        call_helper_for_value_inner(hd, ht, r, ctx, rc)
+    });
+    ht.check_computed()?;
+    result
+}
+
+fn call_helper_for_value_inner<'reg: 'rc, 'rc>(
+    hd: &dyn HelperDef,
+    ht: &Helper<'rc>,
+    r: &'reg Registry<'reg>,
+    ctx: &'rc Context,
+    rc: &mut RenderContext<'reg, 'rc>,
 ) -> Result<PathAndJson<'rc>, RenderError> {
     match hd.call_inner(ht, r, ctx, rc) {
         Ok(result) => Ok(PathAndJson::new(None, result)),
         Err(e) => {
             if e.is_unimplemented() {
                 // parse value from output
-                let mut so = StringOutput::new();
+                let mut so = rc.buffer();
 
                 // here we don't want subexpression result escaped,
                 // so we temporarily disable it
@@ -670,6 +1021,12 @@ impl Parameter {
                         Some(path.raw().to_owned()),
                         ScopedJson::Derived(result.as_json().clone()),
                     ))
+                } else if let Some((items, paths)) = rc.items_at(ctx, path) {
+                    Ok(PathAndJson::from_items(
+                        Some(path.raw().to_owned()),
+                        items,
+                        paths,
+                    ))
                 } else {
                     let result = rc.evaluate2(ctx, path)?;
                     Ok(PathAndJson::new(Some(path.raw().to_owned()), result))
@@ -678,6 +1035,8 @@ impl Parameter {
             Parameter::Literal(j) => Ok(PathAndJson::new(None, ScopedJson::Constant(j))),
             Parameter::Subexpression(t) => match *t.as_element() {
                 Expression(ref ht) => {
+                    #[cfg(feature = "tracing")]
+                    let _span = helper_span(ht, rc).entered();
                     let name = ht.name.expand_as_name(registry, ctx, rc)?;
 
                     let h = Helper::try_from_template(ht, registry, ctx, rc)?;
@@ -716,10 +1075,19 @@ impl Renderable for Template {
         out: &mut dyn Output,
     ) -> Result<(), RenderError> {
         rc.set_current_template_name(self.name.as_ref());
+        rc.cover_template(self);
         let iter = self.elements.iter();
 
         for (idx, t) in iter.enumerate() {
-            t.render(registry, ctx, rc, out).map_err(|mut e| {
+            rc.cover_element(self, idx);
+            let async_scope = rc.async_calls().map(AsyncCalls::begin_element);
+            let result = rc
+                .check_step()
+                .and_then(|_| t.render(registry, ctx, rc, out));
+            if let (Some(calls), Some(used_pending)) = (rc.async_calls(), async_scope) {
+                calls.end_element(used_pending);
+            }
+            result.map_err(|mut e| {
                 // add line/col number if the template has mapping data
                 if e.line_no.is_none() {
                     if let Some(&TemplateMapping(line, col)) = self.mapping.get(idx) {
@@ -782,13 +1150,34 @@ fn render_helper<'reg: 'rc, 'rc>(
     rc: &mut RenderContext<'reg, 'rc>,
     out: &mut dyn Output,
 ) -> Result<(), RenderError> {
-    let h = Helper::try_from_template(ht, registry, ctx, rc)?;
+    #[cfg(feature = "tracing")]
+    let _span = helper_span(ht, rc).entered();
+    let mut h = Helper::try_from_template(ht, registry, ctx, rc)?;
+
+    // in Mustache compatibility mode, a block without helper nor parameter
+    // is a section on the value of its name
+    let section = registry.mustache_compat()
+        && ht.block
+        && ht.params.is_empty()
+        && ht.hash.is_empty()
+        && !helper_exists(h.name(), registry, rc);
+    if section {
+        let value = if let Some(rc_context) = rc.context() {
+            ScopedJson::Derived(rc.evaluate(&rc_context, h.name())?.as_json().clone())
+        } else {
+            rc.evaluate(ctx, h.name())?
+        };
+        h.params
+            .push(PathAndJson::new(Some(h.name().to_owned()), value));
+    }
+
     debug!(
         "Rendering helper: {:?}, params: {:?}, hash: {:?}",
         h.name(),
         h.params(),
         h.hash()
     );
+    rc.observe(|o| o.helper(&h));
     let mut call_indent_aware = |helper_def: &dyn HelperDef, rc: &mut RenderContext<'reg, 'rc>| {
         let indent_directive_before = rc.get_indent_before_write();
         let content_produced_before = rc.get_content_produced();
@@ -797,7 +1186,11 @@ fn render_helper<'reg: 'rc, 'rc>(
             indent_directive_before || (ht.indent_before_write && rc.get_trailine_newline()),
         );
 
-        helper_def.call(&h, registry, ctx, rc, out)?;
+        let result = rc.profiled(ProfileKind::Helper, h.name(), |rc| {
+            helper_def.call(&h, registry, ctx, rc, out)
+        });
+        h.check_computed()?;
+        result?;
 
         if rc.get_content_produced() {
             rc.set_indent_before_write(rc.get_trailine_newline());
@@ -807,7 +1200,9 @@ fn render_helper<'reg: 'rc, 'rc>(
         }
         Ok(())
     };
-    if let Some(ref d) = rc.get_local_helper(h.name()) {
+    if section {
+        call_indent_aware(&helpers::helper_section::SECTION_HELPER, rc)
+    } else if let Some(ref d) = rc.get_local_helper(h.name()) {
         call_indent_aware(&**d, rc)
     } else {
         let mut helper = registry.get_or_load_helper(h.name())?;
@@ -901,7 +1296,7 @@ impl Renderable for TemplateElement {
                                 }
                             }
                         } else {
-                            let rendered = context_json.value().render();
+                            let rendered = context_json.try_value()?.render();
                             let output = do_escape(registry, rc, rendered);
                             indent_aware_write(output.as_ref(), rc, out)
                         }
@@ -917,7 +1312,12 @@ impl Renderable for TemplateElement {
 
                 result
             }
-            HelperBlock(ht) => render_helper(ht, registry, ctx, rc, out),
+            HelperBlock(ht) => {
+                rc.enter_nested()?;
+                let result = render_helper(ht, registry, ctx, rc, out);
+                rc.leave_nested();
+                result
+            }
             DecoratorExpression(_) | DecoratorBlock(_) => self.eval(registry, ctx, rc),
             PartialExpression(dt) | PartialBlock(dt) => {
                 let di = Decorator::try_from_template(dt, registry, ctx, rc)?;
@@ -930,6 +1330,14 @@ impl Renderable for TemplateElement {
                 );
                 rc.set_content_produced(false);
 
+                #[cfg(feature = "tracing")]
+                let _span = tracing::debug_span!(
+                    "partial",
+                    name = di.name(),
+                    template = rc.source_template,
+                    line = dt.spans.element.start_pos.0
+                )
+                .entered();
                 partial::expand_partial(&di, registry, ctx, rc, out)?;
 
                 if rc.get_content_produced() {
@@ -956,7 +1364,12 @@ impl Evaluable for TemplateElement {
             DecoratorExpression(ref dt) | DecoratorBlock(ref dt) => {
                 let di = Decorator::try_from_template(dt, registry, ctx, rc)?;
                 match registry.get_decorator(di.name()) {
-                    Some(d) => d.call(&di, registry, ctx, rc),
+                    Some(d) => {
+                        rc.observe(|o| o.decorator(&di));
+                        rc.profiled(ProfileKind::Decorator, di.name(), |rc| {
+                            d.call(&di, registry, ctx, rc)
+                        })
+                    }
                     None => Err(RenderErrorReason::DecoratorNotFound(di.name().to_owned()).into()),
                 }
             }
@@ -1063,6 +1476,7 @@ mod test {
             elements,
             name: None,
             mapping: Vec::new(),
+            spans: Vec::new(),
         };
 
         {
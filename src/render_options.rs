@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use derive_builder::Builder;
+
+use crate::limits::RenderLimits;
+
+/// Options of a single render, for `Registry::render_with_options` and
+/// `Registry::render_to_write_with_options`
+#[non_exhaustive]
+#[derive(Builder, Clone, Debug, Default)]
+#[builder(default)]
+pub struct RenderOptions {
+    /// Resource limits of the render, the ones of the registry when not set
+    #[builder(setter(strip_option))]
+    pub limits: Option<RenderLimits>,
+    /// Token to cancel the render while it runs
+    #[builder(setter(strip_option))]
+    pub cancellation_token: Option<CancellationToken>,
+}
+
+/// A handle to cancel renders from another thread
+///
+/// The render checks the token between template elements and between
+/// iterations of `{{#each}}`, and fails with `RenderErrorReason::Cancelled`
+/// once it is cancelled. Clones of a token share its state, and a token
+/// cancels all the renders it is given to.
+///
+/// ```
+/// use handlebars::{CancellationToken, Handlebars, RenderErrorReason, RenderOptionsBuilder};
+///
+/// let mut hbs = Handlebars::new();
+/// hbs.register_template_string("t", "{{#each this}}{{this}}{{/each}}").unwrap();
+///
+/// let token = CancellationToken::new();
+/// let options = RenderOptionsBuilder::default()
+///     .cancellation_token(token.clone())
+///     .build()
+///     .unwrap();
+///
+/// // typically called when the client disconnects
+/// token.cancel();
+/// let err = hbs.render_with_options("t", &[1, 2, 3], &options).unwrap_err();
+/// assert!(matches!(err.reason(), RenderErrorReason::Cancelled));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel the renders using this token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Result as IOResult, Write};
+
+    use crate::registry::Registry;
+    use crate::{
+        CancellationToken, RenderErrorReason, RenderLimit, RenderLimitsBuilder,
+        RenderOptionsBuilder,
+    };
+
+    // a client that disconnects after receiving some bytes
+    struct Client {
+        received: usize,
+        token: CancellationToken,
+    }
+
+    impl Write for Client {
+        fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+            self.received += buf.len();
+            if self.received >= 4 {
+                self.token.cancel();
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> IOResult<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("t", "{{#each this}}{{#if this}}{{this}}{{/if}}{{/each}}")
+            .unwrap();
+        let token = CancellationToken::new();
+        let options = RenderOptionsBuilder::default()
+            .cancellation_token(token.clone())
+            .build()
+            .unwrap();
+
+        let data: Vec<u32> = (1..100).collect();
+        let mut client = Client {
+            received: 0,
+            token: token.clone(),
+        };
+        let err = hbs
+            .render_to_write_with_options("t", &data, &mut client, &options)
+            .unwrap_err();
+        assert!(matches!(err.reason(), RenderErrorReason::Cancelled));
+        assert_eq!(client.received, 4);
+
+        // renders are not cancelled without the token
+        assert!(hbs.render("t", &data).is_ok());
+    }
+
+    #[test]
+    fn test_options_limits() {
+        let mut hbs = Registry::new();
+        hbs.register_template_string("t", "{{#each this}}{{this}}{{/each}}")
+            .unwrap();
+        hbs.set_render_limits(
+            RenderLimitsBuilder::default()
+                .max_iterations(1)
+                .build()
+                .unwrap(),
+        );
+
+        let unlimited = RenderOptionsBuilder::default()
+            .limits(Default::default())
+            .build()
+            .unwrap();
+        assert_eq!(
+            hbs.render_with_options("t", &[1, 2], &unlimited).unwrap(),
+            "12"
+        );
+
+        let err = hbs
+            .render_with_options("t", &[1, 2], &Default::default())
+            .unwrap_err();
+        assert!(matches!(
+            err.reason(),
+            RenderErrorReason::LimitExceeded(RenderLimit::Iterations(1))
+        ));
+    }
+}
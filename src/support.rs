@@ -131,6 +131,33 @@ pub mod str {
             .starts_with(newline_matcher)
     }
 
+    /// Line and column, counted in chars from 1, of the byte `offset` of `s`
+    pub(crate) fn line_col(s: &str, offset: usize) -> (usize, usize) {
+        let before = &s[..offset];
+        let line = before.matches('\n').count() + 1;
+        let col = before
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count()
+            + 1;
+        (line, col)
+    }
+
+    /// Byte offset of the line and column of `s`
+    pub(crate) fn offset_of(s: &str, line: usize, col: usize) -> usize {
+        let line_start: usize = s
+            .split_inclusive('\n')
+            .take(line.saturating_sub(1))
+            .map(str::len)
+            .sum();
+        s[line_start..]
+            .char_indices()
+            .nth(col.saturating_sub(1))
+            .map_or(s.len(), |(i, _)| line_start + i)
+    }
+
     #[cfg(test)]
     mod test {
         use crate::support::str::StringWriter;
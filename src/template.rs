@@ -1,5 +1,6 @@
 use std::collections::{HashMap, VecDeque};
 use std::iter::Peekable;
+use std::ops::Range;
 use std::str::FromStr;
 
 use pest::error::LineColLocation;
@@ -7,12 +8,19 @@ use pest::iterators::Pair;
 use pest::{Parser, Position, Span};
 use serde_json::value::Value as Json;
 
+use crate::delimiters::{self, Delimiters};
 use crate::error::{TemplateError, TemplateErrorReason};
 use crate::grammar::{HandlebarsParser, Rule};
 use crate::json::path::{Path, parse_json_path_from_iter};
+use crate::recovery;
 use crate::support;
 
 use derive_builder::Builder;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "serde")]
+pub use crate::template_serde::{TEMPLATE_FORMAT_VERSION, VersionedTemplate};
 
 use self::TemplateElement::{
     Comment, DecoratorBlock, DecoratorExpression, Expression, HelperBlock, HtmlExpression,
@@ -21,33 +29,149 @@ use self::TemplateElement::{
 
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TemplateMapping(pub usize, pub usize);
 
+/// A range of the template source
+///
+/// `start` and `end` are byte offsets, `start_pos` and `end_pos` the line and
+/// column of both ends, counted like in [`TemplateMapping`].
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SourceSpan {
+    pub start: usize,
+    pub end: usize,
+    pub start_pos: (usize, usize),
+    pub end_pos: (usize, usize),
+}
+
+impl SourceSpan {
+    pub fn new(
+        start: usize,
+        end: usize,
+        start_pos: (usize, usize),
+        end_pos: (usize, usize),
+    ) -> SourceSpan {
+        SourceSpan {
+            start,
+            end,
+            start_pos,
+            end_pos,
+        }
+    }
+
+    /// Byte range of the span
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    fn of(span: &Span<'_>) -> SourceSpan {
+        SourceSpan::new(
+            span.start(),
+            span.end(),
+            span.start_pos().line_col(),
+            span.end_pos().line_col(),
+        )
+    }
+
+    pub(crate) fn between(source: &str, start: usize, end: usize) -> SourceSpan {
+        SourceSpan::new(
+            start,
+            end,
+            support::str::line_col(source, start),
+            support::str::line_col(source, end),
+        )
+    }
+}
+
+/// Source spans of an expression, a block or a subexpression
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExpressionSpans {
+    /// The whole element, up to the end of the close tag of blocks
+    pub element: SourceSpan,
+    /// The open tag of blocks, the same as `element` for expressions
+    pub open_tag: SourceSpan,
+    pub name: SourceSpan,
+    pub params: Vec<SourceSpan>,
+    /// Values of the hash, by key
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::template_serde::serialize_hash")
+    )]
+    pub hash: HashMap<String, SourceSpan>,
+    /// `as |name|` of blocks
+    pub block_param: Option<SourceSpan>,
+    /// The `{{else}}` tag of blocks, or `{{else name}}` starting a chain
+    pub inverse_tag: Option<SourceSpan>,
+    pub close_tag: Option<SourceSpan>,
+}
+
+impl ExpressionSpans {
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut SourceSpan> {
+        [&mut self.element, &mut self.open_tag, &mut self.name]
+            .into_iter()
+            .chain(self.params.iter_mut())
+            .chain(self.hash.values_mut())
+            .chain(self.block_param.iter_mut())
+            .chain(self.inverse_tag.iter_mut())
+            .chain(self.close_tag.iter_mut())
+    }
+
+    fn close(&mut self, tag: SourceSpan) {
+        self.element.end = tag.end;
+        self.element.end_pos = tag.end_pos;
+        self.close_tag = Some(tag);
+    }
+}
+
 /// A handlebars template
+///
+/// Templates compare equal regardless of their source spans.
 #[non_exhaustive]
-#[derive(Builder, PartialEq, Eq, Clone, Debug, Default)]
+#[derive(Builder, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Template {
     #[builder(setter(into, strip_option), default)]
     pub name: Option<String>,
     pub elements: Vec<TemplateElement>,
     pub mapping: Vec<TemplateMapping>,
+    /// Source span of each element
+    #[builder(default)]
+    pub spans: Vec<SourceSpan>,
 }
 
-#[derive(Default)]
+// spans are left out, so templates built in code compare equal to compiled
+// ones
+impl PartialEq for Template {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.elements == other.elements && self.mapping == other.mapping
+    }
+}
+
+impl Eq for Template {}
+
+#[derive(Clone, Default)]
 pub(crate) struct TemplateOptions {
     pub(crate) prevent_indent: bool,
     pub(crate) is_partial: bool,
     pub(crate) name: Option<String>,
+    pub(crate) delimiters: Delimiters,
+    // parse `{{^name}}` as an inverted section inside blocks too
+    pub(crate) mustache_compat: bool,
 }
 
 impl TemplateOptions {
-    fn name(&self) -> String {
+    pub(crate) fn name(&self) -> String {
         self.name.clone().unwrap_or_else(|| "Unnamed".to_owned())
     }
 }
 
 #[non_exhaustive]
 #[derive(Builder, PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Subexpression {
     // we use box here avoid resursive struct definition
     pub element: Box<TemplateElement>,
@@ -69,6 +193,7 @@ impl Subexpression {
                 block_param: None,
                 block: false,
                 chain: false,
+                spans: ExpressionSpans::default(),
                 indent_before_write: false,
             }))),
         }
@@ -110,6 +235,7 @@ impl Subexpression {
 
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum BlockParam {
     Single(Parameter),
     Pair((Parameter, Parameter)),
@@ -125,10 +251,13 @@ pub struct ExpressionSpec {
     pub block_param: Option<BlockParam>,
     pub omit_pre_ws: bool,
     pub omit_pro_ws: bool,
+    #[builder(default)]
+    pub spans: ExpressionSpans,
 }
 
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Parameter {
     // for helper name only
     Name(String),
@@ -139,10 +268,15 @@ pub enum Parameter {
 }
 
 #[non_exhaustive]
-#[derive(Builder, PartialEq, Eq, Clone, Debug)]
+#[derive(Builder, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct HelperTemplate {
     pub name: Parameter,
     pub params: Vec<Parameter>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::template_serde::serialize_hash")
+    )]
     pub hash: HashMap<String, Parameter>,
     #[builder(setter(strip_option), default)]
     pub block_param: Option<BlockParam>,
@@ -152,9 +286,28 @@ pub struct HelperTemplate {
     pub inverse: Option<Template>,
     pub block: bool,
     pub chain: bool,
+    #[builder(default)]
+    pub spans: ExpressionSpans,
     pub(crate) indent_before_write: bool,
 }
 
+// equal regardless of spans, like `Template`
+impl PartialEq for HelperTemplate {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.params == other.params
+            && self.hash == other.hash
+            && self.block_param == other.block_param
+            && self.template == other.template
+            && self.inverse == other.inverse
+            && self.block == other.block
+            && self.chain == other.chain
+            && self.indent_before_write == other.indent_before_write
+    }
+}
+
+impl Eq for HelperTemplate {}
+
 impl HelperTemplate {
     pub fn new(exp: ExpressionSpec, block: bool, indent_before_write: bool) -> HelperTemplate {
         HelperTemplate {
@@ -166,6 +319,7 @@ impl HelperTemplate {
             template: None,
             inverse: None,
             chain: false,
+            spans: exp.spans,
             indent_before_write,
         }
     }
@@ -184,6 +338,7 @@ impl HelperTemplate {
             template: None,
             inverse: None,
             chain: true,
+            spans: exp.spans,
             indent_before_write,
         }
     }
@@ -199,6 +354,7 @@ impl HelperTemplate {
             inverse: None,
             block: false,
             chain: false,
+            spans: ExpressionSpans::default(),
             indent_before_write: false,
         }
     }
@@ -274,6 +430,34 @@ impl HelperTemplate {
         }
     }
 
+    fn set_inverse_tag(&mut self, tag: SourceSpan) {
+        if let Some(head) = self.ref_chain_head_mut() {
+            head.spans.inverse_tag = Some(tag);
+        } else {
+            self.spans.inverse_tag = Some(tag);
+        }
+    }
+
+    // close the block and the nodes of its else chain, which is still in
+    // reverse order
+    fn close_spans(&mut self, tag: SourceSpan) {
+        self.spans.close(tag);
+        if !self.chain {
+            return;
+        }
+        let mut inverse = self.inverse.as_mut();
+        while let Some(t) = inverse {
+            inverse = match t.elements.first_mut() {
+                Some(HelperBlock(node)) => {
+                    node.spans.close(tag);
+                    t.spans = vec![node.spans.element];
+                    node.inverse.as_mut()
+                }
+                _ => None,
+            };
+        }
+    }
+
     fn set_chained(&mut self) {
         self.chain = true;
     }
@@ -284,19 +468,40 @@ impl HelperTemplate {
 }
 
 #[non_exhaustive]
-#[derive(Builder, PartialEq, Eq, Clone, Debug)]
+#[derive(Builder, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DecoratorTemplate {
     pub name: Parameter,
     pub params: Vec<Parameter>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::template_serde::serialize_hash")
+    )]
     pub hash: HashMap<String, Parameter>,
     #[builder(setter(strip_option), default)]
     pub template: Option<Template>,
     // for partial indent
     #[builder(setter(into, strip_option), default)]
     pub indent: Option<String>,
+    #[builder(default)]
+    pub spans: ExpressionSpans,
     pub(crate) indent_before_write: bool,
 }
 
+// equal regardless of spans, like `Template`
+impl PartialEq for DecoratorTemplate {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.params == other.params
+            && self.hash == other.hash
+            && self.template == other.template
+            && self.indent == other.indent
+            && self.indent_before_write == other.indent_before_write
+    }
+}
+
+impl Eq for DecoratorTemplate {}
+
 impl DecoratorTemplate {
     pub fn new(exp: ExpressionSpec, indent_before_write: bool) -> DecoratorTemplate {
         DecoratorTemplate {
@@ -305,6 +510,7 @@ impl DecoratorTemplate {
             hash: exp.hash,
             template: None,
             indent: None,
+            spans: exp.spans,
             indent_before_write,
         }
     }
@@ -324,77 +530,91 @@ impl Parameter {
             .map_err(|_| TemplateError::of(TemplateErrorReason::InvalidParam(s.to_owned())))?;
 
         let mut it = parser.flatten().peekable();
-        Template::parse_param(s, &mut it, s.len() - 1)
+        Template::parse_param(s, &mut it, s.len() - 1).map(|(param, _)| param)
     }
 
     fn debug_name(&self) -> String {
         if let Some(name) = self.as_name() {
             name.to_owned()
         } else {
-            format!("{self:?}")
+            crate::printer::parameter_to_source(self)
         }
     }
 }
 
+/// Name of a partial, without the brackets of `{{> [name]}}` or the quotes
+/// of `{{> 'name'}}`
+pub(crate) fn partial_name(raw: &str) -> String {
+    if let Some(name) = raw.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        name.to_owned()
+    } else if let Some(name) = raw.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        name.replace("\\'", "'")
+    } else {
+        raw.to_owned()
+    }
+}
+
 impl Template {
     pub fn new() -> Template {
         Template::default()
     }
 
-    fn push_element(&mut self, e: TemplateElement, line: usize, col: usize) {
+    fn push_element(&mut self, e: TemplateElement, line: usize, col: usize, span: SourceSpan) {
         self.elements.push(e);
         self.mapping.push(TemplateMapping(line, col));
+        self.spans.push(span);
     }
 
     fn parse_subexpression<'a, I>(
         source: &'a str,
         it: &mut Peekable<I>,
-        limit: usize,
+        span: Span<'a>,
     ) -> Result<Parameter, TemplateError>
     where
         I: Iterator<Item = Pair<'a, Rule>>,
     {
-        let espec = Template::parse_expression(source, it.by_ref(), limit)?;
-        Ok(Parameter::Subexpression(Subexpression::new(
-            espec.name,
-            espec.params,
-            espec.hash,
-        )))
+        let espec = Template::parse_expression(source, it.by_ref(), span.end())?;
+        let mut h = HelperTemplate::new(espec, false, false);
+        h.spans.element = SourceSpan::of(&span);
+        h.spans.open_tag = h.spans.element;
+        Ok(Parameter::Subexpression(Subexpression {
+            element: Box::new(Expression(Box::new(h))),
+        }))
     }
 
     fn parse_name<'a, I>(
         source: &'a str,
         it: &mut Peekable<I>,
         _: usize,
-    ) -> Result<Parameter, TemplateError>
+    ) -> Result<(Parameter, SourceSpan), TemplateError>
     where
         I: Iterator<Item = Pair<'a, Rule>>,
     {
         let name_node = it.next().unwrap();
         let rule = name_node.as_rule();
         let name_span = name_node.as_span();
-        match rule {
-            Rule::identifier
-            | Rule::partial_identifier
-            | Rule::opt_identifier
-            | Rule::opt_partial_identifier
-            | Rule::invert_tag_item => Ok(Parameter::Name(name_span.as_str().to_owned())),
+        let name = match rule {
+            Rule::identifier | Rule::opt_identifier | Rule::invert_tag_item => {
+                Ok(Parameter::Name(name_span.as_str().to_owned()))
+            }
+            Rule::partial_identifier | Rule::opt_partial_identifier => {
+                Ok(Parameter::Name(partial_name(name_span.as_str())))
+            }
             Rule::reference => {
                 let paths = parse_json_path_from_iter(it, name_span.end());
                 Ok(Parameter::Path(Path::new(name_span.as_str(), paths)))
             }
-            Rule::subexpression => {
-                Template::parse_subexpression(source, it.by_ref(), name_span.end())
-            }
+            Rule::subexpression => Template::parse_subexpression(source, it.by_ref(), name_span),
             other => unreachable!("{other:?}"),
-        }
+        }?;
+        Ok((name, SourceSpan::of(&name_span)))
     }
 
     fn parse_param<'a, I>(
         source: &'a str,
         it: &mut Peekable<I>,
         _: usize,
-    ) -> Result<Parameter, TemplateError>
+    ) -> Result<(Parameter, SourceSpan), TemplateError>
     where
         I: Iterator<Item = Pair<'a, Rule>>,
     {
@@ -439,9 +659,7 @@ impl Template {
                     )));
                 }
             }
-            Rule::subexpression => {
-                Template::parse_subexpression(source, it.by_ref(), param_span.end())?
-            }
+            Rule::subexpression => Template::parse_subexpression(source, it.by_ref(), param_span)?,
             _ => unreachable!(),
         };
 
@@ -453,14 +671,14 @@ impl Template {
             it.next();
         }
 
-        Ok(result)
+        Ok((result, SourceSpan::of(&param_span)))
     }
 
     fn parse_hash<'a, I>(
         source: &'a str,
         it: &mut Peekable<I>,
         limit: usize,
-    ) -> Result<(String, Parameter), TemplateError>
+    ) -> Result<(String, Parameter, SourceSpan), TemplateError>
     where
         I: Iterator<Item = Pair<'a, Rule>>,
     {
@@ -469,8 +687,8 @@ impl Template {
         // identifier
         let key = name_node.as_str().to_owned();
 
-        let value = Template::parse_param(source, it.by_ref(), limit)?;
-        Ok((key, value))
+        let (value, span) = Template::parse_param(source, it.by_ref(), limit)?;
+        Ok((key, value, span))
     }
 
     fn parse_block_param<'a, I>(_: &'a str, it: &mut Peekable<I>, limit: usize) -> BlockParam
@@ -518,35 +736,44 @@ impl Template {
             it.next();
         }
 
-        let name = Template::parse_name(source, it.by_ref(), limit)?;
+        let (name, name_span) = Template::parse_name(source, it.by_ref(), limit)?;
+        let mut spans = ExpressionSpans {
+            name: name_span,
+            ..Default::default()
+        };
 
         loop {
             let rule;
-            let end;
+            let span;
             if let Some(pair) = it.peek() {
                 let pair_span = pair.as_span();
                 if pair_span.end() < limit {
                     rule = pair.as_rule();
-                    end = pair_span.end();
+                    span = pair_span;
                 } else {
                     break;
                 }
             } else {
                 break;
             }
+            let end = span.end();
 
             it.next();
 
             match rule {
                 Rule::helper_parameter => {
-                    params.push(Template::parse_param(source, it.by_ref(), end)?);
+                    let (param, span) = Template::parse_param(source, it.by_ref(), end)?;
+                    params.push(param);
+                    spans.params.push(span);
                 }
                 Rule::hash => {
-                    let (key, value) = Template::parse_hash(source, it.by_ref(), end)?;
+                    let (key, value, span) = Template::parse_hash(source, it.by_ref(), end)?;
+                    spans.hash.insert(key.clone(), span);
                     hashes.insert(key, value);
                 }
                 Rule::block_param => {
                     block_param = Some(Template::parse_block_param(source, it.by_ref(), end));
+                    spans.block_param = Some(SourceSpan::of(&span));
                 }
                 Rule::trailing_tilde_to_omit_whitespace => {
                     omit_pro_ws = true;
@@ -561,6 +788,7 @@ impl Template {
             block_param,
             omit_pre_ws,
             omit_pro_ws,
+            spans,
         })
     }
 
@@ -589,8 +817,11 @@ impl Template {
         let mut with_trailing_newline = support::str::starts_with_empty_line(continuation);
 
         // For full templates, we behave as if there was a trailing newline if we encounter
-        // the end of input. See #611.
-        with_trailing_newline |= !is_partial && continuation.is_empty();
+        // the end of input, possibly after some whitespaces. See #611.
+        with_trailing_newline |= !is_partial
+            && continuation
+                .trim_start_matches(support::str::whitespace_matcher)
+                .is_empty();
 
         if with_trailing_newline {
             let with_leading_newline =
@@ -662,7 +893,23 @@ impl Template {
         source: &str,
         options: TemplateOptions,
     ) -> Result<Template, TemplateError> {
+        let translation = delimiters::translate(source, &options.delimiters)
+            .map_err(|e| e.in_template(options.name()))?;
+        match translation {
+            Some(translation) => {
+                let mut t = Template::parse(&translation.source, options)
+                    .map_err(|e| translation.restore_error(e, source))?;
+                translation.restore(&mut t, source);
+                Ok(t)
+            }
+            None => Template::parse(source, options),
+        }
+    }
+
+    pub(crate) fn parse(source: &str, options: TemplateOptions) -> Result<Template, TemplateError> {
         let mut helper_stack: VecDeque<HelperTemplate> = VecDeque::new();
+        // whether each helper of the stack is an inverted section `{{^name}}`
+        let mut inverted_stack: VecDeque<bool> = VecDeque::new();
         let mut decorator_stack: VecDeque<DecoratorTemplate> = VecDeque::new();
         let mut template_stack: VecDeque<Template> = VecDeque::new();
 
@@ -672,7 +919,12 @@ impl Template {
         // then the leading whitespaces and newline of next rawstring will be trimed
         let mut trim_line_required = false;
 
-        let parser_queue = HandlebarsParser::parse(Rule::handlebars, source).map_err(|e| {
+        let rule = if options.mustache_compat {
+            Rule::handlebars_compat
+        } else {
+            Rule::handlebars
+        };
+        let parser_queue = HandlebarsParser::parse(rule, source).map_err(|e| {
             let (line_no, col_no) = match e.line_col {
                 LineColLocation::Pos(line_col) => line_col,
                 LineColLocation::Span(line_col, _) => line_col,
@@ -701,7 +953,11 @@ impl Template {
                 let rule = pair.as_rule();
                 let span = pair.as_span();
 
-                let is_trailing_string = rule != Rule::template
+                let is_template = matches!(
+                    rule,
+                    Rule::template | Rule::template_root | Rule::template_compat
+                );
+                let is_trailing_string = !is_template
                     && span.start() != prev_end
                     && !omit_pro_ws
                     && rule != Rule::raw_text
@@ -721,6 +977,7 @@ impl Template {
                             ),
                             line_no,
                             col_no,
+                            SourceSpan::between(source, prev_end, span.start()),
                         );
                         template_stack.push_front(t);
                     } else {
@@ -734,6 +991,7 @@ impl Template {
                             ),
                             line_no,
                             col_no,
+                            SourceSpan::between(source, prev_end, span.start()),
                         );
                     }
 
@@ -743,7 +1001,7 @@ impl Template {
 
                 let (line_no, col_no) = span.start_pos().line_col();
                 match rule {
-                    Rule::template => {
+                    Rule::template | Rule::template_root | Rule::template_compat => {
                         template_stack.push_front(Template::new());
                     }
                     Rule::raw_text => {
@@ -765,16 +1023,20 @@ impl Template {
                             ),
                             line_no,
                             col_no,
+                            SourceSpan::between(source, start, span.end()),
                         );
 
                         // reset standalone statement marker
                         trim_line_required = false;
                     }
                     Rule::helper_block_start
+                    | Rule::inverted_block_start
                     | Rule::raw_block_start
                     | Rule::decorator_block_start
                     | Rule::partial_block_start => {
-                        let exp = Template::parse_expression(source, it.by_ref(), span.end())?;
+                        let mut exp = Template::parse_expression(source, it.by_ref(), span.end())?;
+                        exp.spans.element = SourceSpan::of(&span);
+                        exp.spans.open_tag = exp.spans.element;
 
                         if exp.omit_pre_ws {
                             Template::remove_previous_whitespace(&mut template_stack);
@@ -794,10 +1056,13 @@ impl Template {
                         let indent_before_write = trim_line_required && !exp.omit_pre_ws;
 
                         match rule {
-                            Rule::helper_block_start | Rule::raw_block_start => {
+                            Rule::helper_block_start
+                            | Rule::inverted_block_start
+                            | Rule::raw_block_start => {
                                 let helper_template =
                                     HelperTemplate::new(exp.clone(), true, indent_before_write);
                                 helper_stack.push_front(helper_template);
+                                inverted_stack.push_front(rule == Rule::inverted_block_start);
                             }
                             Rule::decorator_block_start | Rule::partial_block_start => {
                                 let decorator =
@@ -831,6 +1096,8 @@ impl Template {
                             let _ = Template::parse_name(source, &mut it, span.end())?;
                         }
                         let mut exp = Template::parse_expression(source, it.by_ref(), span.end())?;
+                        exp.spans.element = SourceSpan::of(&span);
+                        exp.spans.open_tag = exp.spans.element;
 
                         if invert_omit_pre_ws {
                             exp.omit_pre_ws = true;
@@ -855,6 +1122,7 @@ impl Template {
 
                         let t = template_stack.pop_front().unwrap();
                         let h = helper_stack.front_mut().unwrap();
+                        h.set_inverse_tag(exp.spans.open_tag);
 
                         if rule == Rule::invert_chain_tag {
                             h.set_chained();
@@ -881,6 +1149,7 @@ impl Template {
                             ),
                             line_no,
                             col_no,
+                            SourceSpan::of(&span),
                         );
                         template_stack.push_front(t);
                     }
@@ -892,7 +1161,9 @@ impl Template {
                     | Rule::raw_block_end
                     | Rule::decorator_block_end
                     | Rule::partial_block_end => {
-                        let exp = Template::parse_expression(source, it.by_ref(), span.end())?;
+                        let mut exp = Template::parse_expression(source, it.by_ref(), span.end())?;
+                        exp.spans.element = SourceSpan::of(&span);
+                        exp.spans.open_tag = exp.spans.element;
 
                         if exp.omit_pre_ws {
                             Template::remove_previous_whitespace(&mut template_stack);
@@ -909,7 +1180,7 @@ impl Template {
                                     HtmlExpression(Box::new(helper_template))
                                 };
                                 let t = template_stack.front_mut().unwrap();
-                                t.push_element(el, line_no, col_no);
+                                t.push_element(el, line_no, col_no, SourceSpan::of(&span));
                             }
                             Rule::decorator_expression | Rule::partial_expression => {
                                 // do not auto trim ident spaces for
@@ -947,7 +1218,7 @@ impl Template {
                                     PartialExpression(Box::new(decorator))
                                 };
                                 let t = template_stack.front_mut().unwrap();
-                                t.push_element(el, line_no, col_no);
+                                t.push_element(el, line_no, col_no, SourceSpan::of(&span));
                             }
                             Rule::helper_block_end | Rule::raw_block_end => {
                                 // standalone statement check, it also removes leading whitespaces of
@@ -961,12 +1232,20 @@ impl Template {
                                 );
 
                                 let mut h = helper_stack.pop_front().unwrap();
+                                let inverted = inverted_stack.pop_front().unwrap();
                                 let close_tag_name = exp.name.as_name();
                                 if h.name.as_name() == close_tag_name {
                                     let prev_t = template_stack.pop_front().unwrap();
+                                    h.close_spans(SourceSpan::of(&span));
                                     h.revert_chain_and_set(Some(prev_t));
+                                    if inverted {
+                                        // the content of `{{^name}}` is rendered when
+                                        // the helper doesn't render its block
+                                        std::mem::swap(&mut h.template, &mut h.inverse);
+                                    }
 
                                     let t = template_stack.front_mut().unwrap();
+                                    t.spans.push(h.spans.element);
                                     t.elements.push(HelperBlock(Box::new(h)));
                                 } else {
                                     return Err(TemplateError::of(
@@ -998,7 +1277,9 @@ impl Template {
                                 if empty_close_tag || d.name.as_name() == close_tag_name {
                                     let prev_t = template_stack.pop_front().unwrap();
                                     d.template = Some(prev_t);
+                                    d.spans.close(SourceSpan::of(&span));
                                     let t = template_stack.front_mut().unwrap();
+                                    t.spans.push(d.spans.element);
                                     if rule == Rule::decorator_block_end {
                                         t.elements.push(DecoratorBlock(Box::new(d)));
                                     } else {
@@ -1018,23 +1299,20 @@ impl Template {
                             _ => unreachable!(),
                         }
                     }
-                    Rule::hbs_comment_compact => {
-                        trim_line_required = Template::process_standalone_statement(
-                            &mut template_stack,
-                            source,
-                            &span,
-                            true,
-                            options.is_partial,
-                        );
+                    Rule::hbs_comment_compact | Rule::hbs_comment => {
+                        let mut omit_pre_ws = false;
+                        omit_pro_ws = false;
+                        while let Some(p) = it.next_if(|p| p.as_span().end() <= span.end()) {
+                            match p.as_rule() {
+                                Rule::leading_tilde_to_omit_whitespace => omit_pre_ws = true,
+                                Rule::trailing_tilde_to_omit_whitespace => omit_pro_ws = true,
+                                _ => {}
+                            }
+                        }
+                        if omit_pre_ws {
+                            Template::remove_previous_whitespace(&mut template_stack);
+                        }
 
-                        let text = span
-                            .as_str()
-                            .trim_start_matches("{{!")
-                            .trim_end_matches("}}");
-                        let t = template_stack.front_mut().unwrap();
-                        t.push_element(Comment(text.to_owned()), line_no, col_no);
-                    }
-                    Rule::hbs_comment => {
                         trim_line_required = Template::process_standalone_statement(
                             &mut template_stack,
                             source,
@@ -1043,17 +1321,31 @@ impl Template {
                             options.is_partial,
                         );
 
-                        let text = span
-                            .as_str()
-                            .trim_start_matches("{{!--")
-                            .trim_end_matches("--}}");
+                        let (open, close) = if rule == Rule::hbs_comment {
+                            ("!--", "--")
+                        } else {
+                            ("!", "")
+                        };
+                        let text = &span.as_str()[2..span.as_str().len() - 2];
+                        let text = if omit_pre_ws { &text[1..] } else { text };
+                        let text = if omit_pro_ws {
+                            &text[..text.len() - 1]
+                        } else {
+                            text
+                        };
+                        let text = &text[open.len()..text.len() - close.len()];
                         let t = template_stack.front_mut().unwrap();
-                        t.push_element(Comment(text.to_owned()), line_no, col_no);
+                        t.push_element(
+                            Comment(text.to_owned()),
+                            line_no,
+                            col_no,
+                            SourceSpan::of(&span),
+                        );
                     }
                     _ => {}
                 }
 
-                if rule != Rule::template {
+                if !is_template {
                     end_pos = Some(span.end_pos());
                 }
             } else {
@@ -1063,7 +1355,12 @@ impl Template {
                     // is some called in if check
                     let (line_no, col_no) = end_pos.unwrap().line_col();
                     let t = template_stack.front_mut().unwrap();
-                    t.push_element(RawString(text.to_owned()), line_no, col_no);
+                    t.push_element(
+                        RawString(text.to_owned()),
+                        line_no,
+                        col_no,
+                        SourceSpan::between(source, prev_end, source.len()),
+                    );
                 }
                 let mut root_template = template_stack.pop_front().unwrap();
                 root_template.name = options.name;
@@ -1072,6 +1369,43 @@ impl Template {
         }
     }
 
+    /// Compile a template, recovering from syntax errors
+    ///
+    /// Unlike [`Template::compile`], which stops at the first error, this
+    /// reports every unclosed block, mismatched or unexpected closing tag
+    /// and malformed tag of the source, each with its position and
+    /// [byte span](TemplateError::span). Faulty tags are left out of the
+    /// returned template, which keeps the valid parts of the source; a
+    /// source without errors compiles to the same template as with
+    /// `compile`.
+    ///
+    /// ```
+    /// use handlebars::Template;
+    ///
+    /// let source = "{{#if a}}{{foo bar=}}{{/each}}{{#with b}}";
+    /// let (_, errors) = Template::compile_recovering(source);
+    /// let spans: Vec<_> = errors.iter().filter_map(|e| e.span()).collect();
+    /// assert_eq!(spans, vec![9..21, 21..30, 30..41]);
+    /// ```
+    pub fn compile_recovering(source: &str) -> (Template, Vec<TemplateError>) {
+        recovery::compile(source, TemplateOptions::default())
+    }
+
+    /// Compile a named template, recovering from syntax errors, see
+    /// [`Template::compile_recovering`]
+    pub fn compile_recovering_with_name<S: AsRef<str>>(
+        source: S,
+        name: String,
+    ) -> (Template, Vec<TemplateError>) {
+        recovery::compile(
+            source.as_ref(),
+            TemplateOptions {
+                name: Some(name),
+                ..Default::default()
+            },
+        )
+    }
+
     // These two compile functions are kept for compatibility with 4.x
     // Template APIs in case that some developers are using them
     // without registry.
@@ -1096,6 +1430,7 @@ impl Template {
 
 #[non_exhaustive]
 #[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum TemplateElement {
     RawString(String),
     HtmlExpression(Box<HelperTemplate>),
@@ -1108,6 +1443,241 @@ pub enum TemplateElement {
     Comment(String),
 }
 
+/// Read-only traversal over a compiled `Template`
+///
+/// Every method has a default implementation that descends into the children
+/// of the node by calling the matching `walk_*` function, so implementors only
+/// override the nodes they are interested in. Call the `walk_*` function from
+/// an overridden method to keep descending.
+///
+/// ```
+/// use handlebars::template::{Template, TemplateVisitor, HelperTemplate, walk_helper};
+///
+/// #[derive(Default)]
+/// struct HelperNames(Vec<String>);
+///
+/// impl TemplateVisitor for HelperNames {
+///     fn visit_helper(&mut self, h: &HelperTemplate) {
+///         if h.block || !h.params.is_empty() {
+///             self.0.extend(h.name.as_name().map(ToOwned::to_owned));
+///         }
+///         walk_helper(self, h);
+///     }
+/// }
+///
+/// let t = Template::compile("{{#if a}}{{upper (lower b)}}{{/if}}").unwrap();
+/// let mut v = HelperNames::default();
+/// v.visit_template(&t);
+/// assert_eq!(v.0, vec!["if", "upper", "lower"]);
+/// ```
+pub trait TemplateVisitor {
+    fn visit_template(&mut self, t: &Template) {
+        walk_template(self, t);
+    }
+
+    fn visit_element(&mut self, e: &TemplateElement) {
+        walk_element(self, e);
+    }
+
+    /// Called for `Expression`, `HtmlExpression` and `HelperBlock` elements,
+    /// and for every node of an `else if` chain.
+    fn visit_helper(&mut self, h: &HelperTemplate) {
+        walk_helper(self, h);
+    }
+
+    /// Called for decorator and partial elements, both inline and block.
+    fn visit_decorator(&mut self, d: &DecoratorTemplate) {
+        walk_decorator(self, d);
+    }
+
+    fn visit_parameter(&mut self, p: &Parameter) {
+        walk_parameter(self, p);
+    }
+
+    fn visit_subexpression(&mut self, s: &Subexpression) {
+        walk_subexpression(self, s);
+    }
+
+    fn visit_path(&mut self, _: &Path) {}
+
+    fn visit_raw_string(&mut self, _: &str) {}
+
+    fn visit_comment(&mut self, _: &str) {}
+}
+
+pub fn walk_template<V: TemplateVisitor + ?Sized>(v: &mut V, t: &Template) {
+    for e in &t.elements {
+        v.visit_element(e);
+    }
+}
+
+pub fn walk_element<V: TemplateVisitor + ?Sized>(v: &mut V, e: &TemplateElement) {
+    match e {
+        RawString(s) => v.visit_raw_string(s),
+        Comment(s) => v.visit_comment(s),
+        Expression(ht) | HtmlExpression(ht) | HelperBlock(ht) => v.visit_helper(ht),
+        DecoratorExpression(dt) | DecoratorBlock(dt) | PartialExpression(dt) | PartialBlock(dt) => {
+            v.visit_decorator(dt)
+        }
+    }
+}
+
+/// Visit name, params, hash (in key order), then the block template and
+/// the inverse template.
+pub fn walk_helper<V: TemplateVisitor + ?Sized>(v: &mut V, h: &HelperTemplate) {
+    v.visit_parameter(&h.name);
+    walk_params(v, &h.params, &h.hash);
+    if let Some(ref t) = h.template {
+        v.visit_template(t);
+    }
+    if let Some(ref t) = h.inverse {
+        v.visit_template(t);
+    }
+}
+
+/// Visit name, params, hash (in key order), then the block template.
+pub fn walk_decorator<V: TemplateVisitor + ?Sized>(v: &mut V, d: &DecoratorTemplate) {
+    v.visit_parameter(&d.name);
+    walk_params(v, &d.params, &d.hash);
+    if let Some(ref t) = d.template {
+        v.visit_template(t);
+    }
+}
+
+pub fn walk_parameter<V: TemplateVisitor + ?Sized>(v: &mut V, p: &Parameter) {
+    match p {
+        Parameter::Path(path) => v.visit_path(path),
+        Parameter::Subexpression(s) => v.visit_subexpression(s),
+        Parameter::Name(_) | Parameter::Literal(_) => {}
+    }
+}
+
+pub fn walk_subexpression<V: TemplateVisitor + ?Sized>(v: &mut V, s: &Subexpression) {
+    if let Expression(ref ht) = *s.as_element() {
+        v.visit_helper(ht);
+    }
+}
+
+fn walk_params<V: TemplateVisitor + ?Sized>(
+    v: &mut V,
+    params: &[Parameter],
+    hash: &HashMap<String, Parameter>,
+) {
+    for p in params {
+        v.visit_parameter(p);
+    }
+    for (_, p) in sorted_hash(hash) {
+        v.visit_parameter(p);
+    }
+}
+
+/// Mutable counterpart of `TemplateVisitor`, for rewriting a `Template` in
+/// place. The traversal order is the same.
+pub trait TemplateVisitorMut {
+    fn visit_template_mut(&mut self, t: &mut Template) {
+        walk_template_mut(self, t);
+    }
+
+    fn visit_element_mut(&mut self, e: &mut TemplateElement) {
+        walk_element_mut(self, e);
+    }
+
+    fn visit_helper_mut(&mut self, h: &mut HelperTemplate) {
+        walk_helper_mut(self, h);
+    }
+
+    fn visit_decorator_mut(&mut self, d: &mut DecoratorTemplate) {
+        walk_decorator_mut(self, d);
+    }
+
+    fn visit_parameter_mut(&mut self, p: &mut Parameter) {
+        walk_parameter_mut(self, p);
+    }
+
+    fn visit_subexpression_mut(&mut self, s: &mut Subexpression) {
+        walk_subexpression_mut(self, s);
+    }
+
+    fn visit_path_mut(&mut self, _: &mut Path) {}
+
+    fn visit_raw_string_mut(&mut self, _: &mut String) {}
+
+    fn visit_comment_mut(&mut self, _: &mut String) {}
+}
+
+pub fn walk_template_mut<V: TemplateVisitorMut + ?Sized>(v: &mut V, t: &mut Template) {
+    for e in &mut t.elements {
+        v.visit_element_mut(e);
+    }
+}
+
+pub fn walk_element_mut<V: TemplateVisitorMut + ?Sized>(v: &mut V, e: &mut TemplateElement) {
+    match e {
+        RawString(s) => v.visit_raw_string_mut(s),
+        Comment(s) => v.visit_comment_mut(s),
+        Expression(ht) | HtmlExpression(ht) | HelperBlock(ht) => v.visit_helper_mut(ht),
+        DecoratorExpression(dt) | DecoratorBlock(dt) | PartialExpression(dt) | PartialBlock(dt) => {
+            v.visit_decorator_mut(dt)
+        }
+    }
+}
+
+pub fn walk_helper_mut<V: TemplateVisitorMut + ?Sized>(v: &mut V, h: &mut HelperTemplate) {
+    v.visit_parameter_mut(&mut h.name);
+    walk_params_mut(v, &mut h.params, &mut h.hash);
+    if let Some(ref mut t) = h.template {
+        v.visit_template_mut(t);
+    }
+    if let Some(ref mut t) = h.inverse {
+        v.visit_template_mut(t);
+    }
+}
+
+pub fn walk_decorator_mut<V: TemplateVisitorMut + ?Sized>(v: &mut V, d: &mut DecoratorTemplate) {
+    v.visit_parameter_mut(&mut d.name);
+    walk_params_mut(v, &mut d.params, &mut d.hash);
+    if let Some(ref mut t) = d.template {
+        v.visit_template_mut(t);
+    }
+}
+
+pub fn walk_parameter_mut<V: TemplateVisitorMut + ?Sized>(v: &mut V, p: &mut Parameter) {
+    match p {
+        Parameter::Path(path) => v.visit_path_mut(path),
+        Parameter::Subexpression(s) => v.visit_subexpression_mut(s),
+        Parameter::Name(_) | Parameter::Literal(_) => {}
+    }
+}
+
+pub fn walk_subexpression_mut<V: TemplateVisitorMut + ?Sized>(v: &mut V, s: &mut Subexpression) {
+    if let Expression(ref mut ht) = *s.element {
+        v.visit_helper_mut(ht);
+    }
+}
+
+fn walk_params_mut<V: TemplateVisitorMut + ?Sized>(
+    v: &mut V,
+    params: &mut [Parameter],
+    hash: &mut HashMap<String, Parameter>,
+) {
+    for p in params {
+        v.visit_parameter_mut(p);
+    }
+    let mut entries = hash.iter_mut().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    for (_, p) in entries {
+        v.visit_parameter_mut(p);
+    }
+}
+
+// HashMap iteration order is random, sort hash entries so traversals
+// are deterministic
+pub(crate) fn sorted_hash<V>(hash: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries = hash.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1306,6 +1876,15 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_eq_without_spans() {
+        let t1 = Template::compile("{{#if a}}{{b c=d}}{{/if}}").unwrap();
+        let t2 = Template::compile("{{#if a}}{{b  c=d}}{{/if}}").unwrap();
+        assert_ne!(t1.spans, t2.spans);
+        assert_eq!(t1, t2);
+        assert_ne!(t1, Template::compile("{{#if a}}{{b c=e}}{{/if}}").unwrap());
+    }
+
     #[test]
     fn test_white_space_omitter() {
         let source = "hello~     {{~world~}} \n  !{{~#if true}}else{{/if~}}";
@@ -1537,7 +2116,7 @@ mod test {
         let result = Template::compile(s);
         assert!(result.is_err());
         assert_eq!(
-            "decorator \"Subexpression(Subexpression { element: Expression(HelperTemplate { name: Path(Relative(([Named(\\\"X\\\")], \\\"X\\\"))), params: [], hash: {}, block_param: None, template: None, inverse: None, block: false, chain: false, indent_before_write: false }) })\" was opened, but \"X\" is closing",
+            "decorator \"(X)\" was opened, but \"X\" is closing",
             format!("{}", result.unwrap_err().reason())
         );
     }
@@ -1551,4 +2130,143 @@ mod test {
         let result = Template::compile(s);
         assert!(result.is_ok(), "failed to compile: {:?}", result.err());
     }
+
+    #[derive(Default)]
+    struct Collector {
+        helpers: Vec<String>,
+        paths: Vec<String>,
+        raw: Vec<String>,
+    }
+
+    impl TemplateVisitor for Collector {
+        fn visit_helper(&mut self, h: &HelperTemplate) {
+            self.helpers
+                .push(h.name.as_name().unwrap_or_default().to_owned());
+            walk_helper(self, h);
+        }
+
+        fn visit_path(&mut self, p: &Path) {
+            self.paths.push(p.raw().to_owned());
+        }
+
+        fn visit_raw_string(&mut self, s: &str) {
+            self.raw.push(s.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let t = Template::compile(
+            "{{#if a}}x{{else if (eq b c)}}{{foo d k=e}}{{else}}{{#> p f}}y{{/p}}{{/if}}",
+        )
+        .unwrap();
+        let mut v = Collector::default();
+        v.visit_template(&t);
+
+        assert_eq!(v.helpers, vec!["if", "if", "eq", "foo"]);
+        assert_eq!(v.paths, vec!["a", "b", "c", "d", "e", "f"]);
+        assert_eq!(v.raw, vec!["x", "y"]);
+    }
+
+    struct Rename;
+
+    impl TemplateVisitorMut for Rename {
+        fn visit_path_mut(&mut self, p: &mut Path) {
+            *p = Path::parse(&format!("data.{}", p.raw())).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        let mut t = Template::compile("{{#each items}}{{name}}{{/each}}").unwrap();
+        Rename.visit_template_mut(&mut t);
+
+        let mut v = Collector::default();
+        v.visit_template(&t);
+        assert_eq!(v.paths, vec!["data.items", "data.name"]);
+    }
+
+    #[test]
+    fn test_spans() {
+        let source =
+            "é {{foo (bar x) k=\"v\"}}\n{{#each l as |i|}}{{i}}{{else if c}}b{{else}}d{{/each}}";
+        let t = Template::compile(source).unwrap();
+        let text = |span: &SourceSpan| &source[span.range()];
+
+        let spans: Vec<_> = t.spans.iter().map(text).collect();
+        assert_eq!(
+            spans,
+            vec![
+                "é ",
+                "{{foo (bar x) k=\"v\"}}",
+                "\n",
+                "{{#each l as |i|}}{{i}}{{else if c}}b{{else}}d{{/each}}"
+            ]
+        );
+        assert_eq!(t.spans[1].start_pos, (1, 3));
+        assert_eq!(t.spans[3].end_pos, (2, 56));
+
+        let Expression(ref foo) = t.elements[1] else {
+            panic!("{:?}", t.elements[1]);
+        };
+        assert_eq!(text(&foo.spans.name), "foo");
+        assert_eq!(text(&foo.spans.params[0]), "(bar x)");
+        assert_eq!(text(&foo.spans.hash["k"]), "\"v\"");
+        let Parameter::Subexpression(ref bar) = foo.params[0] else {
+            panic!("{:?}", foo.params[0]);
+        };
+        let Expression(ref bar) = *bar.element else {
+            panic!("{bar:?}");
+        };
+        assert_eq!(text(&bar.spans.element), "(bar x)");
+        assert_eq!(text(&bar.spans.params[0]), "x");
+
+        let HelperBlock(ref each) = t.elements[3] else {
+            panic!("{:?}", t.elements[3]);
+        };
+        assert_eq!(text(&each.spans.open_tag), "{{#each l as |i|}}");
+        assert_eq!(text(each.spans.block_param.as_ref().unwrap()), "as |i|");
+        assert_eq!(
+            text(each.spans.inverse_tag.as_ref().unwrap()),
+            "{{else if c}}"
+        );
+        assert_eq!(text(each.spans.close_tag.as_ref().unwrap()), "{{/each}}");
+        assert_eq!(text(&each.template.as_ref().unwrap().spans[0]), "{{i}}");
+
+        let chain = each.inverse.as_ref().unwrap();
+        let HelperBlock(ref chained) = chain.elements[0] else {
+            panic!("{:?}", chain.elements[0]);
+        };
+        assert_eq!(text(&chain.spans[0]), "{{else if c}}b{{else}}d{{/each}}");
+        assert_eq!(
+            text(chained.spans.inverse_tag.as_ref().unwrap()),
+            "{{else}}"
+        );
+        assert_eq!(text(chained.spans.close_tag.as_ref().unwrap()), "{{/each}}");
+    }
+
+    #[test]
+    fn test_decorator_spans() {
+        let source = "{{#> layout title=t}}{{#*inline \"a\"}}b{{/inline}}{{/layout}}";
+        let t = Template::compile(source).unwrap();
+        let text = |span: &SourceSpan| &source[span.range()];
+
+        let PartialBlock(ref layout) = t.elements[0] else {
+            panic!("{:?}", t.elements[0]);
+        };
+        assert_eq!(text(&layout.spans.element), source);
+        assert_eq!(text(&layout.spans.name), "layout");
+        assert_eq!(text(&layout.spans.hash["title"]), "t");
+        assert_eq!(
+            text(layout.spans.close_tag.as_ref().unwrap()),
+            "{{/layout}}"
+        );
+
+        let inner = layout.template.as_ref().unwrap();
+        let DecoratorBlock(ref inline) = inner.elements[0] else {
+            panic!("{:?}", inner.elements[0]);
+        };
+        assert_eq!(text(&inline.spans.params[0]), "\"a\"");
+        assert_eq!(text(&inner.spans[0]), "{{#*inline \"a\"}}b{{/inline}}");
+    }
 }
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::template::{Template, sorted_hash};
+
+/// Version of the serialized form of `Template`
+///
+/// It is bumped whenever the structure of compiled templates changes, so a
+/// template serialized by another version of this crate can't be read back
+/// as a `VersionedTemplate`.
+pub const TEMPLATE_FORMAT_VERSION: u32 = 2;
+
+/// A compiled `Template` stamped with `TEMPLATE_FORMAT_VERSION` on
+/// serialization
+///
+/// `Template` itself implements `Serialize` and `Deserialize` with the `serde`
+/// feature. Use this wrapper to store templates outside of the process, like
+/// a cache on disk or a build artifact: deserializing fails when the stamp
+/// doesn't match the version of this crate, so stale caches are rejected
+/// instead of being misread.
+///
+/// ```
+/// use handlebars::{Handlebars, Template, VersionedTemplate};
+///
+/// let t = Template::compile("Hello {{name}}").unwrap();
+/// let cached = serde_json::to_string(&VersionedTemplate(t)).unwrap();
+///
+/// let VersionedTemplate(t) = serde_json::from_str(&cached).unwrap();
+/// let mut hbs = Handlebars::new();
+/// hbs.register_template("hello", t);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionedTemplate(pub Template);
+
+const FIELDS: &[&str] = &["format_version", "template"];
+
+impl Serialize for VersionedTemplate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("VersionedTemplate", 2)?;
+        s.serialize_field("format_version", &TEMPLATE_FORMAT_VERSION)?;
+        s.serialize_field("template", &self.0)?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionedTemplate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("VersionedTemplate", FIELDS, VersionedTemplateVisitor)
+    }
+}
+
+struct VersionedTemplateVisitor;
+
+fn check_version<E: de::Error>(version: u32) -> Result<(), E> {
+    if version == TEMPLATE_FORMAT_VERSION {
+        Ok(())
+    } else {
+        Err(E::custom(format_args!(
+            "template format version {version} is not supported, expected {TEMPLATE_FORMAT_VERSION}"
+        )))
+    }
+}
+
+// The version is checked before the template is read, so a template in
+// another format is never parsed.
+impl<'de> Visitor<'de> for VersionedTemplateVisitor {
+    type Value = VersionedTemplate;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a versioned handlebars template")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let version = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        check_version(version)?;
+        let template = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(VersionedTemplate(template))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        next_field(&mut map, FIELDS[0])?;
+        check_version(map.next_value()?)?;
+        next_field(&mut map, FIELDS[1])?;
+        Ok(VersionedTemplate(map.next_value()?))
+    }
+}
+
+fn next_field<'de, A: MapAccess<'de>>(map: &mut A, field: &'static str) -> Result<(), A::Error> {
+    match map.next_key::<String>()? {
+        Some(ref key) if key == field => Ok(()),
+        Some(key) => Err(de::Error::custom(format_args!(
+            "expected field `{field}`, found `{key}`"
+        ))),
+        None => Err(de::Error::missing_field(field)),
+    }
+}
+
+/// Hash arguments are written in key order, so a template is always
+/// serialized to the same output.
+pub(crate) fn serialize_hash<S: Serializer, V: Serialize>(
+    hash: &HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(sorted_hash(hash))
+}
+
+/// (De)serialize the pest rules kept in `PathSeg::Ruled`
+pub(crate) mod rule {
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::{self, Serializer};
+
+    use crate::grammar::Rule;
+
+    const VARIANTS: &[&str] = &["root", "up", "local"];
+
+    pub(crate) fn serialize<S: Serializer>(rule: &Rule, serializer: S) -> Result<S::Ok, S::Error> {
+        let name = match rule {
+            Rule::path_root => "root",
+            Rule::path_up => "up",
+            Rule::path_local => "local",
+            other => {
+                return Err(ser::Error::custom(format_args!(
+                    "unexpected path segment {other:?}"
+                )));
+            }
+        };
+        serializer.serialize_str(name)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Rule, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        match name.as_str() {
+            "root" => Ok(Rule::path_root),
+            "up" => Ok(Rule::path_up),
+            "local" => Ok(Rule::path_local),
+            other => Err(de::Error::unknown_variant(other, VARIANTS)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registry::Registry;
+
+    #[test]
+    fn test_round_trip() {
+        let source = "{{#each items as |item i|}}{{item.name}} {{@index}} {{../title}}\
+                      {{else if (gt @root.count 1) k=\"v\"}}{{{html}}}{{else}}none{{/each}}\
+                      {{#*inline \"p\"}}{{this}}{{/inline}}\n  {{> p a=[1, {\"b\": null}]}}\n\
+                      {{{{raw}}}}{{x}}{{{{/raw}}}}{{!-- c --}}";
+        let t = Template::compile(source).unwrap();
+
+        let json = serde_json::to_string(&VersionedTemplate(t.clone())).unwrap();
+        let VersionedTemplate(t2) = serde_json::from_str(&json).unwrap();
+        assert_eq!(t, t2);
+        // spans aren't compared by `PartialEq`
+        assert_eq!(
+            serde_json::to_string(&VersionedTemplate(t2.clone())).unwrap(),
+            json
+        );
+
+        let data = json!({"items": [{"name": "a"}], "title": "t", "count": 2});
+        let mut r = Registry::new();
+        r.register_template("t", t);
+        r.register_template("t2", t2);
+        assert_eq!(
+            r.render("t", &data).unwrap(),
+            r.render("t2", &data).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stable_output() {
+        let t = Template::compile("{{f a=1 b=2 c=3 d=4 e=5}}").unwrap();
+        let json = serde_json::to_string(&t).unwrap();
+        for _ in 0..10 {
+            let t = Template::compile("{{f a=1 b=2 c=3 d=4 e=5}}").unwrap();
+            assert_eq!(serde_json::to_string(&t).unwrap(), json);
+        }
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        let t = Template::compile("{{a}}").unwrap();
+        let mut json = serde_json::to_value(VersionedTemplate(t)).unwrap();
+        json["format_version"] = json!(TEMPLATE_FORMAT_VERSION + 1);
+        // an unknown structure is not parsed at all
+        json["template"] = json!({"unknown": true});
+
+        let e = serde_json::from_value::<VersionedTemplate>(json).unwrap_err();
+        assert!(e.to_string().contains("template format version"));
+
+        assert!(serde_json::from_str::<VersionedTemplate>(r#"{"template": {}}"#).is_err());
+    }
+}
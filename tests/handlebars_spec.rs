@@ -0,0 +1,180 @@
+//! Run the handlebars.js spec fixtures in `tests/fixtures/handlebars`
+//!
+//! Cases listed in `KNOWN_DIFFERENCES` are expected to fail, the test
+//! fails when they start to pass so the list, and the compatibility notes
+//! in the crate documentation, stay accurate.
+
+#[macro_use]
+extern crate handlebars;
+
+use std::fs;
+use std::path::Path;
+
+use handlebars::{Context, Handlebars, Helper, HelperResult, JsonRender, Output, RenderContext};
+use serde_json::Value as Json;
+
+const SPECS: &[&str] = &[
+    "basic",
+    "blocks",
+    "builtins",
+    "data",
+    "partials",
+    "subexpressions",
+    "whitespace-control",
+];
+
+/// (spec, case name, reason)
+const KNOWN_DIFFERENCES: &[(&str, &str, &str)] = &[
+    (
+        "basic",
+        "compiling with a string context",
+        "properties of JavaScript strings like `length` are not available",
+    ),
+    (
+        "basic",
+        "double quoted literal references",
+        "string literals are not accepted as paths",
+    ),
+    (
+        "basic",
+        "single quoted literal references",
+        "string literals are not accepted as paths",
+    ),
+    (
+        "basic",
+        "decimal literals as paths",
+        "number literals are not looked up as paths",
+    ),
+    (
+        "data",
+        "@root in partial",
+        "empty objects are falsy for `with`",
+    ),
+    (
+        "partials",
+        "partials with parameters",
+        "`..` is not accepted as parameter",
+    ),
+    (
+        "partials",
+        "partial block rendered with context",
+        "`@partial-block` is rendered in the context of the caller",
+    ),
+    (
+        "partials",
+        "partial block inside each",
+        "`@partial-block` is rendered in the context of the caller",
+    ),
+    (
+        "partials",
+        "inline partials in a block",
+        "empty objects are falsy for `with`",
+    ),
+    (
+        "partials",
+        "inline partials override template partials",
+        "empty objects are falsy for `with`",
+    ),
+    (
+        "partials",
+        "inline partials down the entire stack",
+        "empty objects are falsy for `with`",
+    ),
+];
+
+handlebars_helper!(foo: |v: str| format!("{v}{v}"));
+handlebars_helper!(bar: | | "LOL");
+handlebars_helper!(equal: |x: Json, y: Json| x == y);
+handlebars_helper!(dash: |a: str, b: str| format!("{a}-{b}"));
+handlebars_helper!(concat: |a: str, b: str| format!("{a}{b}"));
+
+fn blog(
+    h: &Helper<'_>,
+    _: &Handlebars<'_>,
+    _: &Context,
+    _: &mut RenderContext<'_, '_>,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let values: Vec<_> = match h.hash_get("fun") {
+        Some(fun) => vec![fun.value().render()],
+        None => h.params().iter().map(|p| p.value().render()).collect(),
+    };
+    let values = match values.as_slice() {
+        [init @ .., last] if !init.is_empty() => format!("{} and {last}", init.join(", ")),
+        _ => values.join(""),
+    };
+    out.write(&format!("val is {values}"))?;
+    Ok(())
+}
+
+fn registry(case: &Json) -> Result<Handlebars<'static>, String> {
+    let mut hbs = Handlebars::new();
+    // the default `blockHelperMissing` of handlebars.js
+    hbs.register_section_helper();
+    if case["options"]["preventIndent"].as_bool() == Some(true) {
+        hbs.set_prevent_indent(true);
+    }
+    for helper in case["helpers"].as_array().into_iter().flatten() {
+        match helper.as_str().unwrap() {
+            "foo" => hbs.register_helper("foo", Box::new(foo)),
+            "bar" => hbs.register_helper("bar", Box::new(bar)),
+            "blog" => hbs.register_helper("blog", Box::new(blog)),
+            "equal" => hbs.register_helper("equal", Box::new(equal)),
+            "dash" => hbs.register_helper("dash", Box::new(dash)),
+            "concat" => hbs.register_helper("concat", Box::new(concat)),
+            other => panic!("unknown helper {other}"),
+        }
+    }
+    if let Some(partials) = case["partials"].as_object() {
+        for (name, source) in partials {
+            hbs.register_partial(name, source.as_str().unwrap())
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(hbs)
+}
+
+fn run_case(case: &Json) -> Result<String, String> {
+    registry(case)?
+        .render_template(case["template"].as_str().unwrap(), &case["data"])
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_handlebars_spec() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/handlebars");
+    let mut unexpected = Vec::new();
+    let mut report = Vec::new();
+
+    for spec in SPECS {
+        let source = fs::read_to_string(dir.join(format!("{spec}.json"))).unwrap();
+        let fixture: Json = serde_json::from_str(&source).unwrap();
+        for case in fixture["tests"].as_array().unwrap() {
+            let name = case["name"].as_str().unwrap();
+            let known = KNOWN_DIFFERENCES
+                .iter()
+                .find(|(s, n, _)| s == spec && *n == name);
+            let actual = run_case(case);
+            let passed = match case["expected"].as_str() {
+                Some(expected) => actual.as_deref() == Ok(expected),
+                None => actual.is_err(),
+            };
+
+            match (passed, known) {
+                (true, Some(_)) => unexpected.push(format!("{spec} / {name}: passes now")),
+                (false, None) => unexpected.push(format!(
+                    "{spec} / {name}: {:?} != {actual:?}",
+                    case["expected"]
+                )),
+                (false, Some((_, _, reason))) => report.push(format!("{spec} / {name}: {reason}")),
+                (true, None) => {}
+            }
+        }
+    }
+
+    println!("handlebars.js spec cases differing:");
+    for line in &report {
+        println!("  {line}");
+    }
+    assert!(unexpected.is_empty(), "{}", unexpected.join("\n"));
+}
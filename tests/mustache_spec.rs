@@ -0,0 +1,123 @@
+//! Run the Mustache spec fixtures in `tests/fixtures/mustache` with the
+//! Mustache compatibility mode
+//!
+//! Cases listed in `KNOWN_DIFFERENCES` are expected to fail, the test
+//! fails when they start to pass so the list stays accurate.
+
+use std::fs;
+use std::path::Path;
+
+use handlebars::Handlebars;
+use serde_json::Value as Json;
+
+const SPECS: &[&str] = &[
+    "comments",
+    "delimiters",
+    "interpolation",
+    "inverted",
+    "partials",
+    "sections",
+];
+
+/// (spec, case name, reason)
+const KNOWN_DIFFERENCES: &[(&str, &str, &str)] = &[
+    (
+        "inverted",
+        "Dotted Names - Truthy",
+        "block names are identifiers, not paths",
+    ),
+    (
+        "inverted",
+        "Dotted Names - Falsey",
+        "block names are identifiers, not paths",
+    ),
+    (
+        "inverted",
+        "Dotted Names - Broken Chains",
+        "block names are identifiers, not paths",
+    ),
+    (
+        "partials",
+        "Inline Indentation",
+        "partials not standalone are indented too",
+    ),
+    (
+        "partials",
+        "Standalone Indentation",
+        "lines of interpolated values are indented too",
+    ),
+    (
+        "sections",
+        "Implicit Iterator - Array",
+        "`.` is not accepted as block name",
+    ),
+    (
+        "sections",
+        "Implicit Iterator - Root-level",
+        "`.` is not accepted as block name",
+    ),
+    (
+        "sections",
+        "Dotted Names - Truthy",
+        "block names are identifiers, not paths",
+    ),
+    (
+        "sections",
+        "Dotted Names - Falsey",
+        "block names are identifiers, not paths",
+    ),
+    (
+        "sections",
+        "Dotted Names - Broken Chains",
+        "block names are identifiers, not paths",
+    ),
+];
+
+fn run_case(case: &Json) -> Result<String, String> {
+    let mut hbs = Handlebars::new();
+    hbs.set_mustache_compat(true);
+    if let Some(partials) = case["partials"].as_object() {
+        for (name, source) in partials {
+            hbs.register_partial(name, source.as_str().unwrap())
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    hbs.render_template(case["template"].as_str().unwrap(), &case["data"])
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_mustache_spec() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mustache");
+    let mut unexpected = Vec::new();
+    let mut report = Vec::new();
+
+    for spec in SPECS {
+        let source = fs::read_to_string(dir.join(format!("{spec}.json"))).unwrap();
+        let fixture: Json = serde_json::from_str(&source).unwrap();
+        for case in fixture["tests"].as_array().unwrap() {
+            let name = case["name"].as_str().unwrap();
+            let known = KNOWN_DIFFERENCES
+                .iter()
+                .find(|(s, n, _)| s == spec && *n == name);
+            let expected = case["expected"].as_str().unwrap();
+            let actual = run_case(case);
+            let passed = actual.as_deref() == Ok(expected);
+
+            match (passed, known) {
+                (true, Some(_)) => unexpected.push(format!("{spec} / {name}: passes now")),
+                (false, None) => {
+                    unexpected.push(format!("{spec} / {name}: {expected:?} != {actual:?}"))
+                }
+                (false, Some((_, _, reason))) => report.push(format!("{spec} / {name}: {reason}")),
+                (true, None) => {}
+            }
+        }
+    }
+
+    println!("Mustache spec cases differing in compatibility mode:");
+    for line in &report {
+        println!("  {line}");
+    }
+    assert!(unexpected.is_empty(), "{}", unexpected.join("\n"));
+}